@@ -0,0 +1,469 @@
+//! Counter of *N*-grams in raw text corpora.
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use extsort::{ExternalSorter, Sortable};
+
+use crate::rank_array::RankArray;
+use crate::trie_array::TrieArray;
+use crate::trie_count_lm::{GramsSource, LevelsBuilder};
+use crate::vocabulary::Vocabulary;
+use crate::{CountRecord, GramsFileFormats, TrieCountLm, TOKEN_SEPARATOR};
+
+/// The default memory budget of [`CorpusCounter`] in bytes (i.e., 1 GiB).
+pub const DEFAULT_MEMORY_BUDGET: usize = 1 << 30;
+
+// Approximate number of bytes consumed by a counted gram, except its tokens.
+pub(crate) const GRAM_OVERHEAD: usize = 64;
+
+/// Counter of *N*-grams in raw text corpora,
+/// which builds [`TrieCountLm`] without writing intermediate *N*-gram files.
+///
+/// Each line of a corpus is tokenized by ASCII whitespaces,
+/// and *N*-grams are counted within the line.
+/// When the counts kept in memory exceed the memory budget,
+/// they are spilled to temporary files and merged when sorting.
+/// The vocabulary is sorted in decreasing order of unigram counts,
+/// and the grams of each order are sorted in the order of token ids as [`TrieCountLm`] requires.
+///
+/// # Examples
+///
+/// ```
+/// use tongrams::corpus::CorpusCounter;
+/// use tongrams::EliasFanoTrieCountLm;
+///
+/// let corpus = "A B C\nA B\nB C A\n";
+/// let lm: EliasFanoTrieCountLm = CorpusCounter::new(3)
+///     .build_from_readers(vec![corpus.as_bytes()])
+///     .unwrap();
+///
+/// let mut lookuper = lm.lookuper();
+/// assert_eq!(lookuper.with_str("B"), Some(3));
+/// assert_eq!(lookuper.with_str("A B"), Some(2));
+/// assert_eq!(lookuper.with_str("B C A"), Some(1));
+/// assert_eq!(lookuper.with_str("C B"), None);
+/// ```
+pub struct CorpusCounter {
+    max_order: usize,
+    memory_budget: usize,
+    tmp_dir: Option<PathBuf>,
+}
+
+impl CorpusCounter {
+    /// Creates [`CorpusCounter`] counting *N*-grams of *N* = 1, ..., `max_order`.
+    pub const fn new(max_order: usize) -> Self {
+        Self {
+            max_order,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            tmp_dir: None,
+        }
+    }
+
+    /// Sets the approximate memory budget in bytes for counting and sorting
+    /// (default: [`DEFAULT_MEMORY_BUDGET`]).
+    pub const fn memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    /// Sets the directory in which temporary files are placed
+    /// (default: the temporary directory of the system).
+    pub fn tmp_dir<P>(mut self, tmp_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.tmp_dir = Some(tmp_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Counts *N*-grams in corpus files and builds [`TrieCountLm`].
+    ///
+    /// # Arguments
+    ///
+    ///  - `filepaths`: Paths of corpus files.
+    ///  - `fmt`: File format.
+    pub fn build_from_files<P, T, V, A>(
+        &self,
+        filepaths: &[P],
+        fmt: GramsFileFormats,
+    ) -> Result<TrieCountLm<T, V, A>>
+    where
+        P: AsRef<Path>,
+        T: TrieArray,
+        V: Vocabulary,
+        A: RankArray,
+    {
+        let mut readers = Vec::with_capacity(filepaths.len());
+        for filepath in filepaths {
+            readers.push(fmt.open(filepath)?);
+        }
+        self.build_from_readers(readers)
+    }
+
+    /// Counts *N*-grams in corpora read from `readers` and builds [`TrieCountLm`].
+    pub fn build_from_readers<R, T, V, A>(&self, readers: Vec<R>) -> Result<TrieCountLm<T, V, A>>
+    where
+        R: Read,
+        T: TrieArray,
+        V: Vocabulary,
+        A: RankArray,
+    {
+        if self.max_order == 0 || usize::from(u8::MAX) < self.max_order {
+            return Err(anyhow!(
+                "max_order must be in 1..={}, but got {}.",
+                u8::MAX,
+                self.max_order
+            ));
+        }
+
+        let mut counter = GramCounter::new(readers, self.max_order, self.memory_budget);
+        let counted = self.sorter().sort(&mut counter)?;
+        if let Some(e) = counter.error.take() {
+            return Err(e.into());
+        }
+        let mut counted = counted.peekable();
+
+        // The vocabulary is sorted in decreasing order of counts.
+        let mut unigrams: Vec<_> = merge_counts(&mut counted, 1).collect();
+        unigrams.sort_by(|(g1, c1), (g2, c2)| c2.cmp(c1).then_with(|| g1.cmp(g2)));
+        if unigrams.is_empty() {
+            return Err(anyhow!("The corpus has no tokens."));
+        }
+
+        let token_ids: HashMap<Vec<u8>, usize> = unigrams
+            .iter()
+            .enumerate()
+            .map(|(id, (token, _))| (token.clone(), id))
+            .collect();
+        let mut tokens = Vec::with_capacity(unigrams.len());
+        let mut records = Vec::with_capacity(unigrams.len());
+        for (token, count) in unigrams {
+            let token = String::from_utf8(token)?;
+            tokens.push(token.clone());
+            records.push(CountRecord::new(token, count));
+        }
+        let tokens = Arc::new(tokens);
+
+        let mut sources = vec![GramsSource::records(records)];
+        for order in 2..=self.max_order {
+            let mut num_grams = 0;
+            let mapped = merge_counts(&mut counted, order).map(|(gram, count)| {
+                num_grams += 1;
+                let token_ids = gram
+                    .split(|&b| b == TOKEN_SEPARATOR)
+                    .map(|token| token_ids[token])
+                    .collect();
+                MappedGram { token_ids, count }
+            });
+            let sorted = self.sorter().sort(mapped)?;
+            if num_grams == 0 {
+                // The lines of the corpus are shorter than the order.
+                break;
+            }
+            let tokens = tokens.clone();
+            sources.push(GramsSource::records(
+                sorted.map(move |mapped| mapped.into_record(&tokens)),
+            ));
+        }
+
+        LevelsBuilder::new().build(sources)
+    }
+
+    fn sorter(&self) -> ExternalSorter {
+        sorter(self.memory_budget, &self.tmp_dir)
+    }
+}
+
+/// Creates the external sorter keeping grams in memory up to `memory_budget` bytes.
+pub(crate) fn sorter(memory_budget: usize, tmp_dir: &Option<PathBuf>) -> ExternalSorter {
+    let sorter =
+        ExternalSorter::new().with_segment_size(std::cmp::max(1, memory_budget / GRAM_OVERHEAD));
+    match tmp_dir {
+        Some(tmp_dir) => sorter.with_sort_dir(tmp_dir.clone()),
+        None => sorter,
+    }
+}
+
+/// Merges the adjacent counts of the same grams of `order` in the sorted stream.
+pub(crate) fn merge_counts<I>(
+    counted: &mut Peekable<I>,
+    order: usize,
+) -> impl Iterator<Item = (Vec<u8>, usize)> + '_
+where
+    I: Iterator<Item = CountedGram>,
+{
+    std::iter::from_fn(move || {
+        let first = counted.next_if(|g| g.order == order)?;
+        let mut count = first.count;
+        while let Some(g) = counted.next_if(|g| g.order == order && g.gram == first.gram) {
+            count += g.count;
+        }
+        Some((first.gram, count))
+    })
+}
+
+/// Partial count of a gram, which is counted in memory until the budget is exceeded.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct CountedGram {
+    pub(crate) order: usize,
+    pub(crate) gram: Vec<u8>,
+    pub(crate) count: usize,
+}
+
+impl CountedGram {
+    pub(crate) fn write_into<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_u8(self.order as u8)?;
+        writer.write_u64::<LittleEndian>(self.gram.len() as u64)?;
+        writer.write_all(&self.gram)?;
+        writer.write_u64::<LittleEndian>(self.count as u64)
+    }
+
+    pub(crate) fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let order = reader.read_u8()? as usize;
+        let len = reader.read_u64::<LittleEndian>()? as usize;
+        let mut gram = vec![0; len];
+        reader.read_exact(&mut gram)?;
+        let count = reader.read_u64::<LittleEndian>()? as usize;
+        Ok(Self { order, gram, count })
+    }
+}
+
+impl Sortable for CountedGram {
+    fn encode<W: Write>(&self, writer: &mut W) {
+        self.write_into(writer).unwrap();
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Option<Self> {
+        Self::read_from(reader).ok()
+    }
+}
+
+/// Gram mapped to token ids, which is sorted in the order of the ids.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct MappedGram {
+    pub(crate) token_ids: Vec<usize>,
+    pub(crate) count: usize,
+}
+
+impl MappedGram {
+    pub(crate) fn into_record(self, tokens: &[String]) -> CountRecord {
+        let gram: Vec<&str> = self
+            .token_ids
+            .iter()
+            .map(|&id| tokens[id].as_str())
+            .collect();
+        CountRecord::new(
+            gram.join(&(TOKEN_SEPARATOR as char).to_string()),
+            self.count,
+        )
+    }
+}
+
+impl Sortable for MappedGram {
+    fn encode<W: Write>(&self, writer: &mut W) {
+        writer.write_u8(self.token_ids.len() as u8).unwrap();
+        for &x in &self.token_ids {
+            writer.write_u64::<LittleEndian>(x as u64).unwrap();
+        }
+        writer.write_u64::<LittleEndian>(self.count as u64).unwrap();
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Option<Self> {
+        let len = reader.read_u8().ok()? as usize;
+        let mut token_ids = Vec::with_capacity(len);
+        for _ in 0..len {
+            token_ids.push(reader.read_u64::<LittleEndian>().ok()? as usize);
+        }
+        let count = reader.read_u64::<LittleEndian>().ok()? as usize;
+        Some(Self { token_ids, count })
+    }
+}
+
+/// Iterator emitting partial counts of grams in corpora.
+///
+/// Grams are counted in hash maps and emitted when the memory budget is exceeded
+/// or all the corpora are read.
+struct GramCounter<R> {
+    readers: std::vec::IntoIter<R>,
+    reader: Option<BufReader<R>>,
+    memory_budget: usize,
+    memory: usize,
+    counts: Vec<HashMap<Vec<u8>, usize>>,
+    emitted: std::vec::IntoIter<CountedGram>,
+    line: Vec<u8>,
+    error: Option<std::io::Error>,
+}
+
+impl<R> GramCounter<R>
+where
+    R: Read,
+{
+    fn new(readers: Vec<R>, max_order: usize, memory_budget: usize) -> Self {
+        Self {
+            readers: readers.into_iter(),
+            reader: None,
+            memory_budget,
+            memory: 0,
+            counts: vec![HashMap::new(); max_order],
+            emitted: Vec::new().into_iter(),
+            line: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Reads and counts the next line, returning `false` if no line is left.
+    fn count_next_line(&mut self) -> bool {
+        loop {
+            if self.reader.is_none() {
+                match self.readers.next() {
+                    Some(reader) => self.reader = Some(BufReader::new(reader)),
+                    None => return false,
+                }
+            }
+            self.line.clear();
+            match self
+                .reader
+                .as_mut()
+                .unwrap()
+                .read_until(b'\n', &mut self.line)
+            {
+                Ok(0) => self.reader = None,
+                Ok(_) => break,
+                Err(e) => {
+                    self.error = Some(e);
+                    return false;
+                }
+            }
+        }
+
+        let tokens: Vec<&[u8]> = self
+            .line
+            .split(|b| b.is_ascii_whitespace())
+            .filter(|token| !token.is_empty())
+            .collect();
+        let mut gram = Vec::new();
+        for i in 0..tokens.len() {
+            gram.clear();
+            for (counts, token) in self.counts.iter_mut().zip(&tokens[i..]) {
+                if !gram.is_empty() {
+                    gram.push(TOKEN_SEPARATOR);
+                }
+                gram.extend_from_slice(token);
+                if let Some(count) = counts.get_mut(&gram[..]) {
+                    *count += 1;
+                } else {
+                    self.memory += gram.len() + GRAM_OVERHEAD;
+                    counts.insert(gram.clone(), 1);
+                }
+            }
+        }
+        true
+    }
+
+    fn emit_counts(&mut self) {
+        let mut emitted = Vec::new();
+        for (i, counts) in self.counts.iter_mut().enumerate() {
+            emitted.extend(counts.drain().map(|(gram, count)| CountedGram {
+                order: i + 1,
+                gram,
+                count,
+            }));
+        }
+        self.emitted = emitted.into_iter();
+        self.memory = 0;
+    }
+}
+
+impl<R> Iterator for GramCounter<R>
+where
+    R: Read,
+{
+    type Item = CountedGram;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(g) = self.emitted.next() {
+                return Some(g);
+            }
+            if self.memory > self.memory_budget || !self.count_next_line() {
+                if self.memory == 0 {
+                    return None;
+                }
+                self.emit_counts();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::SimpleTrieCountLm;
+
+    const CORPUS: &str = "the cat sat on the mat
+the cat ran
+
+on the mat the cat sat
+";
+
+    fn check_counts(lm: &SimpleTrieCountLm) {
+        assert_eq!(lm.num_orders(), 3);
+        let mut lookuper = lm.lookuper();
+        assert_eq!(lookuper.with_str("the"), Some(5));
+        assert_eq!(lookuper.with_str("cat"), Some(3));
+        assert_eq!(lookuper.with_str("ran"), Some(1));
+        assert_eq!(lookuper.with_str("the cat"), Some(3));
+        assert_eq!(lookuper.with_str("on the"), Some(2));
+        assert_eq!(lookuper.with_str("the cat sat"), Some(2));
+        assert_eq!(lookuper.with_str("mat the cat"), Some(1));
+        assert_eq!(lookuper.with_str("cat ran the"), None);
+        assert_eq!(lookuper.with_str("sat on the mat"), None);
+    }
+
+    #[test]
+    fn test_basic() {
+        let lm: SimpleTrieCountLm = CorpusCounter::new(3)
+            .build_from_readers(vec![CORPUS.as_bytes()])
+            .unwrap();
+        check_counts(&lm);
+    }
+
+    #[test]
+    fn test_spill() {
+        let lm: SimpleTrieCountLm = CorpusCounter::new(3)
+            .memory_budget(1)
+            .build_from_readers(vec![CORPUS.as_bytes()])
+            .unwrap();
+        check_counts(&lm);
+    }
+
+    #[test]
+    fn test_multiple_readers() {
+        let (first, second) = CORPUS.split_at(CORPUS.find("\n\n").unwrap());
+        let lm: SimpleTrieCountLm = CorpusCounter::new(3)
+            .build_from_readers(vec![first.as_bytes(), second.as_bytes()])
+            .unwrap();
+        check_counts(&lm);
+    }
+
+    #[test]
+    fn test_short_lines() {
+        let lm: SimpleTrieCountLm = CorpusCounter::new(7)
+            .build_from_readers(vec![CORPUS.as_bytes()])
+            .unwrap();
+        assert_eq!(lm.num_orders(), 6);
+    }
+
+    #[test]
+    fn test_empty() {
+        let result: Result<SimpleTrieCountLm> =
+            CorpusCounter::new(3).build_from_readers(vec![&b""[..]]);
+        assert!(result.is_err());
+    }
+}
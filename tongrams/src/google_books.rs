@@ -0,0 +1,685 @@
+//! Ingestion of the [Google Books Ngram](https://storage.googleapis.com/books/ngrams/books/datasetsv3.html) datasets.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use extsort::ExternalSorter;
+use tempfile::NamedTempFile;
+
+use crate::corpus::{
+    self, merge_counts, CountedGram, MappedGram, DEFAULT_MEMORY_BUDGET, GRAM_OVERHEAD,
+};
+use crate::rank_array::RankArray;
+use crate::trie_array::TrieArray;
+use crate::trie_count_lm::{GramsSource, LevelsBuilder};
+use crate::validation::{ValidationError, ValidationErrorKind};
+use crate::vocabulary::Vocabulary;
+use crate::{util, CountRecord, GramsFileFormats, GramsWriter, TrieCountLm, TOKEN_SEPARATOR};
+
+/// Part-of-speech tags attached to tokens, such as `cat_NOUN`.
+const POS_TAGS: [&str; 12] = [
+    "NOUN", "VERB", "ADJ", "ADV", "PRON", "DET", "ADP", "NUM", "CONJ", "PRT", "X", ".",
+];
+
+/// Handling of part-of-speech tagged tokens in [`GoogleBooksCounter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PosTags {
+    /// Keeps tagged tokens as they are, e.g., `cat_NOUN`.
+    Keep,
+    /// Strips the tags from tokens, e.g., `cat_NOUN` into `cat`.
+    ///
+    /// Since the untagged rows in the datasets already include the counts of the tagged ones,
+    /// the stripped grams are counted only if they have no untagged rows,
+    /// summing the counts of the grams that become identical.
+    Strip,
+    /// Skips the rows including tagged tokens,
+    /// keeping the untagged rows that include the counts of the tagged ones.
+    Skip,
+}
+
+impl FromStr for PosTags {
+    type Err = &'static str;
+
+    fn from_str(pos_tags: &str) -> Result<Self, Self::Err> {
+        match pos_tags {
+            "keep" => Ok(Self::Keep),
+            "strip" => Ok(Self::Strip),
+            "skip" => Ok(Self::Skip),
+            _ => Err("Invalid handling of tags"),
+        }
+    }
+}
+
+/// Counter of *N*-grams in the Google Books Ngram datasets,
+/// which aggregates the counts of each gram over a range of years.
+///
+/// Both the rows of version 2 (i.e., `gram TAB year TAB match_count TAB volume_count`)
+/// and version 3 (i.e., `gram TAB year,match_count,volume_count TAB ...`) are accepted,
+/// and the files of different orders can be given in any order.
+/// Standalone tags such as `_NOUN_` are dropped unless [`PosTags::Keep`] is specified.
+/// The counts of each gram are aggregated in memory, and when they exceed the memory budget,
+/// they are spilled to a temporary file and merged when sorting.
+///
+/// The grams should be prefix-closed over the inputs as in *N*-gram counts files,
+/// e.g., the unigram files are needed to build an index of bigrams.
+///
+/// # Examples
+///
+/// ```
+/// use tongrams::google_books::{GoogleBooksCounter, PosTags};
+/// use tongrams::EliasFanoTrieCountLm;
+///
+/// let unigrams = "A_NOUN\t1999\t3\t1\nA_NOUN\t2000\t2\t1\nB_VERB\t2000\t4\t2\n";
+/// let bigrams = "A_NOUN B_VERB\t1999,1,1\t2000,2,1\n";
+///
+/// let mut counter = GoogleBooksCounter::new().years(2000..=2020).pos_tags(PosTags::Strip);
+/// counter.add_reader(unigrams.as_bytes(), "unigrams").unwrap();
+/// counter.add_reader(bigrams.as_bytes(), "bigrams").unwrap();
+/// let lm: EliasFanoTrieCountLm = counter.build().unwrap();
+///
+/// let mut lookuper = lm.lookuper();
+/// assert_eq!(lookuper.with_str("A"), Some(2));
+/// assert_eq!(lookuper.with_str("A B"), Some(2));
+/// ```
+pub struct GoogleBooksCounter {
+    years: RangeInclusive<u32>,
+    pos_tags: PosTags,
+    memory_budget: usize,
+    tmp_dir: Option<PathBuf>,
+    // Counts of the grams as they are in the rows
+    counts: PartialCounts,
+    // Counts of the grams whose tags are stripped, used only for the grams absent from `counts`
+    stripped: PartialCounts,
+    // Approximate number of bytes consumed by `counts` and `stripped`
+    memory: usize,
+}
+
+impl Default for GoogleBooksCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GoogleBooksCounter {
+    /// Creates an empty [`GoogleBooksCounter`].
+    pub const fn new() -> Self {
+        Self {
+            years: 0..=u32::MAX,
+            pos_tags: PosTags::Keep,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            tmp_dir: None,
+            counts: PartialCounts::new(),
+            stripped: PartialCounts::new(),
+            memory: 0,
+        }
+    }
+
+    /// Sets the range of years in which the counts are aggregated (default: all the years).
+    pub const fn years(mut self, years: RangeInclusive<u32>) -> Self {
+        self.years = years;
+        self
+    }
+
+    /// Sets the handling of part-of-speech tagged tokens (default: [`PosTags::Keep`]).
+    pub const fn pos_tags(mut self, pos_tags: PosTags) -> Self {
+        self.pos_tags = pos_tags;
+        self
+    }
+
+    /// Sets the approximate memory budget in bytes for aggregating and sorting
+    /// (default: [`DEFAULT_MEMORY_BUDGET`]).
+    pub const fn memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    /// Sets the directory in which temporary files are placed
+    /// (default: the temporary directory of the system).
+    pub fn tmp_dir<P>(mut self, tmp_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.tmp_dir = Some(tmp_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Aggregates the counts in a file, whose format is detected from the magic bytes.
+    pub fn add_file<P>(&mut self, filepath: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let filepath = filepath.as_ref();
+        let reader = GramsFileFormats::detect(filepath)?.open(filepath)?;
+        self.add_reader(reader, &filepath.display().to_string())
+    }
+
+    /// Aggregates the counts read from `reader`, where `name` is used to report errors.
+    pub fn add_reader<R>(&mut self, reader: R, name: &str) -> Result<()>
+    where
+        R: Read,
+    {
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let malformed =
+                || ValidationError::new(name, i + 1, line.as_str(), ValidationErrorKind::Malformed);
+            let (gram, count) = self.parse_row(&line).ok_or_else(malformed)?;
+            if count == 0 {
+                continue;
+            }
+            let normalized = match self.normalize_gram(gram).ok_or_else(malformed)? {
+                Some(normalized) => normalized,
+                None => continue,
+            };
+            let order = normalized.split(TOKEN_SEPARATOR as char).count();
+            if usize::from(u8::MAX) < order {
+                return Err(malformed().into());
+            }
+            // Stripping tags is the only normalization that changes the kept grams.
+            let counts = if normalized.len() == gram.len() {
+                &mut self.counts
+            } else {
+                &mut self.stripped
+            };
+            self.memory += counts.add(order, normalized, count);
+            if self.memory > self.memory_budget {
+                self.counts.spill(&self.tmp_dir)?;
+                self.stripped.spill(&self.tmp_dir)?;
+                self.memory = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a row of version 2 or 3 into the gram and the count summed over the years.
+    fn parse_row<'a>(&self, line: &'a str) -> Option<(&'a str, usize)> {
+        let mut fields = line.split('\t');
+        let gram = fields.next()?;
+        let fields: Vec<&str> = fields.collect();
+        if fields.is_empty() {
+            return None;
+        }
+        let mut count = 0;
+        if fields[0].contains(',') {
+            for field in fields {
+                let mut values = field.split(',');
+                let year: u32 = values.next()?.parse().ok()?;
+                let match_count: usize = values.next()?.parse().ok()?;
+                values.next()?;
+                if self.years.contains(&year) {
+                    count += match_count;
+                }
+            }
+        } else {
+            if fields.len() != 3 {
+                return None;
+            }
+            let year: u32 = fields[0].parse().ok()?;
+            let match_count: usize = fields[1].parse().ok()?;
+            if self.years.contains(&year) {
+                count += match_count;
+            }
+        }
+        Some((gram, count))
+    }
+
+    /// Normalizes the tags in a gram, returning `Some(None)` if the gram is skipped
+    /// or `None` if the gram has empty tokens.
+    fn normalize_gram(&self, gram: &str) -> Option<Option<String>> {
+        let mut tokens = Vec::new();
+        for token in gram.split(TOKEN_SEPARATOR as char) {
+            if token.is_empty() {
+                return None;
+            }
+            if self.pos_tags == PosTags::Keep {
+                tokens.push(token);
+                continue;
+            }
+            if is_standalone_tag(token) {
+                return Some(None);
+            }
+            match strip_tag(token) {
+                Some(_) if self.pos_tags == PosTags::Skip => return Some(None),
+                Some(stripped) => tokens.push(stripped),
+                None => tokens.push(token),
+            }
+        }
+        Some(Some(tokens.join(&(TOKEN_SEPARATOR as char).to_string())))
+    }
+
+    /// Gets the records of each order sorted as *N*-gram counts files,
+    /// where the unigrams are sorted in decreasing order of counts.
+    ///
+    /// The records of higher orders are sorted in external memory under the memory budget.
+    pub fn sorted_records(&self) -> Result<Vec<SortedRecords>> {
+        let mut counted = self.counts.sort(self.sorter())?.peekable();
+        let mut stripped = self.stripped.sort(self.sorter())?.peekable();
+        let max_order = std::cmp::max(self.counts.max_order(), self.stripped.max_order());
+
+        let mut unigrams: Vec<_> = join_stripped(
+            merge_counts(&mut counted, 1),
+            merge_counts(&mut stripped, 1),
+        )
+        .collect();
+        if unigrams.is_empty() {
+            return Err(anyhow!("No unigrams are given."));
+        }
+        unigrams.sort_by(|(t1, c1), (t2, c2)| c2.cmp(c1).then_with(|| t1.cmp(t2)));
+        let token_ids: HashMap<Vec<u8>, usize> = unigrams
+            .iter()
+            .enumerate()
+            .map(|(id, (token, _))| (token.clone(), id))
+            .collect();
+        let mut tokens = Vec::with_capacity(unigrams.len());
+        let mut records = Vec::with_capacity(unigrams.len());
+        for (token, count) in unigrams {
+            let token = String::from_utf8(token)?;
+            tokens.push(token.clone());
+            records.push(CountRecord::new(token, count));
+        }
+        let tokens = Arc::new(tokens);
+
+        let mut levels = Vec::with_capacity(max_order);
+        levels.push(SortedRecords::new(records.len(), records));
+        for order in 2..=max_order {
+            let mut num_records = 0;
+            let mut missing = None;
+            let merged = join_stripped(
+                merge_counts(&mut counted, order),
+                merge_counts(&mut stripped, order),
+            );
+            let mapped = merged.filter_map(|(gram, count)| {
+                let mut ids = Vec::with_capacity(order);
+                for token in gram.split(|&b| b == TOKEN_SEPARATOR) {
+                    if let Some(&id) = token_ids.get(token) {
+                        ids.push(id);
+                    } else {
+                        missing.get_or_insert_with(|| {
+                            anyhow!(
+                                "Token {:?} of gram {:?} absent from the unigrams.",
+                                String::from_utf8_lossy(token),
+                                String::from_utf8_lossy(&gram)
+                            )
+                        });
+                        return None;
+                    }
+                }
+                num_records += 1;
+                Some(MappedGram {
+                    token_ids: ids,
+                    count,
+                })
+            });
+            let sorted = self.sorter().sort(mapped)?;
+            if let Some(e) = missing {
+                return Err(e);
+            }
+            if num_records == 0 {
+                return Err(anyhow!("No {}-grams are given.", order));
+            }
+            let tokens = tokens.clone();
+            levels.push(SortedRecords::new(
+                num_records,
+                sorted.map(move |mapped| mapped.into_record(&tokens)),
+            ));
+        }
+        Ok(levels)
+    }
+
+    /// Builds [`TrieCountLm`] from the aggregated counts.
+    pub fn build<T, V, A>(&self) -> Result<TrieCountLm<T, V, A>>
+    where
+        T: TrieArray,
+        V: Vocabulary,
+        A: RankArray,
+    {
+        let sources = self
+            .sorted_records()?
+            .into_iter()
+            .map(GramsSource::records)
+            .collect();
+        LevelsBuilder::new().build(sources)
+    }
+
+    /// Writes the aggregated counts into *N*-gram counts files named `<order>-grams.sorted`
+    /// (with the extension of `fmt`) in `dirpath`, returning the paths of the files.
+    pub fn write_files<P>(&self, dirpath: P, fmt: GramsFileFormats) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        let levels = self.sorted_records()?;
+        let mut filepaths = Vec::with_capacity(levels.len());
+        for (i, records) in levels.into_iter().enumerate() {
+            let mut filename = format!("{}-grams.sorted", i + 1);
+            if let Some(ext) = util::get_format_extension(fmt) {
+                filename = format!("{}.{}", filename, ext);
+            }
+            let filepath = dirpath.as_ref().join(filename);
+            let writer = fmt.encoder(BufWriter::new(File::create(&filepath)?))?;
+            let mut writer = GramsWriter::new(writer, records.num_records())?;
+            for rec in records {
+                writer.write_count_record(rec.gram(), rec.count())?;
+            }
+            writer.finish()?;
+            filepaths.push(filepath);
+        }
+        Ok(filepaths)
+    }
+
+    fn sorter(&self) -> ExternalSorter {
+        corpus::sorter(self.memory_budget, &self.tmp_dir)
+    }
+}
+
+/// Counts of grams aggregated in memory until the budget is exceeded,
+/// which are then appended to a temporary file.
+struct PartialCounts {
+    // `counts[i]` maps the grams of N = i + 1 to the counts aggregated since the last spill
+    counts: Vec<HashMap<String, usize>>,
+    spilled: Option<NamedTempFile>,
+    num_spilled: usize,
+}
+
+impl PartialCounts {
+    const fn new() -> Self {
+        Self {
+            counts: vec![],
+            spilled: None,
+            num_spilled: 0,
+        }
+    }
+
+    fn max_order(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Adds the count of a gram, returning the approximate number of bytes newly consumed.
+    fn add(&mut self, order: usize, gram: String, count: usize) -> usize {
+        if self.counts.len() < order {
+            self.counts.resize_with(order, HashMap::new);
+        }
+        let counts = &mut self.counts[order - 1];
+        if let Some(c) = counts.get_mut(&gram) {
+            *c += count;
+            0
+        } else {
+            let memory = gram.len() + GRAM_OVERHEAD;
+            counts.insert(gram, count);
+            memory
+        }
+    }
+
+    /// Appends the counts kept in memory to the temporary file.
+    fn spill(&mut self, tmp_dir: &Option<PathBuf>) -> Result<()> {
+        if self.counts.iter().all(HashMap::is_empty) {
+            return Ok(());
+        }
+        if self.spilled.is_none() {
+            self.spilled = Some(match tmp_dir {
+                Some(tmp_dir) => NamedTempFile::new_in(tmp_dir)?,
+                None => NamedTempFile::new()?,
+            });
+        }
+        let mut writer = BufWriter::new(self.spilled.as_mut().unwrap().as_file_mut());
+        for (i, counts) in self.counts.iter_mut().enumerate() {
+            for (gram, count) in counts.drain() {
+                let gram = CountedGram {
+                    order: i + 1,
+                    gram: gram.into_bytes(),
+                    count,
+                };
+                gram.write_into(&mut writer)?;
+                self.num_spilled += 1;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Sorts the spilled and in-memory counts in the order of [`CountedGram`].
+    fn sort(&self, sorter: ExternalSorter) -> Result<impl Iterator<Item = CountedGram>> {
+        let mut error = None;
+        let spilled = match &self.spilled {
+            Some(spilled) => Some(BufReader::new(spilled.reopen()?)),
+            None => None,
+        };
+        let spilled = spilled
+            .into_iter()
+            .flat_map(|mut reader| {
+                (0..self.num_spilled).map(move |_| CountedGram::read_from(&mut reader))
+            })
+            .map_while(|gram| gram.map_err(|e| error = Some(e)).ok());
+        let in_memory = self.counts.iter().enumerate().flat_map(|(i, counts)| {
+            counts.iter().map(move |(gram, &count)| CountedGram {
+                order: i + 1,
+                gram: gram.as_bytes().to_vec(),
+                count,
+            })
+        });
+        let counted = sorter.sort(spilled.chain(in_memory))?;
+        if let Some(e) = error {
+            return Err(e.into());
+        }
+        Ok(counted)
+    }
+}
+
+/// Joins the sorted counts of grams as they are with those of stripped grams,
+/// where the counts of a stripped gram are used only if the gram has no untagged rows.
+fn join_stripped<I, J>(counted: I, stripped: J) -> impl Iterator<Item = (Vec<u8>, usize)>
+where
+    I: Iterator<Item = (Vec<u8>, usize)>,
+    J: Iterator<Item = (Vec<u8>, usize)>,
+{
+    let mut counted = counted.peekable();
+    let mut stripped = stripped.peekable();
+    std::iter::from_fn(move || {
+        let ord = match (counted.peek(), stripped.peek()) {
+            (Some((g1, _)), Some((g2, _))) => g1.cmp(g2),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return None,
+        };
+        match ord {
+            Ordering::Less => counted.next(),
+            Ordering::Equal => {
+                stripped.next();
+                counted.next()
+            }
+            Ordering::Greater => stripped.next(),
+        }
+    })
+}
+
+/// Records of an order sorted as an *N*-gram counts file,
+/// which are given by [`GoogleBooksCounter::sorted_records`].
+pub struct SortedRecords {
+    num_records: usize,
+    records: Box<dyn Iterator<Item = CountRecord> + Send>,
+}
+
+impl SortedRecords {
+    fn new<I>(num_records: usize, records: I) -> Self
+    where
+        I: IntoIterator<Item = CountRecord>,
+        I::IntoIter: Send + 'static,
+    {
+        Self {
+            num_records,
+            records: Box::new(records.into_iter()),
+        }
+    }
+
+    /// Gets the number of the records.
+    pub const fn num_records(&self) -> usize {
+        self.num_records
+    }
+}
+
+impl Iterator for SortedRecords {
+    type Item = CountRecord;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next()
+    }
+}
+
+/// Checks if a token is a standalone tag such as `_NOUN_`.
+fn is_standalone_tag(token: &str) -> bool {
+    token.len() > 2
+        && token.starts_with('_')
+        && token.ends_with('_')
+        && POS_TAGS.contains(&&token[1..token.len() - 1])
+}
+
+/// Strips the tag from a tagged token such as `cat_NOUN`, returning `None` if it is not tagged.
+fn strip_tag(token: &str) -> Option<&str> {
+    let pos = token.rfind('_')?;
+    if pos != 0 && POS_TAGS.contains(&&token[pos + 1..]) {
+        Some(&token[..pos])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::SimpleTrieCountLm;
+
+    const UNIGRAMS_V2: &str = "A\t1999\t1\t1
+A\t2000\t2\t1
+A_NOUN\t2000\t2\t1
+B\t2000\t1\t1
+B_VERB\t2000\t1\t1
+_NOUN_\t2000\t3\t1
+C\t1800\t5\t1
+";
+
+    const BIGRAMS_V3: &str = "A B\t1999,1,1\t2000,1,1
+A_NOUN B_VERB\t2000,1,1
+_NOUN_ B\t2000,2,1
+B A\t2000,1,1
+";
+
+    fn count(pos_tags: PosTags) -> GoogleBooksCounter {
+        let mut counter = GoogleBooksCounter::new()
+            .years(1900..=2000)
+            .pos_tags(pos_tags);
+        counter.add_reader(BIGRAMS_V3.as_bytes(), "2").unwrap();
+        counter.add_reader(UNIGRAMS_V2.as_bytes(), "1").unwrap();
+        counter
+    }
+
+    #[test]
+    fn test_skip() {
+        let lm: SimpleTrieCountLm = count(PosTags::Skip).build().unwrap();
+        assert_eq!(lm.num_grams(), 4);
+        let mut lookuper = lm.lookuper();
+        assert_eq!(lookuper.with_str("A"), Some(3));
+        assert_eq!(lookuper.with_str("B"), Some(1));
+        assert_eq!(lookuper.with_str("C"), None);
+        assert_eq!(lookuper.with_str("A B"), Some(2));
+        assert_eq!(lookuper.with_str("B A"), Some(1));
+    }
+
+    #[test]
+    fn test_strip() {
+        let lm: SimpleTrieCountLm = count(PosTags::Strip).build().unwrap();
+        assert_eq!(lm.num_grams(), 4);
+        let mut lookuper = lm.lookuper();
+        assert_eq!(lookuper.with_str("A"), Some(3));
+        assert_eq!(lookuper.with_str("B"), Some(1));
+        assert_eq!(lookuper.with_str("A B"), Some(2));
+        assert_eq!(lookuper.with_str("B A"), Some(1));
+
+        // The stripped grams without untagged rows are counted.
+        let mut counter = count(PosTags::Strip);
+        counter
+            .add_reader("B_VERB B_NOUN\t2000,2,1\n".as_bytes(), "2")
+            .unwrap();
+        let lm: SimpleTrieCountLm = counter.build().unwrap();
+        assert_eq!(lm.num_grams(), 5);
+        assert_eq!(lm.lookuper().with_str("B B"), Some(2));
+    }
+
+    fn collect(counter: &GoogleBooksCounter) -> Vec<Vec<CountRecord>> {
+        counter
+            .sorted_records()
+            .unwrap()
+            .into_iter()
+            .map(|records| {
+                let num_records = records.num_records();
+                let records: Vec<_> = records.collect();
+                assert_eq!(records.len(), num_records);
+                records
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_keep() {
+        let records = collect(&count(PosTags::Keep));
+        let unigrams: Vec<String> = records[0].iter().map(|r| r.gram().to_string()).collect();
+        assert_eq!(unigrams, vec!["A", "_NOUN_", "A_NOUN", "B", "B_VERB"]);
+        let bigrams: Vec<String> = records[1].iter().map(|r| r.gram().to_string()).collect();
+        assert_eq!(bigrams, vec!["A B", "_NOUN_ B", "A_NOUN B_VERB", "B A"]);
+    }
+
+    #[test]
+    fn test_spill() {
+        let dir = tempfile::tempdir().unwrap();
+        for pos_tags in [PosTags::Keep, PosTags::Strip, PosTags::Skip] {
+            let mut counter = GoogleBooksCounter::new()
+                .years(1900..=2000)
+                .pos_tags(pos_tags)
+                .memory_budget(1)
+                .tmp_dir(dir.path());
+            counter.add_reader(BIGRAMS_V3.as_bytes(), "2").unwrap();
+            counter.add_reader(UNIGRAMS_V2.as_bytes(), "1").unwrap();
+            assert!(counter.counts.num_spilled != 0);
+            assert_eq!(collect(&counter), collect(&count(pos_tags)));
+        }
+    }
+
+    #[test]
+    fn test_write_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepaths = count(PosTags::Skip)
+            .write_files(dir.path(), GramsFileFormats::Gzip)
+            .unwrap();
+        assert_eq!(filepaths.len(), 2);
+        let lm = SimpleTrieCountLm::from_gz_files(&filepaths).unwrap();
+        assert_eq!(lm.lookuper().with_str("A B"), Some(2));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let rows = [
+            "A\t2000\t1",
+            "A\t2000\tx\t1",
+            "A\t2000,1",
+            "A  B\t2000\t1\t1",
+            "A",
+        ];
+        for row in rows {
+            let mut counter = GoogleBooksCounter::new();
+            let e = counter.add_reader(row.as_bytes(), "1").unwrap_err();
+            let e = e.downcast_ref::<ValidationError>().unwrap();
+            assert_eq!(e.kind, ValidationErrorKind::Malformed);
+        }
+    }
+
+    #[test]
+    fn test_missing_unigrams() {
+        let mut counter = GoogleBooksCounter::new();
+        counter.add_reader(BIGRAMS_V3.as_bytes(), "2").unwrap();
+        assert!(counter.sorted_records().is_err());
+    }
+}
@@ -1,26 +1,75 @@
 use std::fmt;
 
-use crate::TOKEN_SEPARATOR;
+use crate::{GRAM_COUNT_SEPARATOR, TOKEN_SEPARATOR};
+
+/// Separators of tokens in a gram and of fields in a row of *N*-gram files.
+///
+/// The default separators are [`TOKEN_SEPARATOR`] and [`GRAM_COUNT_SEPARATOR`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Separators {
+    /// The separator of tokens in a gram.
+    pub token: u8,
+    /// The separator of a gram and the count (or the prob/backoff-weight).
+    pub field: u8,
+}
+
+impl Separators {
+    /// Creates [`Separators`] of tokens and fields.
+    pub const fn new(token: u8, field: u8) -> Self {
+        Self { token, field }
+    }
+}
+
+impl Default for Separators {
+    fn default() -> Self {
+        Self::new(TOKEN_SEPARATOR, GRAM_COUNT_SEPARATOR)
+    }
+}
 
 /// Handler of a gram.
 #[derive(Clone, Copy, Eq)]
 pub struct Gram<'a> {
     data: &'a [u8],
+    separator: u8,
 }
 
 impl<'a> Gram<'a> {
     /// Creates a [`Gram`] from a byte slice.
     #[inline]
     pub const fn new(data: &'a [u8]) -> Self {
-        Self { data }
+        Self {
+            data,
+            separator: TOKEN_SEPARATOR,
+        }
     }
 
     /// Creates a [`Gram`] from a string.
     #[inline]
     pub const fn from_str(data: &'a str) -> Self {
-        Self {
-            data: data.as_bytes(),
-        }
+        Self::new(data.as_bytes())
+    }
+
+    /// Sets the separator of tokens, which is [`TOKEN_SEPARATOR`] by default.
+    ///
+    /// ```
+    /// use tongrams::Gram;
+    ///
+    /// let gram = Gram::from_str("a b|c").with_separator(b'|');
+    /// let tokens = gram.split_to_tokens();
+    /// assert_eq!(tokens.len(), 2);
+    /// assert_eq!(tokens[0].raw(), b"a b");
+    /// assert_eq!(tokens[1].raw(), b"c");
+    /// ```
+    #[inline]
+    pub const fn with_separator(mut self, separator: u8) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// Gets the separator of tokens.
+    #[inline]
+    pub const fn separator(&self) -> u8 {
+        self.separator
     }
 
     /// Copies `self` into a new `Vec`.
@@ -55,16 +104,16 @@ impl<'a> Gram<'a> {
     /// ```
     #[inline(always)]
     pub fn pop_token(&self) -> Option<(Self, Self)> {
-        let data = self.data;
-        data.iter()
-            .rev()
-            .position(|&x| x == TOKEN_SEPARATOR)
-            .map(|i| {
-                let pos = data.len() - i;
-                let pfx = &data[..pos - 1];
-                let sfx = &data[pos..];
-                (Self { data: pfx }, Self { data: sfx })
-            })
+        let (data, separator) = (self.data, self.separator);
+        data.iter().rev().position(|&x| x == separator).map(|i| {
+            let pos = data.len() - i;
+            let pfx = &data[..pos - 1];
+            let sfx = &data[pos..];
+            (
+                Self::new(pfx).with_separator(separator),
+                Self::new(sfx).with_separator(separator),
+            )
+        })
     }
 
     /// Pops the first token.
@@ -87,11 +136,14 @@ impl<'a> Gram<'a> {
     /// ```
     #[inline(always)]
     pub fn pop_front_token(&self) -> Option<(Self, Self)> {
-        let data = self.data;
-        data.iter().position(|&x| x == TOKEN_SEPARATOR).map(|i| {
+        let (data, separator) = (self.data, self.separator);
+        data.iter().position(|&x| x == separator).map(|i| {
             let pfx = &data[..i];
             let sfx = &data[i + 1..];
-            (Self { data: pfx }, Self { data: sfx })
+            (
+                Self::new(pfx).with_separator(separator),
+                Self::new(sfx).with_separator(separator),
+            )
         })
     }
 
@@ -111,9 +163,10 @@ impl<'a> Gram<'a> {
     /// ```
     #[inline(always)]
     pub fn split_to_tokens(&self) -> Vec<Self> {
+        let separator = self.separator;
         self.data
-            .split(|&b| b == TOKEN_SEPARATOR)
-            .map(|data| Self { data })
+            .split(|&b| b == separator)
+            .map(|data| Self::new(data).with_separator(separator))
             .collect()
     }
 }
@@ -0,0 +1,211 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use tempfile::NamedTempFile;
+
+/// Options of [`IntBuffer`]s created in the construction.
+#[derive(Clone, Debug)]
+pub struct BufferOptions {
+    // The maximum number of integers kept in memory for each buffer
+    capacity: usize,
+    // The directory in which temporary files are placed
+    tmp_dir: Option<PathBuf>,
+}
+
+impl Default for BufferOptions {
+    fn default() -> Self {
+        Self {
+            capacity: usize::MAX,
+            tmp_dir: None,
+        }
+    }
+}
+
+impl BufferOptions {
+    pub fn new(capacity: usize, tmp_dir: Option<PathBuf>) -> Self {
+        Self {
+            capacity: std::cmp::max(capacity, 1),
+            tmp_dir,
+        }
+    }
+
+    /// Creates an empty buffer that is expected to store about `len` integers.
+    pub fn buffer(&self, len: usize) -> IntBuffer {
+        IntBuffer {
+            ints: Vec::with_capacity(std::cmp::min(len, self.capacity)),
+            capacity: self.capacity,
+            tmp_dir: self.tmp_dir.clone(),
+            spilled: None,
+            num_spilled: 0,
+        }
+    }
+}
+
+/// Append-only sequence of integers,
+/// whose front part is spilled to a temporary file when exceeding the capacity.
+pub struct IntBuffer {
+    ints: Vec<usize>,
+    capacity: usize,
+    tmp_dir: Option<PathBuf>,
+    spilled: Option<NamedTempFile>,
+    num_spilled: usize,
+}
+
+impl IntBuffer {
+    /// Creates an in-memory buffer from integers.
+    pub const fn from_vec(ints: Vec<usize>) -> Self {
+        Self {
+            ints,
+            capacity: usize::MAX,
+            tmp_dir: None,
+            spilled: None,
+            num_spilled: 0,
+        }
+    }
+
+    pub fn push(&mut self, x: usize) -> Result<()> {
+        if self.ints.len() >= self.capacity {
+            self.spill()?;
+        }
+        self.ints.push(x);
+        Ok(())
+    }
+
+    /// Gets the mutable reference to the last integer, which is always kept in memory.
+    pub fn last_mut(&mut self) -> Option<&mut usize> {
+        self.ints.last_mut()
+    }
+
+    pub const fn len(&self) -> usize {
+        self.num_spilled + self.ints.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub const fn is_spilled(&self) -> bool {
+        self.num_spilled != 0
+    }
+
+    /// Creates an iterator over the integers, which reads the temporary file from the beginning.
+    pub const fn iter(&self) -> IntBufferIter<'_> {
+        IntBufferIter {
+            buffer: self,
+            reader: None,
+            pos: 0,
+        }
+    }
+
+    /// Releases the integers as a vector.
+    pub fn into_vec(self) -> Result<Vec<usize>> {
+        if self.is_spilled() {
+            self.iter().collect()
+        } else {
+            Ok(self.ints)
+        }
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        if self.spilled.is_none() {
+            self.spilled = Some(match &self.tmp_dir {
+                Some(tmp_dir) => NamedTempFile::new_in(tmp_dir)?,
+                None => NamedTempFile::new()?,
+            });
+        }
+        let file = self.spilled.as_mut().unwrap().as_file_mut();
+        let mut writer = BufWriter::new(file);
+        for &x in &self.ints {
+            writer.write_u64::<LittleEndian>(x as u64)?;
+        }
+        writer.flush()?;
+        self.num_spilled += self.ints.len();
+        self.ints.clear();
+        Ok(())
+    }
+
+    fn open_spilled(&self) -> Result<BufReader<File>> {
+        let path: &Path = self.spilled.as_ref().unwrap().path();
+        Ok(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<'a> IntoIterator for &'a IntBuffer {
+    type Item = Result<usize>;
+    type IntoIter = IntBufferIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the integers of [`IntBuffer`].
+pub struct IntBufferIter<'a> {
+    buffer: &'a IntBuffer,
+    reader: Option<BufReader<File>>,
+    pos: usize,
+}
+
+impl Iterator for IntBufferIter<'_> {
+    type Item = Result<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.pos;
+        if pos >= self.buffer.len() {
+            return None;
+        }
+        self.pos += 1;
+        if pos >= self.buffer.num_spilled {
+            return Some(Ok(self.buffer.ints[pos - self.buffer.num_spilled]));
+        }
+        if self.reader.is_none() {
+            match self.buffer.open_spilled() {
+                Ok(reader) => self.reader = Some(reader),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        let reader = self.reader.as_mut().unwrap();
+        Some(
+            reader
+                .read_u64::<LittleEndian>()
+                .map(|x| x as usize)
+                .map_err(|e| e.into()),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.buffer.len() - self.pos;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spill() {
+        let ints: Vec<usize> = (0..100).map(|i| i * 7 % 31).collect();
+        for capacity in [1, 3, 100, 1000] {
+            let mut buffer = BufferOptions::new(capacity, None).buffer(ints.len());
+            for &x in &ints {
+                buffer.push(x).unwrap();
+            }
+            *buffer.last_mut().unwrap() += 1;
+            assert_eq!(buffer.len(), ints.len());
+            assert_eq!(buffer.is_spilled(), capacity < ints.len());
+
+            let mut expected = ints.clone();
+            *expected.last_mut().unwrap() += 1;
+            // Can be scanned multiple times.
+            for _ in 0..2 {
+                let scanned: Vec<usize> = buffer.iter().map(|x| x.unwrap()).collect();
+                assert_eq!(scanned, expected);
+            }
+            assert_eq!(buffer.into_vec().unwrap(), expected);
+        }
+    }
+}
@@ -96,37 +96,51 @@
 #[cfg(not(target_pointer_width = "64"))]
 compile_error!("`target_pointer_width` must be 64");
 
+pub mod corpus;
+pub mod google_books;
 pub mod gram;
 pub mod loader;
 pub mod parser;
+pub mod progress;
 pub mod record;
+pub mod sorter;
 pub mod trie_count_lm;
 pub mod trie_prob_lm;
 pub mod util;
+pub mod validation;
 pub mod vocabulary;
+pub mod writer;
 
+mod int_buffer;
 mod mappers;
 mod rank_array;
 mod trie_array;
 
-/// The maximum order of *N*-grams (i.e., `1 <= N <= 8`).
+/// The maximum order of *N*-grams whose token ids are mapped into a fixed-size buffer in lookup.
+/// Grams of higher orders are mapped into heap-allocated buffers.
 pub const MAX_ORDER: usize = 8;
 /// The separator for tokens.
 pub const TOKEN_SEPARATOR: u8 = b' ';
 /// The separator for grams and count.
 pub const GRAM_COUNT_SEPARATOR: u8 = b'\t';
 
-pub use gram::Gram;
-pub use record::{CountRecord, ProbRecord};
-pub use trie_count_lm::TrieCountLm;
+pub use gram::{Gram, Separators};
+pub use record::{CountRecord, CountRecordRef, ProbRecord, ProbRecordRef};
+pub use trie_count_lm::{AnyTrieCountLm, TrieCountLm};
 pub use trie_prob_lm::TrieProbLm;
 
 pub use loader::{GramsFileFormats, GramsLoader};
 pub use parser::GramsParser;
+pub use validation::{ValidationError, ValidationErrorKind};
+pub use writer::{FinishWrite, GramsEncoder, GramsWriter};
 
-pub use rank_array::{EliasFanoRankArray, RankArray, SimpleRankArray};
+pub use rank_array::{
+    CompactRankArray, EliasFanoRankArray, GammaRankArray, RankArray, SimpleRankArray,
+};
 pub use trie_array::{EliasFanoTrieArray, SimpleTrieArray, TrieArray};
-pub use vocabulary::{DoubleArrayVocabulary, SimpleVocabulary, Vocabulary};
+pub use vocabulary::{
+    DoubleArrayVocabulary, ReversibleDoubleArrayVocabulary, SimpleVocabulary, Vocabulary,
+};
 
 /// Simple implementation of [`TrieCountLm`].
 /// Note that this is for debug, and do NOT use it for storing massive datasets.
@@ -137,4 +151,20 @@ pub type SimpleTrieCountLm = TrieCountLm<SimpleTrieArray, SimpleVocabulary, Simp
 pub type EliasFanoTrieCountLm =
     TrieCountLm<EliasFanoTrieArray, DoubleArrayVocabulary, EliasFanoRankArray>;
 
+/// Elias-Fano Trie implementation of [`TrieCountLm`] with bit-packed count ranks.
+/// This is faster to look up but larger than [`EliasFanoTrieCountLm`] in general.
+pub type CompactRankTrieCountLm =
+    TrieCountLm<EliasFanoTrieArray, DoubleArrayVocabulary, CompactRankArray>;
+
+/// Elias-Fano Trie implementation of [`TrieCountLm`] with Elias-gamma coded count ranks.
+/// This is effective when the distribution of count ranks is highly skewed.
+pub type GammaRankTrieCountLm =
+    TrieCountLm<EliasFanoTrieArray, DoubleArrayVocabulary, GammaRankArray>;
+
+/// Elias-Fano Trie implementation of [`TrieCountLm`] with the reverse lookup of tokens,
+/// which can be merged and dumped.
+/// This is larger than [`EliasFanoTrieCountLm`] by the bytes of the tokens and their offsets.
+pub type ReversibleTrieCountLm =
+    TrieCountLm<EliasFanoTrieArray, ReversibleDoubleArrayVocabulary, EliasFanoRankArray>;
+
 pub type SimpleTrieProbLm = TrieProbLm<SimpleTrieArray, SimpleVocabulary>;
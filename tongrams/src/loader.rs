@@ -1,15 +1,39 @@
+mod auto;
+#[cfg(feature = "bzip2")]
+mod bzip2;
 mod flate2;
+mod multi;
 mod plain;
+mod reader;
+mod stream;
+#[cfg(feature = "xz")]
+mod xz;
+#[cfg(feature = "zstd")]
+mod zstd;
 
-use std::io::Read;
+use std::fs::File;
+use std::io::{BufReader, Chain, Cursor, Read, Write};
+use std::path::Path;
 use std::str::FromStr;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 use crate::parser::GramsParser;
+use crate::writer::GramsEncoder;
+use crate::Separators;
 
-pub use crate::loader::flate2::GramsGzFileLoader;
+pub use crate::loader::auto::GramsAutoFileLoader;
+#[cfg(feature = "bzip2")]
+pub use crate::loader::bzip2::GramsBzip2FileLoader;
+pub use crate::loader::flate2::{GramsDeflateFileLoader, GramsGzFileLoader, GramsZlibFileLoader};
+pub use crate::loader::multi::GramsMultiOrderLoader;
 pub use crate::loader::plain::{GramsFileLoader, GramsTextLoader};
+pub use crate::loader::reader::GramsReaderLoader;
+pub use crate::loader::stream::GramsStreamLoader;
+#[cfg(feature = "xz")]
+pub use crate::loader::xz::GramsXzFileLoader;
+#[cfg(feature = "zstd")]
+pub use crate::loader::zstd::GramsZstdFileLoader;
 
 /// Loader for a *N*-gram counts file.
 pub trait GramsLoader<R>
@@ -20,11 +44,184 @@ where
     fn parser(&self) -> Result<GramsParser<R>>;
 }
 
+/// Boxed loader whose parsers read decompressed bytes, e.g., of [`GramsMultiOrderLoader::loaders`].
+pub type BoxedGramsLoader = Box<dyn GramsLoader<Box<dyn Read + Send>>>;
+
+/// Options of the parsers created by the loaders detecting the formats.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ParserOptions {
+    fmt: Option<GramsFileFormats>,
+    headerless: bool,
+    separators: Separators,
+}
+
+impl ParserOptions {
+    /// Creates [`GramsParser`] of the decompressed bytes of a reader,
+    /// detecting the format from the magic bytes unless it is given.
+    fn parser<R>(&self, reader: R, name: String) -> Result<GramsParser<Box<dyn Read + Send>>>
+    where
+        R: Read + Send + 'static,
+    {
+        let reader = match self.fmt {
+            Some(fmt) => fmt.decoder(reader)?,
+            None => {
+                let (fmt, reader) = GramsFileFormats::detect_reader(reader)?;
+                fmt.decoder(reader)?
+            }
+        };
+        let reader = BufReader::new(reader);
+        if self.headerless {
+            Ok(GramsParser::without_header(reader, name, self.separators))
+        } else {
+            GramsParser::with_separators(reader, name, self.separators)
+        }
+    }
+}
+
+/// Reader of the bytes of [`GramsFileFormats::detect_reader`], including the magic bytes.
+pub type DetectedReader<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// The number of bytes read to detect the format,
+/// which are also decoded to confirm the header of a zlib stream.
+const MAGIC_BYTES_LEN: usize = 1024;
+
 /// File formats supported.
-#[derive(Clone, Copy, Debug)]
+///
+/// `Zstd`, `Xz`, and `Bzip2` are available with the cargo features
+/// `zstd`, `xz`, and `bzip2`, respectively.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum GramsFileFormats {
     Plain,
     Gzip,
+    Deflate,
+    Zlib,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+impl GramsFileFormats {
+    /// Detects the format from the magic bytes at the beginning of a file,
+    /// where the file is regarded as plain text if no magic bytes are matched.
+    ///
+    /// Raw deflate streams have no magic bytes and cannot be detected.
+    /// Zlib streams are detected from the headers of the default window size without dictionaries,
+    /// which are confirmed with the header checksum and by decoding the following bytes,
+    /// since such headers are also valid text (e.g., `x^`).
+    pub fn from_magic_bytes(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if is_zlib_header(bytes) && is_zlib_prefix(bytes) {
+            Self::Zlib
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Self::Xz
+        } else if bytes.starts_with(b"BZh") {
+            Self::Bzip2
+        } else {
+            Self::Plain
+        }
+    }
+
+    /// Detects the format of a file from the magic bytes.
+    pub fn detect<P>(filepath: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut bytes = Vec::with_capacity(MAGIC_BYTES_LEN);
+        File::open(filepath)?
+            .take(MAGIC_BYTES_LEN as u64)
+            .read_to_end(&mut bytes)?;
+        Ok(Self::from_magic_bytes(&bytes))
+    }
+
+    /// Detects the format of a reader from the magic bytes,
+    /// returning it with the reader of all the bytes including the magic bytes,
+    /// so that streams that cannot be rewound can be detected.
+    pub fn detect_reader<R>(mut reader: R) -> Result<(Self, DetectedReader<R>)>
+    where
+        R: Read,
+    {
+        let mut bytes = Vec::with_capacity(MAGIC_BYTES_LEN);
+        (&mut reader)
+            .take(MAGIC_BYTES_LEN as u64)
+            .read_to_end(&mut bytes)?;
+        Ok((
+            Self::from_magic_bytes(&bytes),
+            Cursor::new(bytes).chain(reader),
+        ))
+    }
+
+    /// Opens a file of the format, returning the reader of the decompressed bytes.
+    pub fn open<P>(self, filepath: P) -> Result<Box<dyn Read + Send>>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::open(filepath)?;
+        self.decoder(file)
+    }
+
+    /// Wraps a reader of the format, returning the reader of the decompressed bytes.
+    pub fn decoder<R>(self, reader: R) -> Result<Box<dyn Read + Send>>
+    where
+        R: Read + Send + 'static,
+    {
+        match self {
+            Self::Plain => Ok(Box::new(reader)),
+            Self::Gzip => Ok(Box::new(::flate2::read::MultiGzDecoder::new(reader))),
+            Self::Deflate => Ok(Box::new(::flate2::read::DeflateDecoder::new(reader))),
+            Self::Zlib => Ok(Box::new(::flate2::read::ZlibDecoder::new(reader))),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(Box::new(::zstd::stream::read::Decoder::new(reader)?)),
+            #[cfg(feature = "xz")]
+            Self::Xz => Ok(Box::new(::xz2::read::XzDecoder::new_multi_decoder(reader))),
+            #[cfg(feature = "bzip2")]
+            Self::Bzip2 => Ok(Box::new(::bzip2::read::MultiBzDecoder::new(reader))),
+            #[allow(unreachable_patterns)]
+            fmt => Err(anyhow!(
+                "{:?} format requires enabling the cargo feature of it.",
+                fmt
+            )),
+        }
+    }
+
+    /// Wraps a writer, returning the writer compressing bytes in the format,
+    /// which should be finished with [`FinishWrite::finish_write`](crate::FinishWrite::finish_write).
+    pub fn encoder<W>(self, writer: W) -> Result<GramsEncoder<W>>
+    where
+        W: Write,
+    {
+        GramsEncoder::new(writer, self)
+    }
+}
+
+/// Checks the header of a zlib stream of the default window size without dictionaries.
+fn is_zlib_header(bytes: &[u8]) -> bool {
+    bytes.len() >= 2
+        && bytes[0] == 0x78
+        && bytes[1] & 0x20 == 0
+        && (u16::from(bytes[0]) << 8 | u16::from(bytes[1])) % 31 == 0
+}
+
+/// Checks if the bytes at the beginning of a stream can be decoded as zlib without errors.
+fn is_zlib_prefix(bytes: &[u8]) -> bool {
+    let mut decompress = ::flate2::Decompress::new(true);
+    let mut buf = [0; 4096];
+    loop {
+        let (total_in, total_out) = (decompress.total_in(), decompress.total_out());
+        let input = &bytes[total_in as usize..];
+        match decompress.decompress(input, &mut buf, ::flate2::FlushDecompress::None) {
+            Ok(::flate2::Status::StreamEnd) => return true,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+        if decompress.total_in() as usize == bytes.len()
+            || (decompress.total_in(), decompress.total_out()) == (total_in, total_out)
+        {
+            return true;
+        }
+    }
 }
 
 impl FromStr for GramsFileFormats {
@@ -34,7 +231,98 @@ impl FromStr for GramsFileFormats {
         match fmt {
             "plain" => Ok(Self::Plain),
             "gzip" => Ok(Self::Gzip),
+            "deflate" => Ok(Self::Deflate),
+            "zlib" => Ok(Self::Zlib),
+            "zstd" => Ok(Self::Zstd),
+            "xz" => Ok(Self::Xz),
+            "bzip2" => Ok(Self::Bzip2),
             _ => Err("Invalid format"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::NamedTempFile;
+
+    use crate::FinishWrite;
+
+    const GRAMS_1: &str = "3
+A\t3
+B\t2
+C\t1
+";
+
+    #[test]
+    fn test_from_magic_bytes() {
+        assert_eq!(
+            GramsFileFormats::from_magic_bytes(&[0x1f, 0x8b, 0x08]),
+            GramsFileFormats::Gzip
+        );
+        assert_eq!(
+            GramsFileFormats::from_magic_bytes(&[0x78, 0x9c]),
+            GramsFileFormats::Zlib
+        );
+        assert_eq!(
+            GramsFileFormats::from_magic_bytes(b"x y"),
+            GramsFileFormats::Plain
+        );
+        assert_eq!(
+            GramsFileFormats::from_magic_bytes(b"x^2\tA\t3\nx^3\tB\t2\n"),
+            GramsFileFormats::Plain
+        );
+        assert_eq!(
+            GramsFileFormats::from_magic_bytes(b"x\x01 y\t1\n"),
+            GramsFileFormats::Plain
+        );
+        assert_eq!(
+            GramsFileFormats::from_magic_bytes(&[0x28, 0xb5, 0x2f, 0xfd]),
+            GramsFileFormats::Zstd
+        );
+        assert_eq!(
+            GramsFileFormats::from_magic_bytes(b"\xfd7zXZ\x00"),
+            GramsFileFormats::Xz
+        );
+        assert_eq!(
+            GramsFileFormats::from_magic_bytes(b"BZh9"),
+            GramsFileFormats::Bzip2
+        );
+        assert_eq!(
+            GramsFileFormats::from_magic_bytes(GRAMS_1.as_bytes()),
+            GramsFileFormats::Plain
+        );
+        assert_eq!(
+            GramsFileFormats::from_magic_bytes(&[]),
+            GramsFileFormats::Plain
+        );
+    }
+
+    #[test]
+    fn test_auto_file_loader() {
+        let fmts = [
+            Some(GramsFileFormats::Plain),
+            Some(GramsFileFormats::Gzip),
+            Some(GramsFileFormats::Deflate),
+            Some(GramsFileFormats::Zlib),
+            cfg!(feature = "zstd").then_some(GramsFileFormats::Zstd),
+            cfg!(feature = "xz").then_some(GramsFileFormats::Xz),
+            cfg!(feature = "bzip2").then_some(GramsFileFormats::Bzip2),
+        ];
+        for &fmt in fmts.iter().flatten() {
+            let tmp = NamedTempFile::new().unwrap();
+            let mut writer = fmt.encoder(File::create(tmp.path()).unwrap()).unwrap();
+            writer.write_all(GRAMS_1.as_bytes()).unwrap();
+            writer.finish_write().unwrap();
+            if fmt != GramsFileFormats::Deflate {
+                assert_eq!(GramsFileFormats::detect(tmp.path()).unwrap(), fmt);
+            }
+
+            let loader = GramsAutoFileLoader::new(tmp.path()).format(fmt);
+            let records = loader.parser().unwrap().into_count_records();
+            let grams: Vec<String> = records.map(|r| r.unwrap().gram().to_string()).collect();
+            assert_eq!(grams, vec!["A", "B", "C"]);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::loader::{GramsFileFormats, GramsLoader, ParserOptions};
+use crate::parser::GramsParser;
+use crate::Separators;
+
+/// Loader for a *N*-gram counts file whose format is detected from the magic bytes
+/// unless it is given with [`GramsAutoFileLoader::format`].
+///
+/// See [`GramsFileFormats::from_magic_bytes`] for the formats detected.
+pub struct GramsAutoFileLoader {
+    filepath: PathBuf,
+    options: ParserOptions,
+}
+
+impl GramsAutoFileLoader {
+    pub fn new<P>(filepath: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            filepath: PathBuf::from(filepath.as_ref()),
+            options: ParserOptions::default(),
+        }
+    }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.options.separators = separators;
+        self
+    }
+
+    /// Sets the format of the file instead of detecting it.
+    pub const fn format(mut self, fmt: GramsFileFormats) -> Self {
+        self.options.fmt = Some(fmt);
+        self
+    }
+
+    /// Sets whether the file has no header (default: detected from the first line).
+    pub const fn headerless(mut self, headerless: bool) -> Self {
+        self.options.headerless = headerless;
+        self
+    }
+}
+
+impl GramsLoader<Box<dyn Read + Send>> for GramsAutoFileLoader {
+    fn parser(&self) -> Result<GramsParser<Box<dyn Read + Send>>> {
+        let file = File::open(&self.filepath)?;
+        self.options
+            .parser(file, self.filepath.display().to_string())
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use bzip2::read::MultiBzDecoder;
+
+use crate::loader::GramsLoader;
+use crate::parser::GramsParser;
+use crate::Separators;
+
+/// Loader for a bzip2 compressed *N*-gram counts file,
+/// which can consist of multiple streams (e.g., compressed by `pbzip2`).
+pub struct GramsBzip2FileLoader {
+    filepath: PathBuf,
+    separators: Separators,
+}
+
+impl GramsBzip2FileLoader {
+    pub fn new<P>(filepath: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            filepath: PathBuf::from(filepath.as_ref()),
+            separators: Separators::default(),
+        }
+    }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.separators = separators;
+        self
+    }
+}
+
+impl GramsLoader<MultiBzDecoder<File>> for GramsBzip2FileLoader {
+    fn parser(&self) -> Result<GramsParser<MultiBzDecoder<File>>> {
+        let reader = MultiBzDecoder::new(File::open(&self.filepath)?);
+        GramsParser::with_separators(
+            BufReader::new(reader),
+            self.filepath.display().to_string(),
+            self.separators,
+        )
+    }
+}
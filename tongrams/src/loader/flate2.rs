@@ -3,13 +3,16 @@ use std::io::BufReader;
 use std::path::{Path, PathBuf};
 
 use anyhow::Result;
-use flate2::read::GzDecoder;
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
 
 use crate::loader::GramsLoader;
 use crate::parser::GramsParser;
+use crate::Separators;
 
+/// Loader for a gzip compressed *N*-gram counts file.
 pub struct GramsGzFileLoader {
     filepath: PathBuf,
+    separators: Separators,
 }
 
 impl GramsGzFileLoader {
@@ -19,13 +22,94 @@ impl GramsGzFileLoader {
     {
         Self {
             filepath: PathBuf::from(filepath.as_ref()),
+            separators: Separators::default(),
         }
     }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.separators = separators;
+        self
+    }
+}
+
+impl GramsLoader<MultiGzDecoder<File>> for GramsGzFileLoader {
+    fn parser(&self) -> Result<GramsParser<MultiGzDecoder<File>>> {
+        let reader = MultiGzDecoder::new(File::open(&self.filepath)?);
+        GramsParser::with_separators(
+            BufReader::new(reader),
+            self.filepath.display().to_string(),
+            self.separators,
+        )
+    }
+}
+
+/// Loader for a raw deflate compressed *N*-gram counts file.
+pub struct GramsDeflateFileLoader {
+    filepath: PathBuf,
+    separators: Separators,
+}
+
+impl GramsDeflateFileLoader {
+    pub fn new<P>(filepath: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            filepath: PathBuf::from(filepath.as_ref()),
+            separators: Separators::default(),
+        }
+    }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.separators = separators;
+        self
+    }
+}
+
+impl GramsLoader<DeflateDecoder<File>> for GramsDeflateFileLoader {
+    fn parser(&self) -> Result<GramsParser<DeflateDecoder<File>>> {
+        let reader = DeflateDecoder::new(File::open(&self.filepath)?);
+        GramsParser::with_separators(
+            BufReader::new(reader),
+            self.filepath.display().to_string(),
+            self.separators,
+        )
+    }
+}
+
+/// Loader for a zlib compressed *N*-gram counts file.
+pub struct GramsZlibFileLoader {
+    filepath: PathBuf,
+    separators: Separators,
+}
+
+impl GramsZlibFileLoader {
+    pub fn new<P>(filepath: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            filepath: PathBuf::from(filepath.as_ref()),
+            separators: Separators::default(),
+        }
+    }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.separators = separators;
+        self
+    }
 }
 
-impl GramsLoader<GzDecoder<File>> for GramsGzFileLoader {
-    fn parser(&self) -> Result<GramsParser<GzDecoder<File>>> {
-        let reader = GzDecoder::new(File::open(&self.filepath)?);
-        GramsParser::new(BufReader::new(reader))
+impl GramsLoader<ZlibDecoder<File>> for GramsZlibFileLoader {
+    fn parser(&self) -> Result<GramsParser<ZlibDecoder<File>>> {
+        let reader = ZlibDecoder::new(File::open(&self.filepath)?);
+        GramsParser::with_separators(
+            BufReader::new(reader),
+            self.filepath.display().to_string(),
+            self.separators,
+        )
     }
 }
@@ -0,0 +1,327 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use tempfile::NamedTempFile;
+
+use crate::loader::{BoxedGramsLoader, GramsFileFormats, GramsLoader};
+use crate::parser::GramsParser;
+use crate::validation::{ValidationError, ValidationErrorKind};
+use crate::Separators;
+
+/// Loader for a *N*-gram counts file containing all the orders, which is split into
+/// the loaders of *N* = 1, 2, ... with [`GramsMultiOrderLoader::loaders`].
+///
+/// The rows of the file are sorted by the orders, and the section of each order is either
+///
+///  - preceded by a section header `\N-grams:` (e.g., `\2-grams:`), or
+///  - detected from the number of tokens in the rows if the file has no section headers.
+///
+/// Blank lines between sections are ignored.
+/// The format of the file is detected from the magic bytes unless it is given with
+/// [`GramsMultiOrderLoader::format`].
+/// A compressed file is decompressed only once, into a temporary file read by the loaders.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Write;
+///
+/// use tongrams::loader::GramsMultiOrderLoader;
+/// use tongrams::trie_count_lm::TrieCountLmBuilder;
+/// use tongrams::EliasFanoTrieCountLm;
+///
+/// let mut file = tempfile::NamedTempFile::new().unwrap();
+/// write!(file, "\\1-grams:\nA\t3\nB\t1\n\n\\2-grams:\nA B\t1\n").unwrap();
+///
+/// let loaders = GramsMultiOrderLoader::new(file.path()).loaders().unwrap();
+/// let lm: EliasFanoTrieCountLm = TrieCountLmBuilder::new(loaders).unwrap().build().unwrap();
+/// assert_eq!(lm.num_orders(), 2);
+/// assert_eq!(lm.lookuper().with_str("A B"), Some(1));
+/// ```
+pub struct GramsMultiOrderLoader {
+    filepath: PathBuf,
+    fmt: Option<GramsFileFormats>,
+    separators: Separators,
+    tmp_dir: Option<PathBuf>,
+}
+
+impl GramsMultiOrderLoader {
+    pub fn new<P>(filepath: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            filepath: PathBuf::from(filepath.as_ref()),
+            fmt: None,
+            separators: Separators::default(),
+            tmp_dir: None,
+        }
+    }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.separators = separators;
+        self
+    }
+
+    /// Sets the format of the file instead of detecting it.
+    pub const fn format(mut self, fmt: GramsFileFormats) -> Self {
+        self.fmt = Some(fmt);
+        self
+    }
+
+    /// Sets the directory in which the temporary file of a compressed file is placed
+    /// (default: the temporary directory of the system).
+    pub fn tmp_dir<P>(mut self, tmp_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.tmp_dir = Some(tmp_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Scans the file to locate the sections, returning the loaders of *N* = 1, 2, ...
+    ///
+    /// Each loader reads only its section, where the compressed file is decompressed
+    /// into a temporary file in scanning.
+    pub fn loaders(&self) -> Result<Vec<BoxedGramsLoader>> {
+        let fmt = match self.fmt {
+            Some(fmt) => fmt,
+            None => GramsFileFormats::detect(&self.filepath)?,
+        };
+        let name = self.filepath.display().to_string();
+        let (sections, spool) = if fmt == GramsFileFormats::Plain {
+            let reader = BufReader::new(File::open(&self.filepath)?);
+            (scan_sections(reader, &name, self.separators)?, None)
+        } else {
+            let mut spool = match &self.tmp_dir {
+                Some(tmp_dir) => NamedTempFile::new_in(tmp_dir)?,
+                None => NamedTempFile::new()?,
+            };
+            let mut reader = BufReader::new(SpoolingReader {
+                reader: fmt.open(&self.filepath)?,
+                spool: BufWriter::new(spool.as_file_mut()),
+            });
+            let sections = scan_sections(&mut reader, &name, self.separators)?;
+            reader.into_inner().spool.flush()?;
+            (sections, Some(Arc::new(spool)))
+        };
+        if sections.is_empty() {
+            return Err(anyhow!("{} has no grams.", name));
+        }
+        Ok(sections
+            .into_iter()
+            .map(|section| {
+                let loader: BoxedGramsLoader = Box::new(GramsSectionLoader {
+                    filepath: self.filepath.clone(),
+                    spool: spool.clone(),
+                    separators: self.separators,
+                    section,
+                });
+                loader
+            })
+            .collect())
+    }
+}
+
+/// Reader writing the bytes read into the temporary file of decompressed bytes.
+struct SpoolingReader<R, W> {
+    reader: R,
+    spool: W,
+}
+
+impl<R, W> Read for SpoolingReader<R, W>
+where
+    R: Read,
+    W: Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.spool.write_all(&buf[..n])?;
+        Ok(n)
+    }
+}
+
+/// Section of an order in the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Section {
+    // The byte offset of the first row in the decompressed file
+    start: u64,
+    // The byte offset next to the last row
+    end: u64,
+    // The number of lines preceding the first row
+    line_offset: usize,
+}
+
+/// Scans the sections of *N* = 1, 2, ... in the decompressed file.
+fn scan_sections<R>(mut reader: R, name: &str, separators: Separators) -> Result<Vec<Section>>
+where
+    R: BufRead,
+{
+    let mut sections: Vec<Section> = vec![];
+    let mut with_headers = None;
+    let mut offset = 0;
+    let mut line = String::new();
+    for line_no in 1.. {
+        line.clear();
+        let len = reader.read_line(&mut line)? as u64;
+        if len == 0 {
+            break;
+        }
+        let row = line.trim_end();
+        let start = offset;
+        offset += len;
+        if row.is_empty() {
+            continue;
+        }
+        let header_order = parse_section_header(row);
+        if *with_headers.get_or_insert_with(|| header_order.is_some()) {
+            if let Some(order) = header_order {
+                if order != sections.len() + 1 {
+                    let kind = ValidationErrorKind::WrongOrder;
+                    return Err(ValidationError::new(name, line_no, row, kind).into());
+                }
+                sections.push(Section {
+                    start: offset,
+                    end: offset,
+                    line_offset: line_no,
+                });
+            } else {
+                sections.last_mut().unwrap().end = offset;
+            }
+            continue;
+        }
+
+        let gram = row.split(separators.field as char).next().unwrap();
+        let order = gram.split(separators.token as char).count();
+        if order == sections.len() + 1 {
+            sections.push(Section {
+                start,
+                end: offset,
+                line_offset: line_no - 1,
+            });
+        } else if order == sections.len() {
+            sections.last_mut().unwrap().end = offset;
+        } else {
+            let kind = ValidationErrorKind::WrongOrder;
+            return Err(ValidationError::new(name, line_no, row, kind).into());
+        }
+    }
+    Ok(sections)
+}
+
+/// Parses a section header `\N-grams:`, returning *N*.
+fn parse_section_header(row: &str) -> Option<usize> {
+    row.strip_prefix('\\')?
+        .strip_suffix("-grams:")?
+        .parse()
+        .ok()
+        .filter(|&order| order != 0)
+}
+
+/// Loader for a section of [`GramsMultiOrderLoader`].
+struct GramsSectionLoader {
+    filepath: PathBuf,
+    // The decompressed bytes of the file if compressed
+    spool: Option<Arc<NamedTempFile>>,
+    separators: Separators,
+    section: Section,
+}
+
+impl GramsLoader<Box<dyn Read + Send>> for GramsSectionLoader {
+    fn parser(&self) -> Result<GramsParser<Box<dyn Read + Send>>> {
+        let Section {
+            start,
+            end,
+            line_offset,
+        } = self.section;
+        let mut file = match &self.spool {
+            Some(spool) => spool.reopen()?,
+            None => File::open(&self.filepath)?,
+        };
+        file.seek(SeekFrom::Start(start))?;
+        let reader: Box<dyn Read + Send> = Box::new(file.take(end - start));
+        let name = self.filepath.display().to_string();
+        let parser = GramsParser::without_header(BufReader::new(reader), name, self.separators);
+        Ok(parser.line_offset(line_offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use crate::FinishWrite;
+
+    fn read_records(loaders: &[BoxedGramsLoader]) -> Vec<Vec<String>> {
+        loaders
+            .iter()
+            .map(|loader| {
+                let records = loader.parser().unwrap().into_count_records();
+                records.map(|rec| rec.unwrap().gram().to_string()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_multi_order() {
+        let texts = [
+            "\\1-grams:\nA\t3\nB\t2\n\n\\2-grams:\nA B\t2\nB A\t1\n\n\\3-grams:\nA B A\t1\n\n",
+            "A\t3\nB\t2\nA B\t2\nB A\t1\n\nA B A\t1\n",
+        ];
+        let expected = vec![
+            vec!["A".to_string(), "B".to_string()],
+            vec!["A B".to_string(), "B A".to_string()],
+            vec!["A B A".to_string()],
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        for text in texts {
+            for fmt in [GramsFileFormats::Plain, GramsFileFormats::Gzip] {
+                let tmp = NamedTempFile::new().unwrap();
+                let mut writer = fmt.encoder(File::create(tmp.path()).unwrap()).unwrap();
+                writer.write_all(text.as_bytes()).unwrap();
+                writer.finish_write().unwrap();
+                let loaders = GramsMultiOrderLoader::new(tmp.path())
+                    .tmp_dir(dir.path())
+                    .loaders()
+                    .unwrap();
+                assert_eq!(read_records(&loaders), expected);
+
+                // Only the compressed file is decompressed into a temporary file.
+                let num_spooled = usize::from(fmt != GramsFileFormats::Plain);
+                assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), num_spooled);
+                drop(loaders);
+                assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_sections() {
+        let scan = |text: &str| {
+            let e = scan_sections(text.as_bytes(), "input", Separators::default()).unwrap_err();
+            e.downcast_ref::<ValidationError>().unwrap().clone()
+        };
+        let e = scan("\\1-grams:\nA\t1\n\\3-grams:\nA A A\t1\n");
+        assert_eq!((e.line, e.kind), (3, ValidationErrorKind::WrongOrder));
+        let e = scan("A\t1\nA B\t1\nA\t1\n");
+        assert_eq!((e.line, e.kind), (3, ValidationErrorKind::WrongOrder));
+
+        // The line numbers are reported in the whole file.
+        let text = "\\1-grams:\nA\t1\n\n\\2-grams:\nA A\tx\n";
+        let sections = scan_sections(text.as_bytes(), "input", Separators::default()).unwrap();
+        let range = sections[1].start as usize..sections[1].end as usize;
+        let reader = BufReader::new(&text.as_bytes()[range]);
+        let parser = GramsParser::without_header(reader, "input", Separators::default())
+            .line_offset(sections[1].line_offset);
+        let e = parser.into_count_records().next().unwrap().unwrap_err();
+        assert_eq!(e.downcast_ref::<ValidationError>().unwrap().line, 5);
+    }
+}
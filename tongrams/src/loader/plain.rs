@@ -6,9 +6,11 @@ use anyhow::Result;
 
 use crate::loader::GramsLoader;
 use crate::parser::GramsParser;
+use crate::{Separators, GRAM_COUNT_SEPARATOR, TOKEN_SEPARATOR};
 
 pub struct GramsFileLoader {
     filepath: PathBuf,
+    separators: Separators,
 }
 
 impl GramsFileLoader {
@@ -18,30 +20,47 @@ impl GramsFileLoader {
     {
         Self {
             filepath: PathBuf::from(filepath.as_ref()),
+            separators: Separators::default(),
         }
     }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.separators = separators;
+        self
+    }
 }
 
 impl GramsLoader<File> for GramsFileLoader {
     fn parser(&self) -> Result<GramsParser<File>> {
         let reader = BufReader::new(File::open(&self.filepath)?);
-        GramsParser::new(reader)
+        GramsParser::with_separators(reader, self.filepath.display().to_string(), self.separators)
     }
 }
 
 pub struct GramsTextLoader<'a> {
     text: &'a [u8],
+    separators: Separators,
 }
 
 impl<'a> GramsTextLoader<'a> {
     pub const fn new(text: &'a [u8]) -> Self {
-        Self { text }
+        Self {
+            text,
+            separators: Separators::new(TOKEN_SEPARATOR, GRAM_COUNT_SEPARATOR),
+        }
+    }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.separators = separators;
+        self
     }
 }
 
 impl<'a> GramsLoader<&'a [u8]> for GramsTextLoader<'a> {
     fn parser(&self) -> Result<GramsParser<&'a [u8]>> {
         let reader = BufReader::new(self.text);
-        GramsParser::new(reader)
+        GramsParser::with_separators(reader, "text", self.separators)
     }
 }
@@ -0,0 +1,113 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+
+use crate::loader::{GramsFileFormats, GramsLoader, ParserOptions};
+use crate::parser::GramsParser;
+use crate::Separators;
+
+/// Loader for *N*-gram counts in a seekable reader (e.g., an in-memory buffer or an opened file),
+/// which is rewound to the initial position for each parser.
+///
+/// The format is detected from the magic bytes unless it is given with [`GramsReaderLoader::format`].
+/// Since the parsers share the reader, a parser should be consumed before the next one is created.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+///
+/// use tongrams::loader::{GramsLoader, GramsReaderLoader};
+/// use tongrams::EliasFanoTrieCountLm;
+/// use tongrams::trie_count_lm::TrieCountLmBuilder;
+///
+/// let loaders: Vec<Box<dyn GramsLoader<_>>> = vec![
+///     Box::new(GramsReaderLoader::new(Cursor::new("2\nA\t3\nB\t1\n")).unwrap()),
+///     Box::new(GramsReaderLoader::new(Cursor::new("1\nA B\t1\n")).unwrap()),
+/// ];
+/// let lm: EliasFanoTrieCountLm = TrieCountLmBuilder::new(loaders).unwrap().build().unwrap();
+/// assert_eq!(lm.lookuper().with_str("A B"), Some(1));
+/// ```
+pub struct GramsReaderLoader<R> {
+    reader: Arc<Mutex<R>>,
+    start: u64,
+    name: String,
+    options: ParserOptions,
+}
+
+impl<R> GramsReaderLoader<R>
+where
+    R: Read + Seek + Send + 'static,
+{
+    /// Creates [`GramsReaderLoader`] reading from the current position of `reader`.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let start = reader.stream_position()?;
+        Ok(Self {
+            reader: Arc::new(Mutex::new(reader)),
+            start,
+            name: "reader".to_string(),
+            options: ParserOptions::default(),
+        })
+    }
+
+    /// Sets the name of the input used to report errors (default: `reader`).
+    pub fn name<S>(mut self, name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.options.separators = separators;
+        self
+    }
+
+    /// Sets the format of the bytes instead of detecting it.
+    pub const fn format(mut self, fmt: GramsFileFormats) -> Self {
+        self.options.fmt = Some(fmt);
+        self
+    }
+
+    /// Sets whether the input has no header (default: detected from the first line).
+    pub const fn headerless(mut self, headerless: bool) -> Self {
+        self.options.headerless = headerless;
+        self
+    }
+}
+
+impl<R> GramsLoader<Box<dyn Read + Send>> for GramsReaderLoader<R>
+where
+    R: Read + Seek + Send + 'static,
+{
+    fn parser(&self) -> Result<GramsParser<Box<dyn Read + Send>>> {
+        self.reader
+            .lock()
+            .map_err(|_| anyhow!("The reader of {} is poisoned.", self.name))?
+            .seek(SeekFrom::Start(self.start))?;
+        let reader = SharedReader {
+            reader: Arc::clone(&self.reader),
+        };
+        self.options.parser(reader, self.name.clone())
+    }
+}
+
+/// Reader sharing the underlying reader of [`GramsReaderLoader`].
+struct SharedReader<R> {
+    reader: Arc<Mutex<R>>,
+}
+
+impl<R> Read for SharedReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader
+            .lock()
+            .map_err(|_| std::io::Error::other("poisoned reader"))?
+            .read(buf)
+    }
+}
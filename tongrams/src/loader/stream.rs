@@ -0,0 +1,260 @@
+use std::io::{Read, Stdin, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use tempfile::NamedTempFile;
+
+use crate::loader::{GramsFileFormats, GramsLoader, ParserOptions};
+use crate::parser::GramsParser;
+use crate::Separators;
+
+/// Loader for *N*-gram counts in a one-shot stream (e.g., the standard input or a pipe),
+/// which cannot be re-opened.
+///
+/// The first parser reads the stream directly, while the bytes read are spooled
+/// to a temporary file unless disabled with [`GramsStreamLoader::spool`].
+/// The later parsers read the spooled bytes, where the rest of the stream is spooled in advance,
+/// so that builders requiring multiple passes (e.g., [`TrieProbLmBuilder`](crate::trie_prob_lm::TrieProbLmBuilder)) can be used.
+/// A later parser cannot be created while the first parser is reading the stream,
+/// i.e., until the first parser reaches the end of the stream or is dropped.
+/// Since [`TrieCountLmBuilder`](crate::trie_count_lm::TrieCountLmBuilder) reads each input only once,
+/// the spooling can be disabled for it.
+///
+/// The format is detected from the magic bytes unless it is given with [`GramsStreamLoader::format`].
+pub struct GramsStreamLoader<R> {
+    state: Arc<Mutex<StreamState<R>>>,
+    name: String,
+    options: ParserOptions,
+    spool: bool,
+    tmp_dir: Option<PathBuf>,
+}
+
+/// State of the stream shared with the first parser.
+struct StreamState<R> {
+    // The stream not read to the end yet
+    stream: Option<R>,
+    // The temporary file of the bytes read from the stream
+    spool: Option<NamedTempFile>,
+    opened: bool,
+    // Whether the first parser is alive
+    active: bool,
+}
+
+impl GramsStreamLoader<Stdin> {
+    /// Creates [`GramsStreamLoader`] reading from the standard input.
+    pub fn stdin() -> Self {
+        Self::new(std::io::stdin()).name("stdin")
+    }
+}
+
+impl<R> GramsStreamLoader<R>
+where
+    R: Read + Send + 'static,
+{
+    /// Creates [`GramsStreamLoader`] reading from `stream`.
+    pub fn new(stream: R) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(StreamState {
+                stream: Some(stream),
+                spool: None,
+                opened: false,
+                active: false,
+            })),
+            name: "stream".to_string(),
+            options: ParserOptions::default(),
+            spool: true,
+            tmp_dir: None,
+        }
+    }
+
+    /// Sets the name of the input used to report errors (default: `stream`).
+    pub fn name<S>(mut self, name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets whether to spool the stream to a temporary file for multiple passes (default: `true`).
+    ///
+    /// If disabled, only one parser can be created.
+    pub const fn spool(mut self, spool: bool) -> Self {
+        self.spool = spool;
+        self
+    }
+
+    /// Sets the directory in which the temporary file is placed
+    /// (default: the temporary directory of the system).
+    pub fn tmp_dir<P>(mut self, tmp_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.tmp_dir = Some(tmp_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.options.separators = separators;
+        self
+    }
+
+    /// Sets the format of the stream instead of detecting it.
+    pub const fn format(mut self, fmt: GramsFileFormats) -> Self {
+        self.options.fmt = Some(fmt);
+        self
+    }
+
+    /// Sets whether the stream has no header (default: detected from the first line).
+    pub const fn headerless(mut self, headerless: bool) -> Self {
+        self.options.headerless = headerless;
+        self
+    }
+}
+
+impl<R> GramsLoader<Box<dyn Read + Send>> for GramsStreamLoader<R>
+where
+    R: Read + Send + 'static,
+{
+    fn parser(&self) -> Result<GramsParser<Box<dyn Read + Send>>> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| anyhow!("The stream of {} is poisoned.", self.name))?;
+        if !state.opened {
+            state.opened = true;
+            state.active = true;
+            if self.spool {
+                state.spool = Some(match &self.tmp_dir {
+                    Some(tmp_dir) => NamedTempFile::new_in(tmp_dir)?,
+                    None => NamedTempFile::new()?,
+                });
+            }
+            drop(state);
+            let reader = TeeReader {
+                state: Arc::clone(&self.state),
+            };
+            return self.options.parser(reader, self.name.clone());
+        }
+
+        if state.active && state.stream.is_some() {
+            return Err(anyhow!(
+                "The stream of {} is being read by another parser.",
+                self.name
+            ));
+        }
+        let stream = state.stream.take();
+        let spool = state.spool.as_mut().ok_or_else(|| {
+            anyhow!(
+                "The stream of {} can be read only once without spooling.",
+                self.name
+            )
+        })?;
+        if let Some(mut stream) = stream {
+            std::io::copy(&mut stream, spool)?;
+        }
+        spool.flush()?;
+        let file = spool.reopen()?;
+        drop(state);
+        self.options.parser(file, self.name.clone())
+    }
+}
+
+/// Reader of the stream of [`GramsStreamLoader`], spooling the bytes read if enabled.
+struct TeeReader<R> {
+    state: Arc<Mutex<StreamState<R>>>,
+}
+
+impl<R> Read for TeeReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| std::io::Error::other("poisoned stream"))?;
+        // The stream is taken if the later parsers have spooled the rest of it.
+        let n = match state.stream.as_mut() {
+            Some(stream) => stream.read(buf)?,
+            None => return Ok(0),
+        };
+        if n == 0 && !buf.is_empty() {
+            state.stream = None;
+        }
+        if let Some(spool) = state.spool.as_mut() {
+            spool.write_all(&buf[..n])?;
+        }
+        drop(state);
+        Ok(n)
+    }
+}
+
+impl<R> Drop for TeeReader<R> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.active = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use crate::CountRecord;
+
+    const GRAMS_1: &str = "3
+A\t3
+B\t2
+C\t1
+";
+
+    fn read_records<R>(loader: &GramsStreamLoader<R>) -> Result<Vec<CountRecord>>
+    where
+        R: Read + Send + 'static,
+    {
+        loader.parser()?.into_count_records().collect()
+    }
+
+    #[test]
+    fn test_spool() {
+        let loader = GramsStreamLoader::new(GRAMS_1.as_bytes());
+        let records = read_records(&loader).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(read_records(&loader).unwrap(), records);
+        assert_eq!(read_records(&loader).unwrap(), records);
+
+        // The rest of the stream is spooled if the first parser is dropped before the end,
+        // but no parser can be created while the first parser is reading the stream.
+        let loader = GramsStreamLoader::new(GRAMS_1.as_bytes()).format(GramsFileFormats::Plain);
+        let mut parser = loader.parser().unwrap();
+        parser.next_count_record().unwrap().unwrap();
+        assert!(loader.parser().is_err());
+        drop(parser);
+        assert_eq!(read_records(&loader).unwrap(), records);
+
+        let loader = GramsStreamLoader::new(GRAMS_1.as_bytes()).spool(false);
+        assert_eq!(read_records(&loader).unwrap(), records);
+        assert!(loader.parser().is_err());
+    }
+
+    #[test]
+    fn test_compressed() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(GRAMS_1.as_bytes()).unwrap();
+        let bytes = encoder.finish().unwrap();
+
+        let expected = read_records(&GramsStreamLoader::new(GRAMS_1.as_bytes())).unwrap();
+        let loader = GramsStreamLoader::new(Cursor::new(bytes));
+        assert_eq!(read_records(&loader).unwrap(), expected);
+        assert_eq!(read_records(&loader).unwrap(), expected);
+    }
+}
@@ -0,0 +1,45 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use xz2::read::XzDecoder;
+
+use crate::loader::GramsLoader;
+use crate::parser::GramsParser;
+use crate::Separators;
+
+/// Loader for a xz compressed *N*-gram counts file, which can consist of multiple streams.
+pub struct GramsXzFileLoader {
+    filepath: PathBuf,
+    separators: Separators,
+}
+
+impl GramsXzFileLoader {
+    pub fn new<P>(filepath: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            filepath: PathBuf::from(filepath.as_ref()),
+            separators: Separators::default(),
+        }
+    }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.separators = separators;
+        self
+    }
+}
+
+impl GramsLoader<XzDecoder<File>> for GramsXzFileLoader {
+    fn parser(&self) -> Result<GramsParser<XzDecoder<File>>> {
+        let reader = XzDecoder::new_multi_decoder(File::open(&self.filepath)?);
+        GramsParser::with_separators(
+            BufReader::new(reader),
+            self.filepath.display().to_string(),
+            self.separators,
+        )
+    }
+}
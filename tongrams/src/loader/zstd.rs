@@ -0,0 +1,45 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use zstd::stream::read::Decoder;
+
+use crate::loader::GramsLoader;
+use crate::parser::GramsParser;
+use crate::Separators;
+
+/// Loader for a zstd compressed *N*-gram counts file.
+pub struct GramsZstdFileLoader {
+    filepath: PathBuf,
+    separators: Separators,
+}
+
+impl GramsZstdFileLoader {
+    pub fn new<P>(filepath: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            filepath: PathBuf::from(filepath.as_ref()),
+            separators: Separators::default(),
+        }
+    }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.separators = separators;
+        self
+    }
+}
+
+impl GramsLoader<Decoder<'static, BufReader<File>>> for GramsZstdFileLoader {
+    fn parser(&self) -> Result<GramsParser<Decoder<'static, BufReader<File>>>> {
+        let reader = Decoder::new(File::open(&self.filepath)?)?;
+        GramsParser::with_separators(
+            BufReader::new(reader),
+            self.filepath.display().to_string(),
+            self.separators,
+        )
+    }
+}
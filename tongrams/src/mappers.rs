@@ -3,9 +3,13 @@ use crate::Gram;
 
 use crate::MAX_ORDER;
 
+/// Mapper from tokens to their ids.
+/// Grams of up to [`MAX_ORDER`] tokens are mapped into a fixed-size buffer,
+/// and longer grams are mapped into a heap-allocated buffer.
 #[derive(Default)]
 pub struct SortedArrayMapper {
     mapped: [usize; MAX_ORDER],
+    spilled: Vec<usize>,
     len: usize,
 }
 
@@ -17,18 +21,7 @@ impl SortedArrayMapper {
         V: Vocabulary,
     {
         let tokens = gram.split_to_tokens();
-        if MAX_ORDER < tokens.len() {
-            return false;
-        }
-        for (i, &w) in tokens.iter().enumerate() {
-            if let Some(mapped_id) = vocab.get(w) {
-                self.mapped[i] = mapped_id;
-            } else {
-                return false;
-            }
-        }
-        self.len = tokens.len();
-        true
+        self.map_tokens(tokens.iter().copied(), tokens.len(), vocab)
     }
 
     #[inline(always)]
@@ -37,24 +30,44 @@ impl SortedArrayMapper {
     where
         V: Vocabulary,
     {
-        if MAX_ORDER < tokens.len() {
-            return false;
+        self.map_tokens(
+            tokens.iter().map(|&w| Gram::from_str(w)),
+            tokens.len(),
+            vocab,
+        )
+    }
+
+    #[inline(always)]
+    pub fn get(&self) -> &[usize] {
+        if self.len <= MAX_ORDER {
+            &self.mapped[..self.len]
+        } else {
+            &self.spilled[..self.len]
         }
-        for (i, &w) in tokens.iter().enumerate() {
-            if let Some(mapped_id) = vocab.get(Gram::from_str(w)) {
-                self.mapped[i] = mapped_id;
+    }
+
+    #[inline(always)]
+    fn map_tokens<'a, I, V>(&mut self, tokens: I, len: usize, vocab: &V) -> bool
+    where
+        I: Iterator<Item = Gram<'a>>,
+        V: Vocabulary,
+    {
+        let mapped = if len <= MAX_ORDER {
+            &mut self.mapped[..len]
+        } else {
+            self.spilled.resize(len, 0);
+            &mut self.spilled[..len]
+        };
+        for (x, w) in mapped.iter_mut().zip(tokens) {
+            if let Some(mapped_id) = vocab.get(w) {
+                *x = mapped_id;
             } else {
                 return false;
             }
         }
-        self.len = tokens.len();
+        self.len = len;
         true
     }
-
-    #[inline(always)]
-    pub fn get(&self) -> &[usize] {
-        &self.mapped[..self.len]
-    }
 }
 
 #[cfg(test)]
@@ -80,4 +93,17 @@ mod tests {
         assert_eq!(mapper.get(), &[0, 2, 1][..]);
         assert_eq!(mapper.from_tokens(&["E", "B"], &vocab), false);
     }
+
+    #[test]
+    fn test_long() {
+        let grams = vec![Gram::from_str("A"), Gram::from_str("B")];
+        let vocab = SimpleVocabulary::build(&grams).unwrap();
+        let mut mapper = SortedArrayMapper::default();
+
+        assert!(mapper.from_gram(Gram::from_str("A B A B A B A B A B"), &vocab));
+        assert_eq!(mapper.get(), &[0, 1, 0, 1, 0, 1, 0, 1, 0, 1][..]);
+        assert!(mapper.from_gram(Gram::from_str("B A"), &vocab));
+        assert_eq!(mapper.get(), &[1, 0][..]);
+        assert!(!mapper.from_gram(Gram::from_str("A B A B A B A B A E"), &vocab));
+    }
 }
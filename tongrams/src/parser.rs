@@ -1,15 +1,39 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use std::io::{BufRead, BufReader, Read};
 
-use crate::GRAM_COUNT_SEPARATOR;
-use crate::{CountRecord, ProbRecord};
+use crate::validation::{ValidationError, ValidationErrorKind};
+use crate::{CountRecord, CountRecordRef, Gram, ProbRecord, ProbRecordRef, Separators};
 
 /// Parser for a *N*-gram file of counts or probs/backoff-weights.
 /// TODO: Add example of the format.
+///
+/// The first line of the file is the header with the number of grams,
+/// which can be omitted (e.g., in outputs of Hadoop or Spark jobs).
+/// A file without the header is parsed until EOF.
+///
+/// The separators of tokens and fields can be specified with [`Separators`].
+///
+/// Malformed rows and a mismatch between the header and the number of rows
+/// are reported as [`ValidationError`]s.
+/// Non-blank rows beyond the number in the header are also reported,
+/// so a stale header should be fixed or the file parsed with [`GramsParser::without_header`].
+///
+/// The records can be parsed without allocations by [`GramsParser::next_count_record_ref`]
+/// and [`GramsParser::next_prob_record_ref`], which reuse one line buffer
+/// and return the records borrowing it.
 pub struct GramsParser<R> {
     reader: BufReader<R>,
-    num_grams: usize,
+    name: String,
+    separators: Separators,
+    num_grams: Option<usize>,
     num_parsed: usize,
+    // The buffer of the current row, reused for every row
+    line: Vec<u8>,
+    // Whether the line buffer holds the first row read in detecting the header
+    pending: bool,
+    // The number of lines preceding the input, added to the line numbers reported
+    line_offset: usize,
+    finished: bool,
 }
 
 impl<R> GramsParser<R>
@@ -17,72 +41,157 @@ where
     R: Read,
 {
     /// Creates a new [`GramsParser`] from `BufReader` of a *N*-gram file.
-    pub fn new(mut reader: BufReader<R>) -> Result<Self> {
-        let num_grams = {
-            let mut header = String::new();
-            reader.read_line(&mut header)?;
-            header.trim().parse()?
+    pub fn new(reader: BufReader<R>) -> Result<Self> {
+        Self::with_name(reader, "input")
+    }
+
+    /// Creates a new [`GramsParser`] from `BufReader` of a *N*-gram file,
+    /// whose name (e.g., the file path) is used to report errors.
+    ///
+    /// The header is detected from the first line, which is the header
+    /// if it consists of only a number and is the first row if it has a separator of the count.
+    pub fn with_name<S>(reader: BufReader<R>, name: S) -> Result<Self>
+    where
+        S: Into<String>,
+    {
+        Self::with_separators(reader, name, Separators::default())
+    }
+
+    /// Creates a new [`GramsParser`] from `BufReader` of a *N*-gram file,
+    /// in which tokens and fields are separated by `separators`.
+    ///
+    /// The header is detected in the same manner as [`GramsParser::with_name`].
+    pub fn with_separators<S>(
+        mut reader: BufReader<R>,
+        name: S,
+        separators: Separators,
+    ) -> Result<Self>
+    where
+        S: Into<String>,
+    {
+        let name = name.into();
+        let mut line = vec![];
+        reader.read_until(b'\n', &mut line)?;
+        let (num_grams, pending) = if line.contains(&separators.field) {
+            (None, true)
+        } else {
+            let header = std::str::from_utf8(&line).ok();
+            let num_grams = header.and_then(|h| h.trim().parse().ok()).ok_or_else(|| {
+                let kind = ValidationErrorKind::Malformed;
+                ValidationError::new(&name, 1, String::from_utf8_lossy(trim_end(&line)), kind)
+            })?;
+            (Some(num_grams), false)
         };
         Ok(Self {
             reader,
+            name,
+            separators,
             num_grams,
             num_parsed: 0,
+            line,
+            pending,
+            line_offset: 0,
+            finished: false,
         })
     }
 
-    /// Gets the number of input grams.
+    /// Creates a new [`GramsParser`] from `BufReader` of a *N*-gram file without the header,
+    /// whose name (e.g., the file path) is used to report errors.
+    pub fn without_header<S>(reader: BufReader<R>, name: S, separators: Separators) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            reader,
+            name: name.into(),
+            separators,
+            num_grams: None,
+            num_parsed: 0,
+            line: vec![],
+            pending: false,
+            line_offset: 0,
+            finished: false,
+        }
+    }
+
+    /// Sets the number of lines preceding the input in the file (default: `0`),
+    /// which are added to the line numbers reported, e.g., for a section of a file.
+    pub const fn line_offset(mut self, line_offset: usize) -> Self {
+        self.line_offset = line_offset;
+        self
+    }
+
+    /// Gets the number of input grams in the header, or `0` if the file has no header.
     #[allow(clippy::missing_const_for_fn)]
     pub fn num_grams(&self) -> usize {
-        self.num_grams
+        self.num_grams.unwrap_or(0)
     }
 
-    /// Parses a next [`CountRecord`].
-    pub fn next_count_record(&mut self) -> Option<Result<CountRecord>> {
-        self.num_parsed += 1;
-        if self.num_parsed > self.num_grams {
-            return None;
+    /// Checks if the file has the header of the number of grams.
+    pub const fn has_header(&self) -> bool {
+        self.num_grams.is_some()
+    }
+
+    /// Gets the line number (1-origin) of the first row, i.e., `2` with the header and `1` otherwise,
+    /// plus the line offset.
+    pub const fn first_row_line(&self) -> usize {
+        if self.num_grams.is_some() {
+            self.line_offset + 2
+        } else {
+            self.line_offset + 1
         }
+    }
 
-        let mut buffer = String::new();
-        self.reader.read_line(&mut buffer).ok()?;
+    /// Gets the name of the input.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 
-        let items: Vec<&str> = buffer
-            .trim_end()
-            .split(GRAM_COUNT_SEPARATOR as char)
-            .collect();
-        if items.len() != 2 {
-            return Some(Err(anyhow!("Invalid line, {:?}", items)));
-        }
+    /// Gets the separators of tokens and fields.
+    pub const fn separators(&self) -> Separators {
+        self.separators
+    }
 
-        let gram = items[0].to_string();
-        items[1].parse().map_or_else(
-            |_| Some(Err(anyhow!("Parse error, {:?}", items))),
-            |count| Some(Ok(CountRecord::new(gram, count))),
-        )
+    /// Converts into the iterator over [`CountRecord`]s.
+    pub const fn into_count_records(self) -> CountRecords<R> {
+        CountRecords { parser: self }
     }
 
-    /// Parses a next [`ProbRecord`].
-    pub fn next_prob_record(&mut self) -> Option<Result<ProbRecord>> {
-        self.num_parsed += 1;
-        if self.num_parsed > self.num_grams {
-            return None;
+    /// Parses a next [`CountRecord`].
+    pub fn next_count_record(&mut self) -> Option<Result<CountRecord>> {
+        self.next_count_record_ref()
+            .map(|rec| rec.map(CountRecord::from))
+    }
+
+    /// Parses a next [`CountRecordRef`] borrowing the line buffer of the parser,
+    /// which is overwritten by the next call.
+    pub fn next_count_record_ref(&mut self) -> Option<Result<CountRecordRef<'_>>> {
+        if let Err(e) = self.next_row()? {
+            return Some(Err(e));
+        }
+        match split_count_row(trim_end(&self.line), self.separators.field) {
+            Some((len, count)) => {
+                let gram = Gram::new(&self.line[..len]).with_separator(self.separators.token);
+                Some(Ok(CountRecordRef::new(gram, count)))
+            }
+            None => Some(Err(self.malformed())),
         }
+    }
 
-        let mut buffer = String::new();
-        self.reader.read_line(&mut buffer).ok()?;
+    /// Parses a next [`ProbRecord`].
+    pub fn next_prob_record(&mut self) -> Option<Result<ProbRecord>> {
+        self.next_prob_record_ref()
+            .map(|rec| rec.map(ProbRecord::from))
+    }
 
-        let items: Vec<&str> = buffer
-            .trim_end()
-            .split(GRAM_COUNT_SEPARATOR as char)
-            .collect();
-        if items.len() != 2 && items.len() != 3 {
-            return Some(Err(anyhow!("Invalid line, {:?}", items)));
+    /// Parses a next [`ProbRecordRef`] borrowing the line buffer of the parser,
+    /// which is overwritten by the next call.
+    pub fn next_prob_record_ref(&mut self) -> Option<Result<ProbRecordRef<'_>>> {
+        if let Err(e) = self.next_row()? {
+            return Some(Err(e));
         }
-
-        let gram = items[0].to_string();
-        items[1].parse().map_or_else(
-            |_| Some(Err(anyhow!("Parse error, {:?}", items))),
-            |prob| {
+        match split_prob_row(trim_end(&self.line), self.separators.field) {
+            Some((len, prob, backoff)) => {
                 let prob = if prob > 0.0 {
                     eprintln!(
                         "Warning: positive log10 probability detected. This will be mapped to 0."
@@ -91,16 +200,185 @@ where
                 } else {
                     prob
                 };
-                if let Some(x) = items.get(2) {
-                    x.parse().map_or_else(
-                        |_| Some(Err(anyhow!("Parse error, {:?}", items))),
-                        |backoff| Some(Ok(ProbRecord::new(gram, prob, backoff))),
-                    )
-                } else {
-                    Some(Ok(ProbRecord::new(gram, prob, 0.0)))
-                }
-            },
-        )
+                let gram = Gram::new(&self.line[..len]).with_separator(self.separators.token);
+                Some(Ok(ProbRecordRef::new(gram, prob, backoff)))
+            }
+            None => Some(Err(self.malformed())),
+        }
+    }
+
+    /// Gets the number of the remaining rows if the file has the header, or `0` otherwise.
+    pub(crate) fn num_remaining(&self) -> usize {
+        self.num_grams
+            .map_or(0, |num_grams| num_grams.saturating_sub(self.num_parsed))
+    }
+
+    /// Reads the next row into the line buffer,
+    /// checking the number of rows against the header if exists.
+    fn next_row(&mut self) -> Option<Result<()>> {
+        if self.finished {
+            return None;
+        }
+        if self.pending {
+            self.pending = false;
+            self.num_parsed += 1;
+            return Some(Ok(()));
+        }
+        if Some(self.num_parsed) == self.num_grams {
+            self.finished = true;
+            return self.check_trailing_rows().err().map(Err);
+        }
+
+        self.line.clear();
+        match self.reader.read_until(b'\n', &mut self.line) {
+            Ok(0) => {
+                self.finished = true;
+                let expected = self.num_grams?;
+                let kind = ValidationErrorKind::HeaderMismatch {
+                    expected,
+                    actual: self.num_parsed,
+                };
+                Some(Err(ValidationError::new(
+                    &self.name,
+                    self.line_offset + self.num_parsed + 2,
+                    "",
+                    kind,
+                )
+                .into()))
+            }
+            Ok(_) => {
+                self.num_parsed += 1;
+                Some(Ok(()))
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e.into()))
+            }
+        }
+    }
+
+    /// Checks that no row follows the number of grams in the header, ignoring blank lines.
+    fn check_trailing_rows(&mut self) -> Result<()> {
+        let num_grams = self.num_grams.unwrap_or(self.num_parsed);
+        let mut first_row = None;
+        let mut num_rows = 0;
+        let mut buffer = String::new();
+        loop {
+            buffer.clear();
+            if self.reader.read_line(&mut buffer)? == 0 {
+                break;
+            }
+            if !buffer.trim().is_empty() {
+                first_row.get_or_insert_with(|| buffer.trim_end().to_string());
+                num_rows += 1;
+            }
+        }
+        first_row.map_or(Ok(()), |row| {
+            let kind = ValidationErrorKind::HeaderMismatch {
+                expected: num_grams,
+                actual: num_grams + num_rows,
+            };
+            let line = self.line_offset + num_grams + 2;
+            Err(ValidationError::new(&self.name, line, row, kind).into())
+        })
+    }
+
+    /// Reports the row in the line buffer as malformed.
+    fn malformed(&self) -> anyhow::Error {
+        let kind = ValidationErrorKind::Malformed;
+        let line = self.first_row_line() + self.num_parsed - 1;
+        let row = String::from_utf8_lossy(trim_end(&self.line));
+        ValidationError::new(&self.name, line, row, kind).into()
+    }
+}
+
+/// Splits a row of a gram and its count, returning the length of the gram and the count.
+fn split_count_row(row: &[u8], separator: u8) -> Option<(usize, usize)> {
+    let mut fields = row.split(|&b| b == separator);
+    let gram = parse_gram(fields.next()?)?;
+    let count = parse_bytes(fields.next()?)?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some((gram.len(), count))
+}
+
+/// Splits a row of a gram, its probability, and optionally its backoff weight,
+/// returning the length of the gram, the probability, and the backoff weight (or `0`).
+fn split_prob_row(row: &[u8], separator: u8) -> Option<(usize, f32, f32)> {
+    let mut fields = row.split(|&b| b == separator);
+    let gram = parse_gram(fields.next()?)?;
+    let prob = parse_bytes(fields.next()?)?;
+    let backoff = match fields.next() {
+        Some(field) => parse_bytes(field)?,
+        None => 0.0,
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+    Some((gram.len(), prob, backoff))
+}
+
+/// Checks that the gram is valid UTF-8.
+fn parse_gram(gram: &[u8]) -> Option<&[u8]> {
+    std::str::from_utf8(gram).ok().map(str::as_bytes)
+}
+
+/// Trims the trailing whitespaces (including the line break) of a row.
+fn trim_end(row: &[u8]) -> &[u8] {
+    let len = row
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(0, |pos| pos + 1);
+    &row[..len]
+}
+
+/// Parses a number from the bytes of a field without allocations.
+fn parse_bytes<T>(field: &[u8]) -> Option<T>
+where
+    T: std::str::FromStr,
+{
+    std::str::from_utf8(field).ok()?.parse().ok()
+}
+
+/// Iterator over [`CountRecord`]s, created by [`GramsParser::into_count_records`].
+pub struct CountRecords<R> {
+    parser: GramsParser<R>,
+}
+
+impl<R> CountRecords<R>
+where
+    R: Read,
+{
+    /// Gets the name of the input.
+    pub fn name(&self) -> &str {
+        self.parser.name()
+    }
+
+    /// Gets the line number (1-origin) of the first record.
+    pub const fn first_row_line(&self) -> usize {
+        self.parser.first_row_line()
+    }
+
+    /// Gets the separators of tokens and fields.
+    pub const fn separators(&self) -> Separators {
+        self.parser.separators()
+    }
+}
+
+impl<R> Iterator for CountRecords<R>
+where
+    R: Read,
+{
+    type Item = Result<CountRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.parser.next_count_record()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.parser.num_remaining();
+        (remaining, self.parser.num_grams.map(|_| remaining))
     }
 }
 
@@ -170,6 +448,134 @@ D D D\t1
         assert!(gp.next_count_record().is_none());
     }
 
+    fn validation_error(gp: &mut GramsParser<&[u8]>) -> ValidationError {
+        loop {
+            match gp.next_count_record().unwrap() {
+                Ok(_) => continue,
+                Err(e) => return e.downcast_ref::<ValidationError>().unwrap().clone(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_header_mismatch() {
+        let mut gp = GramsParser::new(BufReader::new(&b"3\nA\t1\nB\t1\n"[..])).unwrap();
+        let e = validation_error(&mut gp);
+        assert_eq!(e.line, 4);
+        assert_eq!(
+            e.kind,
+            ValidationErrorKind::HeaderMismatch {
+                expected: 3,
+                actual: 2
+            }
+        );
+        assert!(gp.next_count_record().is_none());
+
+        let mut gp = GramsParser::new(BufReader::new(&b"1\nA\t1\nB\t1\n\n"[..])).unwrap();
+        let e = validation_error(&mut gp);
+        assert_eq!(e.line, 3);
+        assert_eq!(e.gram, "B\t1");
+        assert_eq!(
+            e.kind,
+            ValidationErrorKind::HeaderMismatch {
+                expected: 1,
+                actual: 2
+            }
+        );
+        assert!(gp.next_count_record().is_none());
+    }
+
+    #[test]
+    fn test_malformed() {
+        for text in ["2\nA\t1\nB\n", "2\nA\t1\nB\tx\n"] {
+            let mut gp =
+                GramsParser::with_name(BufReader::new(text.as_bytes()), "2-grams").unwrap();
+            let e = validation_error(&mut gp);
+            assert_eq!(e.name, "2-grams");
+            assert_eq!(e.line, 3);
+            assert_eq!(e.kind, ValidationErrorKind::Malformed);
+        }
+        assert!(GramsParser::new(BufReader::new(&b"x\nA\t1\n"[..])).is_err());
+    }
+
+    #[test]
+    fn test_headerless() {
+        let text = COUNT_GRAMS_2.split_once('\n').unwrap().1;
+        let expected = [("A A", 1), ("A C", 2), ("B B", 3), ("D C", 1)];
+        let parsers = [
+            GramsParser::new(BufReader::new(text.as_bytes())).unwrap(),
+            GramsParser::without_header(
+                BufReader::new(text.as_bytes()),
+                "input",
+                Separators::default(),
+            ),
+        ];
+        for gp in parsers {
+            assert!(!gp.has_header());
+            assert_eq!(gp.num_grams(), 0);
+            assert_eq!(gp.first_row_line(), 1);
+            let records: Vec<CountRecord> = gp.into_count_records().map(|r| r.unwrap()).collect();
+            let expected: Vec<CountRecord> = expected
+                .iter()
+                .map(|&(gram, count)| CountRecord::new(gram.to_string(), count))
+                .collect();
+            assert_eq!(records, expected);
+        }
+
+        let mut gp = GramsParser::new(BufReader::new(&b"A\t1\nB\n"[..])).unwrap();
+        let e = validation_error(&mut gp);
+        assert_eq!(e.line, 2);
+        assert_eq!(e.kind, ValidationErrorKind::Malformed);
+    }
+
+    #[test]
+    fn test_separators() {
+        let separators = Separators::new(b'_', b',');
+        for text in ["2\nA_B,1\nA C_D,2\n", "A_B,1\nA C_D,2\n"] {
+            let gp =
+                GramsParser::with_separators(BufReader::new(text.as_bytes()), "input", separators)
+                    .unwrap();
+            assert_eq!(gp.separators(), separators);
+            let records: Vec<CountRecord> = gp.into_count_records().map(|r| r.unwrap()).collect();
+            assert_eq!(
+                records,
+                vec![
+                    CountRecord::new("A_B".to_string(), 1),
+                    CountRecord::new("A C_D".to_string(), 2)
+                ]
+            );
+        }
+        let mut gp =
+            GramsParser::with_separators(BufReader::new(&b"1\nA\t1\n"[..]), "input", separators)
+                .unwrap();
+        assert_eq!(
+            validation_error(&mut gp).kind,
+            ValidationErrorKind::Malformed
+        );
+    }
+
+    #[test]
+    fn test_record_ref() {
+        let mut gp = GramsParser::new(BufReader::new(COUNT_GRAMS_2.as_bytes())).unwrap();
+        let mut records = vec![];
+        while let Some(rec) = gp.next_count_record_ref() {
+            records.push(CountRecord::from(rec.unwrap()));
+        }
+        let gp = GramsParser::new(BufReader::new(COUNT_GRAMS_2.as_bytes())).unwrap();
+        let expected: Vec<CountRecord> = gp.into_count_records().map(|r| r.unwrap()).collect();
+        assert_eq!(records, expected);
+
+        let mut gp = GramsParser::new(BufReader::new(PROB_GRAMS_2.as_bytes())).unwrap();
+        let rec = gp.next_prob_record_ref().unwrap().unwrap();
+        assert_eq!(rec.gram(), Gram::from_str("A A"));
+        assert_eq!((rec.prob(), rec.backoff()), (-1.43, -0.33));
+
+        // Grams should be valid UTF-8.
+        let mut gp = GramsParser::new(BufReader::new(&b"2\nA\t1\n\xff\t1\n"[..])).unwrap();
+        let e = validation_error(&mut gp);
+        assert_eq!((e.line, e.kind), (3, ValidationErrorKind::Malformed));
+    }
+
     const PROB_GRAMS_1: &'static str = "4
 A\t-1.83\t-0.74
 B\t-2.01\t-0.69
@@ -0,0 +1,124 @@
+//! Progress reports of the construction.
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The number of records processed between two reports in a phase.
+const REPORT_INTERVAL: usize = 1 << 16;
+
+/// Phase of the construction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildPhase {
+    /// Building the vocabulary from the unigrams.
+    Vocabulary,
+    /// Ranking the counts of the grams of *N* = `n`.
+    Counts(usize),
+    /// Scanning the grams of *N* = `n` and building the level.
+    Level(usize),
+}
+
+impl fmt::Display for BuildPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Vocabulary => write!(f, "vocabulary"),
+            Self::Counts(n) => write!(f, "counts of {}-grams", n),
+            Self::Level(n) => write!(f, "{}-grams", n),
+        }
+    }
+}
+
+/// Progress of a phase, reported periodically and at the end of the phase.
+#[derive(Clone, Debug)]
+pub struct BuildProgress {
+    /// The current phase.
+    pub phase: BuildPhase,
+    /// The number of records processed in the phase.
+    pub num_records: usize,
+    /// The elapsed time since the phase started.
+    pub elapsed: Duration,
+    /// The number of bytes of the data structures built before the phase.
+    pub memory: usize,
+    /// Whether the phase is finished.
+    pub finished: bool,
+}
+
+impl BuildProgress {
+    /// Gets the number of records processed per second.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.num_records as f64 / secs
+        }
+    }
+}
+
+/// Callback receiving [`BuildProgress`]es, which can be called from multiple threads.
+pub type ProgressCallback = Arc<dyn Fn(&BuildProgress) + Send + Sync>;
+
+/// Reporter of the progress to an optional callback.
+#[derive(Clone, Default)]
+pub(crate) struct ProgressReporter {
+    callback: Option<ProgressCallback>,
+}
+
+impl ProgressReporter {
+    pub fn new(callback: ProgressCallback) -> Self {
+        Self {
+            callback: Some(callback),
+        }
+    }
+
+    /// Starts a phase, given the memory of the data structures built so far.
+    pub fn start(&self, phase: BuildPhase, memory: usize) -> PhaseProgress<'_> {
+        PhaseProgress {
+            callback: self.callback.as_deref(),
+            phase,
+            memory,
+            start: Instant::now(),
+            num_records: 0,
+        }
+    }
+
+    pub const fn is_enabled(&self) -> bool {
+        self.callback.is_some()
+    }
+}
+
+/// Progress of a running phase.
+pub(crate) struct PhaseProgress<'a> {
+    callback: Option<&'a (dyn Fn(&BuildProgress) + Send + Sync)>,
+    phase: BuildPhase,
+    memory: usize,
+    start: Instant,
+    num_records: usize,
+}
+
+impl PhaseProgress<'_> {
+    /// Counts a processed record, reporting the progress at intervals.
+    #[inline(always)]
+    pub fn tick(&mut self) {
+        self.num_records += 1;
+        if self.num_records & (REPORT_INTERVAL - 1) == 0 {
+            self.report(false);
+        }
+    }
+
+    /// Reports the end of the phase.
+    pub fn finish(self) {
+        self.report(true);
+    }
+
+    fn report(&self, finished: bool) {
+        if let Some(callback) = self.callback {
+            callback(&BuildProgress {
+                phase: self.phase,
+                num_records: self.num_records,
+                elapsed: self.start.elapsed(),
+                memory: self.memory,
+                finished,
+            });
+        }
+    }
+}
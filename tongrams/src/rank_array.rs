@@ -1,18 +1,38 @@
+pub mod compact;
 pub mod ef;
+pub mod gamma;
 pub mod simple;
 
 use std::io::{Read, Write};
 
 use anyhow::Result;
 
+pub use crate::rank_array::compact::CompactRankArray;
 pub use crate::rank_array::ef::EliasFanoRankArray;
+pub use crate::rank_array::gamma::GammaRankArray;
 pub use crate::rank_array::simple::SimpleRankArray;
 
 /// Trait for a data structure for storing count ranks.
 pub trait RankArray {
+    /// Name of the data structure, which identifies it in serialized indexes.
+    const TYPE_NAME: &'static str;
+
     /// Builds a [`RankArray`] from a sequence of count ranks.
     fn build(count_ranks: Vec<usize>) -> Self;
 
+    /// Builds a [`RankArray`] from a sequence of count ranks that can be scanned multiple times.
+    ///
+    /// This is used to construct the index in external memory.
+    /// The default implementation collects the sequence in memory.
+    fn build_from_iter<I>(count_ranks: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = Result<usize>> + Clone,
+        Self: Sized,
+    {
+        let count_ranks = count_ranks.into_iter().collect::<Result<_>>()?;
+        Ok(Self::build(count_ranks))
+    }
+
     /// Serializes the data structure into the writer.
     fn serialize_into<W: Write>(&self, writer: W) -> Result<usize>;
 
@@ -41,6 +61,8 @@ pub trait RankArray {
 mod tests {
     use super::*;
 
+    use sucds::Searial;
+
     fn test_basic<A: RankArray>() {
         let count_ranks = vec![3, 0, 0, 0, 1, 2, 0, 1, 1];
         let ra = A::build(count_ranks.clone());
@@ -49,8 +71,69 @@ mod tests {
         }
     }
 
+    fn test_long<A: RankArray>() {
+        let count_ranks: Vec<usize> = (0..1000).map(|i| (i * i) % 7 + (i % 100) / 99).collect();
+        let ra = A::build(count_ranks.clone());
+        assert_eq!(ra.len(), count_ranks.len());
+        for (i, &x) in count_ranks.iter().enumerate() {
+            assert_eq!(ra.get(i), x);
+        }
+
+        let mut data = vec![];
+        ra.serialize_into(&mut data).unwrap();
+        assert_eq!(data.len(), ra.size_in_bytes());
+        let other = A::deserialize_from(&data[..]).unwrap();
+        for (i, &x) in count_ranks.iter().enumerate() {
+            assert_eq!(other.get(i), x);
+        }
+    }
+
+    fn test_from_iter<A: RankArray>() {
+        let count_ranks = vec![3, 0, 0, 0, 1, 2, 0, 1, 1];
+        let ra = A::build_from_iter(count_ranks.iter().map(|&x| Ok(x))).unwrap();
+        assert_eq!(ra.len(), count_ranks.len());
+        for (i, &x) in count_ranks.iter().enumerate() {
+            assert_eq!(ra.get(i), x);
+        }
+    }
+
     #[test]
     fn test_basic_simple() {
         test_basic::<SimpleRankArray>();
+        test_from_iter::<SimpleRankArray>();
+    }
+
+    #[test]
+    fn test_basic_ef() {
+        test_basic::<EliasFanoRankArray>();
+        test_long::<EliasFanoRankArray>();
+        test_from_iter::<EliasFanoRankArray>();
+        let count_ranks: Vec<usize> = vec![];
+        let ra = EliasFanoRankArray::build_from_iter(count_ranks.iter().map(|&x| Ok(x))).unwrap();
+        assert!(ra.is_empty());
+
+        // The streamed array is serialized in the layout of `sucds::EliasFanoList`.
+        let count_ranks: Vec<usize> = (0..1000).map(|i| (i * i) % 7).collect();
+        let ra = EliasFanoRankArray::build_from_iter(count_ranks.iter().map(|&x| Ok(x))).unwrap();
+        let (mut data, mut expected) = (vec![], vec![]);
+        ra.serialize_into(&mut data).unwrap();
+        sucds::EliasFanoList::from_slice(&count_ranks)
+            .unwrap()
+            .serialize_into(&mut expected)
+            .unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_basic_compact() {
+        test_basic::<CompactRankArray>();
+        test_long::<CompactRankArray>();
+        test_from_iter::<CompactRankArray>();
+    }
+
+    #[test]
+    fn test_basic_gamma() {
+        test_basic::<GammaRankArray>();
+        test_long::<GammaRankArray>();
     }
 }
@@ -0,0 +1,79 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+use sucds::Searial;
+
+use crate::rank_array::RankArray;
+
+/// Bit-packed implementation of [`RankArray`] with `sucds::CompactVector`.
+#[derive(Default, Debug)]
+pub struct CompactRankArray {
+    count_ranks: sucds::CompactVector,
+}
+
+impl RankArray for CompactRankArray {
+    const TYPE_NAME: &'static str = "CompactRankArray";
+
+    fn build(count_ranks: Vec<usize>) -> Self {
+        let max_rank = count_ranks.iter().copied().max().unwrap_or(0);
+        let mut cv = sucds::CompactVector::with_capacity(
+            count_ranks.len(),
+            sucds::util::needed_bits(max_rank),
+        );
+        count_ranks.iter().for_each(|&x| cv.push(x));
+        Self { count_ranks: cv }
+    }
+
+    fn build_from_iter<I>(count_ranks: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = Result<usize>> + Clone,
+    {
+        let (mut len, mut max_rank) = (0, 0);
+        for x in count_ranks.clone() {
+            len += 1;
+            max_rank = std::cmp::max(max_rank, x?);
+        }
+        let mut cv = sucds::CompactVector::with_capacity(len, sucds::util::needed_bits(max_rank));
+        for x in count_ranks {
+            cv.push(x?);
+        }
+        Ok(Self { count_ranks: cv })
+    }
+
+    fn serialize_into<W>(&self, mut writer: W) -> Result<usize>
+    where
+        W: Write,
+    {
+        self.count_ranks.serialize_into(&mut writer)
+    }
+
+    fn deserialize_from<R>(mut reader: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let count_ranks = sucds::CompactVector::deserialize_from(&mut reader)?;
+        Ok(Self { count_ranks })
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.count_ranks.size_in_bytes()
+    }
+
+    fn memory_statistics(&self) -> serde_json::Value {
+        let count_ranks = self.count_ranks.size_in_bytes();
+        serde_json::json!({ "count_ranks": count_ranks })
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize) -> usize {
+        self.count_ranks.get(i)
+    }
+
+    fn len(&self) -> usize {
+        self.count_ranks.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -12,10 +12,43 @@ pub struct EliasFanoRankArray {
 }
 
 impl RankArray for EliasFanoRankArray {
+    const TYPE_NAME: &'static str = "EliasFanoRankArray";
+
     fn build(count_ranks: Vec<usize>) -> Self {
-        Self {
-            count_ranks: sucds::EliasFanoList::from_slice(&count_ranks).unwrap(),
+        Self::build_from_iter(count_ranks.into_iter().map(Ok)).unwrap()
+    }
+
+    fn build_from_iter<I>(count_ranks: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = Result<usize>> + Clone,
+    {
+        // The first scan computes the universe of the prefix sums.
+        let (mut num_ranks, mut universe) = (0, 0);
+        for x in count_ranks.clone() {
+            num_ranks += 1;
+            universe += x?;
+        }
+        if num_ranks == 0 {
+            return Ok(Self::default());
         }
+        // `sucds::EliasFanoList` stores the prefix sums in `sucds::EliasFano`
+        // and is serialized as it, but can be built only from a slice,
+        // so the prefix sums are streamed into the builder and the list is read from the bytes.
+        let bytes = {
+            let mut efb = sucds::EliasFanoBuilder::new(universe + 1, num_ranks)?;
+            let mut prefix_sum = 0;
+            for x in count_ranks {
+                prefix_sum += x?;
+                efb.push(prefix_sum)?;
+            }
+            let ef = efb.build();
+            let mut bytes = Vec::with_capacity(ef.size_in_bytes());
+            ef.serialize_into(&mut bytes)?;
+            bytes
+        };
+        Ok(Self {
+            count_ranks: sucds::EliasFanoList::deserialize_from(&bytes[..])?,
+        })
     }
 
     fn serialize_into<W>(&self, mut writer: W) -> Result<usize>
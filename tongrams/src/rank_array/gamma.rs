@@ -0,0 +1,130 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+use sucds::{util::IntIO, Searial};
+
+use crate::rank_array::RankArray;
+
+/// The number of codes between two sampled positions.
+const SAMPLE_RATE: usize = 32;
+
+/// Entropy-oriented implementation of [`RankArray`] with Elias-gamma codes.
+///
+/// Since count ranks are assigned in decreasing order of frequency,
+/// most of them are very small and take only a few bits in Elias-gamma codes.
+/// The starting position of every [`SAMPLE_RATE`]-th code is sampled for random access.
+#[derive(Default, Debug)]
+pub struct GammaRankArray {
+    codes: sucds::BitVector,
+    samples: sucds::CompactVector,
+    len: usize,
+}
+
+impl RankArray for GammaRankArray {
+    const TYPE_NAME: &'static str = "GammaRankArray";
+
+    fn build(count_ranks: Vec<usize>) -> Self {
+        let mut codes = sucds::BitVector::new();
+        let mut positions = Vec::with_capacity(count_ranks.len() / SAMPLE_RATE + 1);
+
+        for (i, &x) in count_ranks.iter().enumerate() {
+            if i % SAMPLE_RATE == 0 {
+                positions.push(codes.len());
+            }
+            // NOTE: Elias-gamma codes cannot represent zero.
+            let x = x + 1;
+            let nbits = sucds::util::needed_bits(x);
+            for _ in 1..nbits {
+                codes.push_bit(false);
+            }
+            codes.push_bit(true);
+            if nbits > 1 {
+                codes.push_bits(x & ((1 << (nbits - 1)) - 1), nbits - 1);
+            }
+        }
+
+        let max_position = positions.last().copied().unwrap_or(0);
+        let mut samples = sucds::CompactVector::with_capacity(
+            positions.len(),
+            sucds::util::needed_bits(max_position),
+        );
+        positions.iter().for_each(|&x| samples.push(x));
+
+        Self {
+            codes,
+            samples,
+            len: count_ranks.len(),
+        }
+    }
+
+    fn serialize_into<W>(&self, mut writer: W) -> Result<usize>
+    where
+        W: Write,
+    {
+        Ok(self.codes.serialize_into(&mut writer)?
+            + self.samples.serialize_into(&mut writer)?
+            + self.len.serialize_into(&mut writer)?)
+    }
+
+    fn deserialize_from<R>(mut reader: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let codes = sucds::BitVector::deserialize_from(&mut reader)?;
+        let samples = sucds::CompactVector::deserialize_from(&mut reader)?;
+        let len = usize::deserialize_from(&mut reader)?;
+        Ok(Self {
+            codes,
+            samples,
+            len,
+        })
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.codes.size_in_bytes() + self.samples.size_in_bytes() + usize::size_in_bytes()
+    }
+
+    fn memory_statistics(&self) -> serde_json::Value {
+        let codes = self.codes.size_in_bytes();
+        let samples = self.samples.size_in_bytes();
+        serde_json::json!({
+            "codes": codes,
+            "samples": samples,
+        })
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize) -> usize {
+        let mut pos = self.samples.get(i / SAMPLE_RATE);
+        for _ in 0..i % SAMPLE_RATE {
+            pos = self.decode(pos).1;
+        }
+        self.decode(pos).0
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl GammaRankArray {
+    /// Decodes the code starting at `pos`, returning the value and the next position.
+    #[inline(always)]
+    fn decode(&self, mut pos: usize) -> (usize, usize) {
+        let mut nbits = 1;
+        while !self.codes.get_bit(pos) {
+            nbits += 1;
+            pos += 1;
+        }
+        pos += 1;
+        if nbits == 1 {
+            return (0, pos);
+        }
+        let x = (1 << (nbits - 1)) | self.codes.get_bits(pos, nbits - 1);
+        (x - 1, pos + nbits - 1)
+    }
+}
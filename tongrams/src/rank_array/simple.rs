@@ -12,6 +12,8 @@ pub struct SimpleRankArray {
 }
 
 impl RankArray for SimpleRankArray {
+    const TYPE_NAME: &'static str = "SimpleRankArray";
+
     fn build(count_ranks: Vec<usize>) -> Self {
         Self { count_ranks }
     }
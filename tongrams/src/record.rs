@@ -29,6 +29,46 @@ impl CountRecord {
     }
 }
 
+/// Handler of a pair of a gram and its count, borrowing the gram from a buffer,
+/// e.g., the line buffer of [`GramsParser`](crate::GramsParser).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CountRecordRef<'a> {
+    gram: Gram<'a>,
+    count: usize,
+}
+
+impl<'a> CountRecordRef<'a> {
+    /// Creates a [`CountRecordRef`].
+    #[inline]
+    pub const fn new(gram: Gram<'a>, count: usize) -> Self {
+        Self { gram, count }
+    }
+
+    /// Gets the [`Gram`].
+    #[inline]
+    pub const fn gram(&self) -> Gram<'a> {
+        self.gram
+    }
+
+    /// Gets the count.
+    #[inline]
+    pub const fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl<'a> From<&'a CountRecord> for CountRecordRef<'a> {
+    fn from(rec: &'a CountRecord) -> Self {
+        Self::new(rec.gram(), rec.count())
+    }
+}
+
+impl From<CountRecordRef<'_>> for CountRecord {
+    fn from(rec: CountRecordRef<'_>) -> Self {
+        Self::new(rec.gram().to_string(), rec.count())
+    }
+}
+
 /// Handler of a tuple of a gram, its probability, and its backoff weight.
 #[derive(Clone, Debug)]
 pub struct ProbRecord {
@@ -76,3 +116,48 @@ impl PartialEq for ProbRecord {
 }
 
 impl Eq for ProbRecord {}
+
+/// Handler of a tuple of a gram, its probability, and its backoff weight,
+/// borrowing the gram from a buffer, e.g., the line buffer of [`GramsParser`](crate::GramsParser).
+#[derive(Clone, Copy, Debug)]
+pub struct ProbRecordRef<'a> {
+    gram: Gram<'a>,
+    prob: f32,
+    backoff: f32,
+}
+
+impl<'a> ProbRecordRef<'a> {
+    /// Creates a [`ProbRecordRef`].
+    #[inline]
+    pub const fn new(gram: Gram<'a>, prob: f32, backoff: f32) -> Self {
+        Self {
+            gram,
+            prob,
+            backoff,
+        }
+    }
+
+    /// Gets the [`Gram`].
+    #[inline]
+    pub const fn gram(&self) -> Gram<'a> {
+        self.gram
+    }
+
+    /// Gets the probability.
+    #[inline]
+    pub const fn prob(&self) -> f32 {
+        self.prob
+    }
+
+    /// Gets the backoff weight.
+    #[inline]
+    pub const fn backoff(&self) -> f32 {
+        self.backoff
+    }
+}
+
+impl From<ProbRecordRef<'_>> for ProbRecord {
+    fn from(rec: ProbRecordRef<'_>) -> Self {
+        Self::new(rec.gram().to_string(), rec.prob(), rec.backoff())
+    }
+}
@@ -0,0 +1,150 @@
+//! Sorting of grams in the order of token ids, as required to build the tries.
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use extsort::{ExternalSorter, Sortable};
+
+/// Record whose gram is mapped to token ids, identified by the position in the input
+/// and carrying its count through the sorting.
+///
+/// Records are ordered by the token ids and then by the positions.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct MappedRecord {
+    /// Token ids of the gram.
+    pub mapped_ids: Vec<usize>,
+    /// Position of the record in the input.
+    pub record_id: usize,
+    /// Count of the gram.
+    pub count: usize,
+}
+
+impl Sortable for MappedRecord {
+    fn encode<W: Write>(&self, writer: &mut W) {
+        writer
+            .write_u64::<LittleEndian>(self.mapped_ids.len() as u64)
+            .unwrap();
+        for &x in &self.mapped_ids {
+            writer.write_u64::<LittleEndian>(x as u64).unwrap();
+        }
+        writer
+            .write_u64::<LittleEndian>(self.record_id as u64)
+            .unwrap();
+        writer.write_u64::<LittleEndian>(self.count as u64).unwrap();
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Option<Self> {
+        let len = reader.read_u64::<LittleEndian>().ok()? as usize;
+        let mut mapped_ids = Vec::with_capacity(len);
+        for _ in 0..len {
+            mapped_ids.push(reader.read_u64::<LittleEndian>().ok()? as usize);
+        }
+        let record_id = reader.read_u64::<LittleEndian>().ok()? as usize;
+        let count = reader.read_u64::<LittleEndian>().ok()? as usize;
+        Some(Self {
+            mapped_ids,
+            record_id,
+            count,
+        })
+    }
+}
+
+/// Sorter of [`MappedRecord`]s, which sorts them in memory up to a threshold
+/// and externally with temporary files beyond it.
+#[derive(Clone, Debug, Default)]
+pub struct RecordsSorter {
+    max_in_memory: Option<usize>,
+    tmp_dir: Option<PathBuf>,
+}
+
+impl RecordsSorter {
+    /// Creates [`RecordsSorter`] that sorts all the records in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of records sorted in memory (default: unlimited),
+    /// beyond which sorted runs are written to temporary files and merged.
+    pub const fn max_in_memory(mut self, max_in_memory: usize) -> Self {
+        self.max_in_memory = Some(max_in_memory);
+        self
+    }
+
+    /// Sets the maximum number of records sorted in memory from an approximate memory budget
+    /// in bytes, where each record has `num_ids` token ids.
+    pub const fn memory_budget(self, memory_budget: usize, num_ids: usize) -> Self {
+        let record_bytes =
+            std::mem::size_of::<MappedRecord>() + (num_ids + 1) * std::mem::size_of::<usize>();
+        self.max_in_memory(memory_budget / record_bytes)
+    }
+
+    /// Sets the directory in which temporary files are placed
+    /// (default: the temporary directory of the system).
+    pub fn tmp_dir<P>(mut self, tmp_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.tmp_dir = Some(tmp_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sorts the records.
+    pub fn sort<I>(&self, records: I) -> Result<Box<dyn Iterator<Item = MappedRecord>>>
+    where
+        I: Iterator<Item = MappedRecord>,
+    {
+        match self.max_in_memory {
+            None => {
+                let mut records: Vec<_> = records.collect();
+                records.sort_unstable();
+                Ok(Box::new(records.into_iter()))
+            }
+            Some(max_in_memory) => {
+                let sorter = ExternalSorter::new().with_segment_size(max_in_memory.max(1));
+                let sorter = match &self.tmp_dir {
+                    Some(tmp_dir) => sorter.with_sort_dir(tmp_dir.clone()),
+                    None => sorter,
+                };
+                Ok(Box::new(sorter.sort(records)?))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort() {
+        let ids = [[2, 0], [0, 1], [1, 2], [0, 0], [2, 0]];
+        let records = || {
+            ids.iter().enumerate().map(|(record_id, ids)| MappedRecord {
+                mapped_ids: ids.to_vec(),
+                record_id,
+                count: record_id + 1,
+            })
+        };
+        for sorter in [RecordsSorter::new(), RecordsSorter::new().max_in_memory(2)] {
+            let sorted: Vec<(usize, usize)> = sorter
+                .sort(records())
+                .unwrap()
+                .map(|r| (r.record_id, r.count))
+                .collect();
+            assert_eq!(sorted, vec![(3, 4), (1, 2), (2, 3), (0, 1), (4, 5)]);
+        }
+    }
+
+    #[test]
+    fn test_encode_long_gram() {
+        let record = MappedRecord {
+            mapped_ids: (0..300).collect(),
+            record_id: 1,
+            count: 2,
+        };
+        let mut bytes = vec![];
+        record.encode(&mut bytes);
+        assert_eq!(MappedRecord::decode(&mut &bytes[..]), Some(record));
+    }
+}
@@ -10,9 +10,28 @@ pub use crate::trie_array::simple::SimpleTrieArray;
 
 /// Trait for a data structure for sorted arrays of each trie level.
 pub trait TrieArray {
+    /// Name of the data structure, which identifies it in serialized indexes.
+    const TYPE_NAME: &'static str;
+
     /// Builds a [`TrieArray`] from sequences of token ids and pointers.
     fn build(token_ids: Vec<usize>, pointers: Vec<usize>) -> Self;
 
+    /// Builds a [`TrieArray`] from sequences of token ids and pointers
+    /// that can be scanned multiple times.
+    ///
+    /// This is used to construct the index in external memory.
+    /// The default implementation collects the sequences in memory.
+    fn build_from_iters<I, P>(token_ids: I, pointers: P) -> Result<Self>
+    where
+        I: IntoIterator<Item = Result<usize>> + Clone,
+        P: IntoIterator<Item = Result<usize>> + Clone,
+        Self: Sized,
+    {
+        let token_ids = token_ids.into_iter().collect::<Result<_>>()?;
+        let pointers = pointers.into_iter().collect::<Result<_>>()?;
+        Ok(Self::build(token_ids, pointers))
+    }
+
     /// Serializes the data structure into the writer.
     fn serialize_into<W: Write>(&self, writer: W) -> Result<usize>;
 
@@ -87,15 +106,37 @@ mod tests {
         assert_eq!(ta.num_pointers(), 10);
     }
 
+    fn test_from_iters<T: TrieArray>() {
+        let token_ids = [2, 2, 3, 3, 1, 2, 3];
+        let pointers = [0, 1, 1, 3, 4, 4, 4, 4, 6, 7];
+        let ta = T::build_from_iters(
+            token_ids.iter().map(|&x| Ok(x)),
+            pointers.iter().map(|&x| Ok(x)),
+        )
+        .unwrap();
+
+        for (i, &x) in token_ids.iter().enumerate() {
+            assert_eq!(ta.token_id(i), x);
+        }
+        for i in 0..pointers.len() - 1 {
+            assert_eq!(ta.range(i), (pointers[i], pointers[i + 1]));
+        }
+        assert_eq!(ta.find_token(7, 1), Some(4));
+        assert_eq!(ta.num_tokens(), 7);
+        assert_eq!(ta.num_pointers(), 10);
+    }
+
     #[test]
     fn test_basic_simple() {
         test_basic_1::<SimpleTrieArray>();
         test_basic_2::<SimpleTrieArray>();
+        test_from_iters::<SimpleTrieArray>();
     }
 
     #[test]
     fn test_basic_ef() {
         test_basic_1::<EliasFanoTrieArray>();
         test_basic_2::<EliasFanoTrieArray>();
+        test_from_iters::<EliasFanoTrieArray>();
     }
 }
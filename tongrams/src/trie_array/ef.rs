@@ -1,7 +1,7 @@
 use std::cmp::Ordering;
 use std::io::{Read, Write};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use sucds::Searial;
 
 use crate::trie_array::TrieArray;
@@ -14,6 +14,8 @@ pub struct EliasFanoTrieArray {
 }
 
 impl TrieArray for EliasFanoTrieArray {
+    const TYPE_NAME: &'static str = "EliasFanoTrieArray";
+
     fn build(token_ids: Vec<usize>, pointers: Vec<usize>) -> Self {
         if token_ids.is_empty() {
             return Self::default();
@@ -28,6 +30,40 @@ impl TrieArray for EliasFanoTrieArray {
         }
     }
 
+    fn build_from_iters<I, P>(token_ids: I, pointers: P) -> Result<Self>
+    where
+        I: IntoIterator<Item = Result<usize>> + Clone,
+        P: IntoIterator<Item = Result<usize>> + Clone,
+    {
+        // The first scan computes the universe of the shifted token ids.
+        let (mut num_tokens, mut universe) = (0, 0);
+        Self::scan_token_sequence(token_ids.clone(), pointers.clone(), |x| {
+            num_tokens += 1;
+            universe = x;
+            Ok(())
+        })?;
+        if num_tokens == 0 {
+            return Ok(Self::default());
+        }
+        let mut token_efb = sucds::EliasFanoBuilder::new(universe + 1, num_tokens)?;
+        Self::scan_token_sequence(token_ids, pointers.clone(), |x| token_efb.push(x))?;
+
+        let (mut num_pointers, mut last_pointer) = (0, 0);
+        for x in pointers.clone() {
+            num_pointers += 1;
+            last_pointer = x?;
+        }
+        let mut pointer_efb = sucds::EliasFanoBuilder::new(last_pointer + 1, num_pointers)?;
+        for x in pointers {
+            pointer_efb.push(x?)?;
+        }
+
+        Ok(Self {
+            token_ids: token_efb.build(),
+            pointers: pointer_efb.build().enable_rank(),
+        })
+    }
+
     fn serialize_into<W>(&self, mut writer: W) -> Result<usize>
     where
         W: Write,
@@ -130,6 +166,36 @@ impl EliasFanoTrieArray {
         token_efb.build()
     }
 
+    /// Scans the token ids shifted in the same manner as [`Self::build_token_sequence`].
+    fn scan_token_sequence<I, P, F>(token_ids: I, pointers: P, mut f: F) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<usize>>,
+        P: IntoIterator<Item = Result<usize>>,
+        F: FnMut(usize) -> Result<()>,
+    {
+        let mut token_ids = token_ids.into_iter();
+        let mut pointers = pointers.into_iter();
+        let mut b = match pointers.next() {
+            Some(b) => b?,
+            None => return Ok(()),
+        };
+        let (mut sampled_id, mut last_id) = (0, 0);
+        for e in pointers {
+            let e = e?;
+            debug_assert!(b <= e);
+            for _ in b..e {
+                let token_id = token_ids
+                    .next()
+                    .ok_or_else(|| anyhow!("token_ids is shorter than pointers."))??;
+                last_id = token_id + sampled_id;
+                f(last_id)?;
+            }
+            sampled_id = last_id;
+            b = e;
+        }
+        Ok(())
+    }
+
     fn build_pointers(pointers: Vec<usize>) -> sucds::EliasFano {
         let mut pointer_efb =
             sucds::EliasFanoBuilder::new(pointers.last().unwrap() + 1, pointers.len()).unwrap();
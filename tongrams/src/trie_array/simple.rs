@@ -13,6 +13,8 @@ pub struct SimpleTrieArray {
 }
 
 impl TrieArray for SimpleTrieArray {
+    const TYPE_NAME: &'static str = "SimpleTrieArray";
+
     fn build(token_ids: Vec<usize>, pointers: Vec<usize>) -> Self {
         Self {
             token_ids,
@@ -1,28 +1,66 @@
+mod any;
 mod builder;
+mod dumper;
+mod grams_iter;
+mod header;
 mod lookuper;
+mod merger;
+mod overlay;
+mod pruner;
 
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use sucds::{util::IntIO, Searial};
 
-use crate::loader::{GramsFileLoader, GramsGzFileLoader, GramsLoader, GramsTextLoader};
+use crate::loader::{
+    GramsAutoFileLoader, GramsFileLoader, GramsGzFileLoader, GramsLoader, GramsMultiOrderLoader,
+    GramsTextLoader,
+};
 use crate::rank_array::RankArray;
 use crate::trie_array::TrieArray;
+use crate::trie_count_lm::header::Checksum;
 use crate::vocabulary::Vocabulary;
-use crate::GramsFileFormats;
-
-pub use crate::trie_count_lm::builder::TrieCountLmBuilder;
+use crate::{FinishWrite, Gram, GramsFileFormats, TOKEN_SEPARATOR};
+
+pub use crate::trie_count_lm::any::{AnyTrieCountLm, CountLm, CountLmLookuper};
+pub use crate::trie_count_lm::builder::{
+    GramsSource, TrieCountLmBuilder, TrieCountLmRecordsBuilder,
+};
+pub(crate) use crate::trie_count_lm::builder::{LevelsBuilder, RecordsLocation};
+pub use crate::trie_count_lm::grams_iter::TrieCountLmGramsIter;
+pub use crate::trie_count_lm::header::{IndexHeader, INDEX_MAGIC, INDEX_VERSION};
 pub use crate::trie_count_lm::lookuper::TrieCountLmLookuper;
+pub use crate::trie_count_lm::merger::TrieCountLmMerger;
+pub use crate::trie_count_lm::overlay::TrieCountLmOverlay;
 
 /// Elias-Fano trie for indexing *N*-grams with their frequency counts.
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct TrieCountLm<T, V, A> {
     vocab: V,
     arrays: Vec<T>,
     count_ranks: Vec<A>,
     counts: Vec<sucds::CompactVector>,
+    // The separator of tokens, used to split grams given as strings
+    separator: u8,
+}
+
+impl<T, V, A> Default for TrieCountLm<T, V, A>
+where
+    T: Default,
+    V: Default,
+    A: Default,
+{
+    fn default() -> Self {
+        Self {
+            vocab: V::default(),
+            arrays: vec![],
+            count_ranks: vec![],
+            counts: vec![],
+            separator: TOKEN_SEPARATOR,
+        }
+    }
 }
 
 impl<T, V, A> TrieCountLm<T, V, A>
@@ -33,6 +71,8 @@ where
 {
     /// Builds the index from *N*-gram counts files.
     ///
+    /// Unsorted files can be indexed with [`TrieCountLmBuilder::sort_grams`].
+    ///
     /// # Arguments
     ///
     ///  - `filepaths`: Paths of *N*-gram counts files that should be sorted by *N* = 1, 2, ...
@@ -44,9 +84,37 @@ where
         match fmt {
             GramsFileFormats::Plain => Self::from_plain_files(filepaths),
             GramsFileFormats::Gzip => Self::from_gz_files(filepaths),
+            _ => Self::from_file_loaders(filepaths, |filepath| {
+                GramsAutoFileLoader::new(filepath).format(fmt)
+            }),
         }
     }
 
+    /// Builds the index from *N*-gram counts files,
+    /// detecting the format of each file from the magic bytes.
+    ///
+    /// # Arguments
+    ///
+    ///  - `filepaths`: Paths of *N*-gram counts files that should be sorted by *N* = 1, 2, ...
+    pub fn from_detected_files<P>(filepaths: &[P]) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_file_loaders(filepaths, |filepath| GramsAutoFileLoader::new(filepath))
+    }
+
+    /// Builds the index from a *N*-gram counts file containing all the orders,
+    /// detecting the format from the magic bytes.
+    ///
+    /// See [`GramsMultiOrderLoader`] for the format.
+    pub fn from_multi_order_file<P>(filepath: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let loaders = GramsMultiOrderLoader::new(filepath).loaders()?;
+        TrieCountLmBuilder::new(loaders)?.build()
+    }
+
     /// Builds the index from *N*-gram counts files in a plain text format.
     ///
     /// # Arguments
@@ -56,12 +124,7 @@ where
     where
         P: AsRef<Path>,
     {
-        let mut loaders = Vec::with_capacity(filepaths.len());
-        for filepath in filepaths {
-            let loader: Box<dyn GramsLoader<_>> = Box::new(GramsFileLoader::new(filepath));
-            loaders.push(loader);
-        }
-        TrieCountLmBuilder::new(loaders)?.build()
+        Self::from_file_loaders(filepaths, |filepath| GramsFileLoader::new(filepath))
     }
 
     /// Builds the index from *N*-gram counts files in a gzip compressed format.
@@ -73,11 +136,21 @@ where
     where
         P: AsRef<Path>,
     {
-        let mut loaders = Vec::with_capacity(filepaths.len());
-        for filepath in filepaths {
-            let loader: Box<dyn GramsLoader<_>> = Box::new(GramsGzFileLoader::new(filepath));
-            loaders.push(loader);
-        }
+        Self::from_file_loaders(filepaths, |filepath| GramsGzFileLoader::new(filepath))
+    }
+
+    /// Builds the index from *N*-gram counts files with the loaders made by `make_loader`.
+    fn from_file_loaders<P, R, L, F>(filepaths: &[P], make_loader: F) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        R: Read,
+        L: GramsLoader<R> + 'static,
+        F: Fn(&P) -> L,
+    {
+        let loaders = filepaths
+            .iter()
+            .map(|filepath| Box::new(make_loader(filepath)) as Box<dyn GramsLoader<R>>)
+            .collect();
         TrieCountLmBuilder::new(loaders)?.build()
     }
 
@@ -92,8 +165,137 @@ where
         TrieCountLmBuilder::new(loaders)?.build()
     }
 
-    /// Serializes the index into the writer.
+    /// Merges indexes into a new one, without going back to *N*-gram counts files.
+    ///
+    /// The vocabularies are unioned, and the counts of identical grams are summed.
+    /// The grams of each order are re-sorted in memory,
+    /// for which [`TrieCountLmMerger`] can set a memory budget.
+    /// The vocabulary should support the reverse lookup of tokens
+    /// (e.g., [`ReversibleDoubleArrayVocabulary`](crate::ReversibleDoubleArrayVocabulary)).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tongrams::SimpleTrieCountLm;
+    ///
+    /// let lm1 = SimpleTrieCountLm::from_texts(vec!["2\nA\t3\nB\t1\n", "1\nA B\t1\n"]).unwrap();
+    /// let lm2 = SimpleTrieCountLm::from_texts(vec!["2\nC\t2\nA\t1\n", "2\nC A\t2\nA C\t1\n"]).unwrap();
+    /// let merged = SimpleTrieCountLm::merge(&[&lm1, &lm2]).unwrap();
+    ///
+    /// let mut lookuper = merged.lookuper();
+    /// assert_eq!(lookuper.with_str("A"), Some(4));
+    /// assert_eq!(lookuper.with_str("C"), Some(2));
+    /// assert_eq!(lookuper.with_str("A B"), Some(1));
+    /// assert_eq!(lookuper.with_str("A C"), Some(1));
+    /// assert_eq!(lookuper.with_str("C A"), Some(2));
+    /// ```
+    pub fn merge(lms: &[&Self]) -> Result<Self> {
+        TrieCountLmMerger::new().merge(lms)
+    }
+
+    /// Writes the grams of *N* = `n` with their counts into `writer` as a *N*-gram counts file
+    /// with the header, in the sorted order of the trie.
+    ///
+    /// The tokens are separated by [`TrieCountLm::token_separator`].
+    /// The vocabulary should support the reverse lookup of tokens,
+    /// which is checked before writing (see [`TrieCountLm::check_reverse_lookup`]).
+    ///
+    /// # Panics
+    ///
+    /// It panics if `n` is not in `1..=self.num_orders()`.
+    pub fn dump_order<W>(&self, n: usize, writer: W) -> Result<()>
+    where
+        W: FinishWrite,
+    {
+        dumper::dump_order(self, n, writer)
+    }
+
+    /// Writes the grams of all the orders into *N*-gram counts files named `<order>-grams.sorted`
+    /// (with the extension of `fmt`) in `dirpath`, returning the paths of the files.
+    ///
+    /// The files are sorted as the input of [`TrieCountLm::from_files`],
+    /// from which the identical index is built
+    /// (with the same token separator if it is not the default one).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tongrams::{GramsFileFormats, ReversibleTrieCountLm};
+    ///
+    /// let lm = ReversibleTrieCountLm::from_texts(vec!["2\nA\t3\nB\t1\n", "1\nA B\t1\n"]).unwrap();
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let filepaths = lm.dump_files(dir.path(), GramsFileFormats::Gzip).unwrap();
+    ///
+    /// let other = ReversibleTrieCountLm::from_files(&filepaths, GramsFileFormats::Gzip).unwrap();
+    /// assert_eq!(other.lookuper().with_str("A B"), Some(1));
+    /// ```
+    pub fn dump_files<P>(&self, dirpath: P, fmt: GramsFileFormats) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        dumper::dump_files(self, dirpath, fmt)
+    }
+
+    /// Serializes the index into the writer, preceded by [`IndexHeader`].
+    ///
+    /// The data is scanned twice, first to compute the checksum in the header.
     pub fn serialize_into<W>(&self, mut writer: W) -> Result<usize>
+    where
+        W: Write,
+    {
+        let mut checksum = Checksum::new(std::io::sink());
+        self.serialize_body(&mut checksum)?;
+        let mem = self
+            .header(checksum.checksum())
+            .serialize_into(&mut writer)?;
+        Ok(mem + self.serialize_body(&mut writer)?)
+    }
+
+    /// Deserializes the index from the reader, verifying [`IndexHeader`].
+    ///
+    /// An error is returned if the data is not an index of the supported version,
+    /// consists of other component types than `T`, `V`, and `A`, or is corrupted.
+    pub fn deserialize_from<R>(mut reader: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let header = IndexHeader::deserialize_from(&mut reader)?;
+        Self::deserialize_with_header(&header, reader)
+    }
+
+    /// Deserializes the index following `header`, which has been read from the reader.
+    pub(crate) fn deserialize_with_header<R>(header: &IndexHeader, reader: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        header.check_types::<T, V, A>()?;
+        let mut reader = Checksum::new(reader);
+        let lm = Self::deserialize_body(&mut reader, header.num_orders())
+            .context("The index is truncated or corrupted.")?;
+        if reader.checksum() != header.checksum() {
+            return Err(anyhow!(
+                "The checksum of the index mismatches the header, i.e., the index is corrupted."
+            ));
+        }
+        let num_grams: Vec<usize> = lm.count_ranks.iter().map(|a| a.len()).collect();
+        if num_grams != header.num_grams() {
+            return Err(anyhow!(
+                "The numbers of grams of the index {:?} mismatch the header {:?}.",
+                num_grams,
+                header.num_grams()
+            ));
+        }
+        Ok(lm)
+    }
+
+    /// Gets [`IndexHeader`] of the index with the checksum of the data.
+    fn header(&self, checksum: u64) -> IndexHeader {
+        let num_grams = self.count_ranks.iter().map(|a| a.len()).collect();
+        IndexHeader::new::<T, V, A>(num_grams, checksum)
+    }
+
+    /// Serializes the data following the header.
+    fn serialize_body<W>(&self, mut writer: W) -> Result<usize>
     where
         W: Write,
     {
@@ -115,17 +317,20 @@ where
         for count in &self.counts {
             mem += count.serialize_into(&mut writer)?;
         }
+        // separator
+        writer.write_all(&[self.separator])?;
+        mem += 1;
         Ok(mem)
     }
 
-    /// Deserializes the index from the reader.
-    pub fn deserialize_from<R>(mut reader: R) -> Result<Self>
+    /// Deserializes the data following the header with `num_orders` orders.
+    fn deserialize_body<R>(mut reader: R, num_orders: usize) -> Result<Self>
     where
         R: Read,
     {
         let vocab = V::deserialize_from(&mut reader)?;
         let arrays = {
-            let len = usize::deserialize_from(&mut reader)?;
+            let len = Self::deserialize_num_components(&mut reader, num_orders)?;
             let mut arrays = Vec::with_capacity(len);
             for _ in 0..len {
                 arrays.push(T::deserialize_from(&mut reader)?);
@@ -133,7 +338,7 @@ where
             arrays
         };
         let count_ranks = {
-            let len = usize::deserialize_from(&mut reader)?;
+            let len = Self::deserialize_num_components(&mut reader, num_orders)?;
             let mut count_ranks = Vec::with_capacity(len);
             for _ in 0..len {
                 count_ranks.push(A::deserialize_from(&mut reader)?);
@@ -141,24 +346,44 @@ where
             count_ranks
         };
         let counts = {
-            let len = usize::deserialize_from(&mut reader)?;
+            let len = Self::deserialize_num_components(&mut reader, num_orders)?;
             let mut counts = Vec::with_capacity(len);
             for _ in 0..len {
                 counts.push(sucds::CompactVector::deserialize_from(&mut reader)?);
             }
             counts
         };
+        let mut separator = [0];
+        reader.read_exact(&mut separator)?;
         Ok(Self {
             vocab,
             arrays,
             count_ranks,
             counts,
+            separator: separator[0],
         })
     }
 
+    /// Deserializes the number of components per order, which is checked against `num_orders`
+    /// before allocating them, since the data has not been verified with the checksum yet.
+    fn deserialize_num_components<R>(reader: R, num_orders: usize) -> Result<usize>
+    where
+        R: Read,
+    {
+        let len = usize::deserialize_from(reader)?;
+        if len > num_orders {
+            return Err(anyhow!(
+                "The number of components {} exceeds the number of orders {} in the header.",
+                len,
+                num_orders
+            ));
+        }
+        Ok(len)
+    }
+
     /// Gets the number of bytes to serialize the index.
     pub fn size_in_bytes(&self) -> usize {
-        let mut mem = 0;
+        let mut mem = self.header(0).size_in_bytes();
         // vocab
         mem += self.vocab.size_in_bytes();
         // arrays
@@ -176,6 +401,8 @@ where
         for count in &self.counts {
             mem += count.size_in_bytes();
         }
+        // separator
+        mem += 1;
         mem
     }
 
@@ -216,6 +443,52 @@ where
         TrieCountLmLookuper::new(self)
     }
 
+    /// Makes the iterator over the pairs of token ids and counts of the grams of *N* = `n`,
+    /// in the sorted order of the trie.
+    /// The token ids are those of the vocabulary, i.e., the positions in the unigram file.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `n` is not in `1..=self.num_orders()`.
+    pub fn grams(&self, n: usize) -> TrieCountLmGramsIter<'_, T, V, A> {
+        assert!(
+            1 <= n && n <= self.num_orders(),
+            "n must be in 1..=num_orders."
+        );
+        TrieCountLmGramsIter::new(self, n - 1)
+    }
+
+    /// Checks that the vocabulary supports the reverse lookup of tokens,
+    /// which is needed to merge and dump the index.
+    ///
+    /// For example, [`DoubleArrayVocabulary`](crate::DoubleArrayVocabulary) does not support it,
+    /// while [`ReversibleDoubleArrayVocabulary`](crate::ReversibleDoubleArrayVocabulary) does.
+    pub fn check_reverse_lookup(&self) -> Result<()> {
+        if self.num_grams() != 0 && self.vocab.token(0).is_none() {
+            return Err(anyhow!(
+                "{} does not support the reverse lookup of tokens, which is needed to merge and dump the index.",
+                V::TYPE_NAME
+            ));
+        }
+        Ok(())
+    }
+
+    /// Gets the token of an id, failing if the vocabulary does not support the reverse lookup.
+    fn token(&self, id: usize) -> Result<Gram<'_>> {
+        self.vocab.token(id).ok_or_else(|| {
+            anyhow!(
+                "Token id {} is not found in {}, which may not support the reverse lookup.",
+                id,
+                V::TYPE_NAME
+            )
+        })
+    }
+
+    /// Gets the separator of tokens, with which the grams given as strings are split.
+    pub const fn token_separator(&self) -> u8 {
+        self.separator
+    }
+
     /// Gets the maximum of *N*.
     pub fn num_orders(&self) -> usize {
         self.count_ranks.len()
@@ -230,7 +503,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{EliasFanoTrieCountLm, Gram, SimpleTrieCountLm};
+    use crate::{EliasFanoTrieCountLm, Gram, ReversibleTrieCountLm, SimpleTrieCountLm};
 
     const GRAMS_1: &'static str = "4
 A\t10
@@ -334,6 +607,48 @@ D D D\t1
         test_trigrams(&lm.arrays[1], &lm.count_ranks[2]);
     }
 
+    #[test]
+    fn test_parallel_components() {
+        let mut loaders = Vec::new();
+        for text in [GRAMS_1, GRAMS_2, GRAMS_3] {
+            let loader: Box<dyn GramsLoader<_>> = Box::new(GramsTextLoader::new(text.as_bytes()));
+            loaders.push(loader);
+        }
+        let lm: EliasFanoTrieCountLm = TrieCountLmBuilder::new(loaders)
+            .unwrap()
+            .build_parallel(2)
+            .unwrap();
+        test_vocabulary(&lm.vocab);
+        test_unigrams(&lm.count_ranks[0]);
+        test_bigrams(&lm.arrays[0], &lm.count_ranks[1]);
+        test_trigrams(&lm.arrays[1], &lm.count_ranks[2]);
+    }
+
+    #[test]
+    fn test_high_order_lookup() {
+        let max_order = 10;
+        let mut texts = vec!["2\nA\t20\nB\t1\n"];
+        for order in 2..=max_order {
+            let gram = vec!["A"; order - 1].join(" ");
+            let text = format!("2\n{} A\t{}\n{} B\t1\n", gram, 20 - order, gram);
+            texts.push(Box::leak(text.into_boxed_str()));
+        }
+
+        let lm = EliasFanoTrieCountLm::from_texts(texts).unwrap();
+        assert_eq!(lm.num_orders(), max_order);
+
+        let mut lookuper = lm.lookuper();
+        for order in 2..=max_order {
+            let gram = vec!["A"; order].join(" ");
+            assert_eq!(lookuper.with_str(&gram), Some(20 - order));
+            let gram = format!("{} B", vec!["A"; order - 1].join(" "));
+            assert_eq!(lookuper.with_str(&gram), Some(1));
+        }
+        assert_eq!(lookuper.with_tokens(&["A"; 10]), Some(10));
+        assert_eq!(lookuper.with_tokens(&["A"; 11]), None);
+        assert_eq!(lookuper.with_str("A B A"), None);
+    }
+
     #[test]
     fn test_simple_lookup() {
         let lm = SimpleTrieCountLm::from_texts(vec![GRAMS_1, GRAMS_2, GRAMS_3]).unwrap();
@@ -395,4 +710,78 @@ D D D\t1
         assert_eq!(lookuper.with_gram(Gram::from_str("B A")), None);
         assert_eq!(lookuper.with_gram(Gram::from_str("B B A")), None);
     }
+
+    #[test]
+    fn test_grams() {
+        let lm = ReversibleTrieCountLm::from_texts(vec![GRAMS_1, GRAMS_2, GRAMS_3]).unwrap();
+        for (n, text) in [GRAMS_1, GRAMS_2, GRAMS_3].iter().enumerate() {
+            let loader = GramsTextLoader::new(text.as_bytes());
+            let records = loader.parser().unwrap().into_count_records();
+            let grams: Vec<(Vec<usize>, usize)> = lm.grams(n + 1).collect();
+            assert_eq!(grams.len(), lm.count_ranks[n].len());
+            for (rec, (token_ids, count)) in records.zip(grams) {
+                let rec = rec.unwrap();
+                let tokens: Vec<String> = token_ids
+                    .iter()
+                    .map(|&id| lm.vocab.token(id).unwrap().to_string())
+                    .collect();
+                assert_eq!(tokens.join(" "), rec.gram().to_string());
+                assert_eq!(count, rec.count());
+            }
+        }
+    }
+
+    #[test]
+    fn test_corrupted_num_components() {
+        let lm = SimpleTrieCountLm::from_texts(vec![GRAMS_1, GRAMS_2, GRAMS_3]).unwrap();
+        let mut data = vec![];
+        lm.serialize_into(&mut data).unwrap();
+
+        // A broken number of arrays is rejected before allocating them.
+        let pos = lm.header(0).size_in_bytes() + lm.vocab.size_in_bytes();
+        data[pos + 7] ^= 0xff;
+        let e = SimpleTrieCountLm::deserialize_from(&data[..])
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", e).contains("exceeds the number of orders 3"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let lm = ReversibleTrieCountLm::from_texts(vec![GRAMS_1, GRAMS_2, GRAMS_3]).unwrap();
+        let other =
+            ReversibleTrieCountLm::from_texts(vec!["2\nE\t5\nA\t1\n", "1\nE A\t1\n"]).unwrap();
+        let merged = ReversibleTrieCountLm::merge(&[&lm, &other, &lm]).unwrap();
+        assert_eq!(merged.num_orders(), 3);
+        assert_eq!(merged.num_grams(), lm.num_grams() + 2);
+
+        let mut lookuper = merged.lookuper();
+        for text in [GRAMS_1, GRAMS_2, GRAMS_3] {
+            let loader = GramsTextLoader::new(text.as_bytes());
+            let mut gp = loader.parser().unwrap();
+            while let Some(rec) = gp.next_count_record() {
+                let rec = rec.unwrap();
+                let expected = if rec.gram().to_string() == "A" {
+                    21
+                } else {
+                    rec.count() * 2
+                };
+                assert_eq!(lookuper.with_gram(rec.gram()), Some(expected));
+            }
+        }
+        assert_eq!(lookuper.with_str("E"), Some(5));
+        assert_eq!(lookuper.with_str("E A"), Some(1));
+
+        // The grams are re-sorted with temporary files beyond the memory budget.
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let external = TrieCountLmMerger::new()
+            .memory_budget(1)
+            .tmp_dir(tmp_dir.path())
+            .merge(&[&lm, &other, &lm])
+            .unwrap();
+        let (mut data, mut expected) = (vec![], vec![]);
+        external.serialize_into(&mut data).unwrap();
+        merged.serialize_into(&mut expected).unwrap();
+        assert_eq!(data, expected);
+    }
 }
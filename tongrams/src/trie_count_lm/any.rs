@@ -0,0 +1,381 @@
+use std::any::Any;
+use std::io::{Read, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::rank_array::RankArray;
+use crate::trie_array::TrieArray;
+use crate::trie_count_lm::{IndexHeader, TrieCountLm, TrieCountLmLookuper, TrieCountLmMerger};
+use crate::vocabulary::Vocabulary;
+use crate::{
+    CompactRankArray, DoubleArrayVocabulary, EliasFanoRankArray, EliasFanoTrieArray,
+    GammaRankArray, Gram, GramsFileFormats, ReversibleDoubleArrayVocabulary, SimpleRankArray,
+    SimpleTrieArray, SimpleVocabulary,
+};
+
+/// Object-safe interface of [`TrieCountLm`] regardless of the component types,
+/// through which [`AnyTrieCountLm`] is used.
+pub trait CountLm: Send + Sync {
+    /// Makes the lookuper.
+    fn lookuper(&self) -> Box<dyn CountLmLookuper + '_>;
+
+    /// Gets the maximum of *N*.
+    fn num_orders(&self) -> usize;
+
+    /// Gets the number of stored grams.
+    fn num_grams(&self) -> usize;
+
+    /// Gets the separator of tokens, with which the grams given as strings are split.
+    fn token_separator(&self) -> u8;
+
+    /// Gets the number of bytes to serialize the index.
+    fn size_in_bytes(&self) -> usize;
+
+    /// Gets breakdowns of memory usages for components.
+    fn memory_statistics(&self) -> serde_json::Value;
+
+    /// Serializes the index into the writer.
+    fn serialize_into(&self, writer: &mut dyn Write) -> Result<usize>;
+
+    /// Makes the iterator over the pairs of token ids and counts of the grams of *N* = `n`,
+    /// as [`TrieCountLm::grams`].
+    fn grams(&self, n: usize) -> Box<dyn Iterator<Item = (Vec<usize>, usize)> + '_>;
+
+    /// Gets the token of an id, failing if the vocabulary does not support the reverse lookup.
+    fn token(&self, id: usize) -> Result<Gram<'_>>;
+
+    /// Checks that the vocabulary supports the reverse lookup of tokens,
+    /// as [`TrieCountLm::check_reverse_lookup`].
+    fn check_reverse_lookup(&self) -> Result<()>;
+
+    /// Writes the grams of all the orders into *N*-gram counts files in `dirpath`,
+    /// as [`TrieCountLm::dump_files`].
+    fn dump_files(&self, dirpath: &Path, fmt: GramsFileFormats) -> Result<Vec<PathBuf>>;
+
+    /// Merges the index and `others` into a new one with `merger`,
+    /// as [`TrieCountLmMerger::merge_any`].
+    ///
+    /// `others` can be of any component types,
+    /// and the merged index consists of the component types of this index.
+    fn merge_with(
+        &self,
+        others: &[&dyn CountLm],
+        merger: &TrieCountLmMerger,
+    ) -> Result<Box<dyn CountLm>>;
+
+    /// Gets the index as [`Any`], which can be downcast to the concrete [`TrieCountLm`].
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Object-safe interface of [`TrieCountLmLookuper`].
+pub trait CountLmLookuper {
+    /// Looks up a gram, returning the count.
+    fn with_gram(&mut self, gram: Gram) -> Option<usize>;
+
+    /// Looks up a gram in which tokens are sparated by the separator of the index,
+    /// returning the count.
+    fn with_str(&mut self, gram: &str) -> Option<usize>;
+
+    /// Looks up a gram formed by a token list, returning the count.
+    fn with_tokens(&mut self, tokens: &[&str]) -> Option<usize>;
+}
+
+impl<T, V, A> CountLm for TrieCountLm<T, V, A>
+where
+    T: TrieArray + Send + Sync + 'static,
+    V: Vocabulary + Send + Sync + 'static,
+    A: RankArray + Send + Sync + 'static,
+{
+    fn lookuper(&self) -> Box<dyn CountLmLookuper + '_> {
+        Box::new(Self::lookuper(self))
+    }
+
+    fn num_orders(&self) -> usize {
+        Self::num_orders(self)
+    }
+
+    fn num_grams(&self) -> usize {
+        Self::num_grams(self)
+    }
+
+    fn token_separator(&self) -> u8 {
+        Self::token_separator(self)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        Self::size_in_bytes(self)
+    }
+
+    fn memory_statistics(&self) -> serde_json::Value {
+        Self::memory_statistics(self)
+    }
+
+    fn serialize_into(&self, writer: &mut dyn Write) -> Result<usize> {
+        Self::serialize_into(self, writer)
+    }
+
+    fn grams(&self, n: usize) -> Box<dyn Iterator<Item = (Vec<usize>, usize)> + '_> {
+        Box::new(Self::grams(self, n))
+    }
+
+    fn token(&self, id: usize) -> Result<Gram<'_>> {
+        Self::token(self, id)
+    }
+
+    fn check_reverse_lookup(&self) -> Result<()> {
+        Self::check_reverse_lookup(self)
+    }
+
+    fn dump_files(&self, dirpath: &Path, fmt: GramsFileFormats) -> Result<Vec<PathBuf>> {
+        Self::dump_files(self, dirpath, fmt)
+    }
+
+    fn merge_with(
+        &self,
+        others: &[&dyn CountLm],
+        merger: &TrieCountLmMerger,
+    ) -> Result<Box<dyn CountLm>> {
+        let mut lms: Vec<&dyn CountLm> = vec![self];
+        lms.extend_from_slice(others);
+        Ok(Box::new(merger.merge_any::<T, V, A>(&lms)?))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl<T, V, A> CountLmLookuper for TrieCountLmLookuper<'_, T, V, A>
+where
+    T: TrieArray,
+    V: Vocabulary,
+    A: RankArray,
+{
+    fn with_gram(&mut self, gram: Gram) -> Option<usize> {
+        Self::with_gram(self, gram)
+    }
+
+    fn with_str(&mut self, gram: &str) -> Option<usize> {
+        Self::with_str(self, gram)
+    }
+
+    fn with_tokens(&mut self, tokens: &[&str]) -> Option<usize> {
+        Self::with_tokens(self, tokens)
+    }
+}
+
+/// Handle of [`TrieCountLm`] of any component types, which are identified from [`IndexHeader`]
+/// in deserialization.
+///
+/// The index is used through [`CountLm`], to which the handle is dereferenced.
+///
+/// # Examples
+///
+/// ```
+/// use tongrams::{AnyTrieCountLm, EliasFanoTrieCountLm, SimpleTrieCountLm};
+///
+/// let lm = SimpleTrieCountLm::from_texts(vec!["2\nA\t3\nB\t1\n", "1\nA B\t1\n"]).unwrap();
+/// let mut data = vec![];
+/// lm.serialize_into(&mut data).unwrap();
+///
+/// let any = AnyTrieCountLm::deserialize_from(&data[..]).unwrap();
+/// assert_eq!(any.header().trie_array(), "SimpleTrieArray");
+/// assert_eq!(any.lookuper().with_str("A B"), Some(1));
+/// assert!(any.downcast_ref::<SimpleTrieCountLm>().is_some());
+/// assert!(any.downcast_ref::<EliasFanoTrieCountLm>().is_none());
+/// ```
+pub struct AnyTrieCountLm {
+    header: IndexHeader,
+    lm: Box<dyn CountLm>,
+}
+
+impl AnyTrieCountLm {
+    /// Deserializes the index from the reader,
+    /// dispatching to [`TrieCountLm`] of the component types in the header.
+    pub fn deserialize_from<R>(mut reader: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let header = IndexHeader::deserialize_from(&mut reader)?;
+        let lm = deserialize_trie_array(&header, reader)?;
+        Ok(Self { header, lm })
+    }
+
+    /// Gets the header of the index.
+    pub const fn header(&self) -> &IndexHeader {
+        &self.header
+    }
+
+    /// Gets the reference to the concrete [`TrieCountLm`] if the index is of it.
+    pub fn downcast_ref<L>(&self) -> Option<&L>
+    where
+        L: CountLm + 'static,
+    {
+        self.lm.as_any().downcast_ref()
+    }
+
+    /// Gets the index as [`CountLm`].
+    pub fn into_inner(self) -> Box<dyn CountLm> {
+        self.lm
+    }
+}
+
+impl Deref for AnyTrieCountLm {
+    type Target = dyn CountLm;
+
+    fn deref(&self) -> &Self::Target {
+        self.lm.as_ref()
+    }
+}
+
+/// Dispatches the deserialization by [`IndexHeader::trie_array`].
+fn deserialize_trie_array<R>(header: &IndexHeader, reader: R) -> Result<Box<dyn CountLm>>
+where
+    R: Read,
+{
+    let name = header.trie_array();
+    if name == SimpleTrieArray::TYPE_NAME {
+        deserialize_vocabulary::<SimpleTrieArray, _>(header, reader)
+    } else if name == EliasFanoTrieArray::TYPE_NAME {
+        deserialize_vocabulary::<EliasFanoTrieArray, _>(header, reader)
+    } else {
+        Err(anyhow!("The index has an unknown TrieArray {}.", name))
+    }
+}
+
+/// Dispatches the deserialization by [`IndexHeader::vocabulary`].
+fn deserialize_vocabulary<T, R>(header: &IndexHeader, reader: R) -> Result<Box<dyn CountLm>>
+where
+    T: TrieArray + Send + Sync + 'static,
+    R: Read,
+{
+    let name = header.vocabulary();
+    if name == SimpleVocabulary::TYPE_NAME {
+        deserialize_rank_array::<T, SimpleVocabulary, _>(header, reader)
+    } else if name == DoubleArrayVocabulary::TYPE_NAME {
+        deserialize_rank_array::<T, DoubleArrayVocabulary, _>(header, reader)
+    } else if name == ReversibleDoubleArrayVocabulary::TYPE_NAME {
+        deserialize_rank_array::<T, ReversibleDoubleArrayVocabulary, _>(header, reader)
+    } else {
+        Err(anyhow!("The index has an unknown Vocabulary {}.", name))
+    }
+}
+
+/// Dispatches the deserialization by [`IndexHeader::rank_array`].
+fn deserialize_rank_array<T, V, R>(header: &IndexHeader, reader: R) -> Result<Box<dyn CountLm>>
+where
+    T: TrieArray + Send + Sync + 'static,
+    V: Vocabulary + Send + Sync + 'static,
+    R: Read,
+{
+    let name = header.rank_array();
+    if name == SimpleRankArray::TYPE_NAME {
+        deserialize::<T, V, SimpleRankArray, _>(header, reader)
+    } else if name == EliasFanoRankArray::TYPE_NAME {
+        deserialize::<T, V, EliasFanoRankArray, _>(header, reader)
+    } else if name == CompactRankArray::TYPE_NAME {
+        deserialize::<T, V, CompactRankArray, _>(header, reader)
+    } else if name == GammaRankArray::TYPE_NAME {
+        deserialize::<T, V, GammaRankArray, _>(header, reader)
+    } else {
+        Err(anyhow!("The index has an unknown RankArray {}.", name))
+    }
+}
+
+fn deserialize<T, V, A, R>(header: &IndexHeader, reader: R) -> Result<Box<dyn CountLm>>
+where
+    T: TrieArray + Send + Sync + 'static,
+    V: Vocabulary + Send + Sync + 'static,
+    A: RankArray + Send + Sync + 'static,
+    R: Read,
+{
+    let lm = TrieCountLm::<T, V, A>::deserialize_with_header(header, reader)?;
+    Ok(Box::new(lm))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        CompactRankTrieCountLm, EliasFanoTrieCountLm, GammaRankTrieCountLm, ReversibleTrieCountLm,
+        SimpleTrieCountLm,
+    };
+
+    const GRAMS_1: &str = "2\nA\t3\nB\t1\n";
+    const GRAMS_2: &str = "1\nA B\t1\n";
+
+    fn open<T, V, A>() -> AnyTrieCountLm
+    where
+        T: TrieArray,
+        V: Vocabulary,
+        A: RankArray,
+    {
+        let lm = TrieCountLm::<T, V, A>::from_texts(vec![GRAMS_1, GRAMS_2]).unwrap();
+        let mut data = vec![];
+        lm.serialize_into(&mut data).unwrap();
+        AnyTrieCountLm::deserialize_from(&data[..]).unwrap()
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let lms = [
+            open::<SimpleTrieArray, SimpleVocabulary, SimpleRankArray>(),
+            open::<EliasFanoTrieArray, DoubleArrayVocabulary, EliasFanoRankArray>(),
+            open::<EliasFanoTrieArray, DoubleArrayVocabulary, CompactRankArray>(),
+            open::<EliasFanoTrieArray, DoubleArrayVocabulary, GammaRankArray>(),
+            open::<SimpleTrieArray, DoubleArrayVocabulary, EliasFanoRankArray>(),
+            open::<EliasFanoTrieArray, ReversibleDoubleArrayVocabulary, EliasFanoRankArray>(),
+        ];
+        for lm in &lms {
+            assert_eq!(lm.num_orders(), 2);
+            assert_eq!(lm.num_grams(), 3);
+            let mut lookuper = lm.lookuper();
+            assert_eq!(lookuper.with_str("A"), Some(3));
+            assert_eq!(lookuper.with_tokens(&["A", "B"]), Some(1));
+            assert_eq!(lookuper.with_str("B A"), None);
+        }
+        assert!(lms[1].downcast_ref::<EliasFanoTrieCountLm>().is_some());
+        assert!(lms[2].downcast_ref::<CompactRankTrieCountLm>().is_some());
+        assert!(lms[3].downcast_ref::<GammaRankTrieCountLm>().is_some());
+        assert!(lms[4].downcast_ref::<EliasFanoTrieCountLm>().is_none());
+        assert!(lms[5].downcast_ref::<ReversibleTrieCountLm>().is_some());
+
+        // The index is serialized back as it is.
+        let mut data = vec![];
+        lms[1].serialize_into(&mut data).unwrap();
+        assert_eq!(data.len(), lms[1].size_in_bytes());
+        let lm = EliasFanoTrieCountLm::deserialize_from(&data[..]).unwrap();
+        assert_eq!(lm.lookuper().with_str("A B"), Some(1));
+    }
+
+    #[test]
+    fn test_merge_with() {
+        let lm1 = open::<EliasFanoTrieArray, ReversibleDoubleArrayVocabulary, EliasFanoRankArray>();
+        let lm2 = open::<EliasFanoTrieArray, ReversibleDoubleArrayVocabulary, EliasFanoRankArray>();
+        let merger = TrieCountLmMerger::new();
+        let merged = lm1.merge_with(&[&*lm2], &merger).unwrap();
+        assert!(merged.as_any().is::<ReversibleTrieCountLm>());
+        let mut lookuper = merged.lookuper();
+        assert_eq!(lookuper.with_str("A"), Some(6));
+        assert_eq!(lookuper.with_str("A B"), Some(2));
+
+        // The component types can differ, and the merged index takes those of the first one.
+        let lm3 = open::<SimpleTrieArray, SimpleVocabulary, SimpleRankArray>();
+        let merged = lm3.merge_with(&[&*lm1, &*lm2], &merger).unwrap();
+        assert!(merged.as_any().is::<SimpleTrieCountLm>());
+        let mut lookuper = merged.lookuper();
+        assert_eq!(lookuper.with_str("A"), Some(9));
+        assert_eq!(lookuper.with_str("A B"), Some(3));
+
+        // The vocabularies must support the reverse lookup.
+        let lm4 = open::<EliasFanoTrieArray, DoubleArrayVocabulary, EliasFanoRankArray>();
+        let e = lm1.merge_with(&[&*lm4], &merger).err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            "DoubleArrayVocabulary does not support the reverse lookup of tokens, which is needed to merge and dump the index."
+        );
+    }
+}
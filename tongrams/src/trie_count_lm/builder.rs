@@ -1,23 +1,35 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
+use rayon::prelude::*;
 
+use crate::int_buffer::{BufferOptions, IntBuffer};
 use crate::loader::GramsLoader;
+use crate::parser::GramsParser;
+use crate::progress::{BuildPhase, BuildProgress, PhaseProgress, ProgressReporter};
 use crate::rank_array::RankArray;
+use crate::sorter::{MappedRecord, RecordsSorter};
 use crate::trie_array::TrieArray;
+use crate::trie_count_lm::pruner;
+use crate::validation::{ValidationError, ValidationErrorKind};
 use crate::vocabulary::Vocabulary;
 use crate::Gram;
 use crate::TrieCountLm;
-use crate::MAX_ORDER;
+use crate::TOKEN_SEPARATOR;
+use crate::{CountRecord, CountRecordRef};
 
 /// Builder for [`TrieCountLm`].
+///
+/// Each input file is decoded only once.
+/// The token ids and counts of each order are buffered in memory
+/// (or in temporary files with [`TrieCountLmBuilder::memory_budget`]),
+/// and the count ranks are derived from the buffered counts.
 pub struct TrieCountLmBuilder<R, T, V, A> {
     loaders: Vec<Box<dyn GramsLoader<R>>>,
-    vocab: V,
-    arrays: Vec<T>,
-    count_ranks: Vec<A>,
-    counts_builder: CountsBuilder,
+    levels_builder: LevelsBuilder<T, V, A>,
 }
 
 impl<R, T, V, A> TrieCountLmBuilder<R, T, V, A>
@@ -29,132 +41,1526 @@ where
 {
     /// Creates [`TrieCountLmBuilder`] from loaders.
     pub fn new(loaders: Vec<Box<dyn GramsLoader<R>>>) -> Result<Self> {
-        if MAX_ORDER < loaders.len() {
-            return Err(anyhow!("loaders.len() must be no more than {}", MAX_ORDER));
+        if loaders.is_empty() {
+            return Err(anyhow!("loaders must not be empty."));
         }
         Ok(Self {
             loaders,
+            levels_builder: LevelsBuilder::new(),
+        })
+    }
+
+    /// Sets the approximate memory budget in bytes for the buffered data of an order
+    /// (default: unlimited).
+    ///
+    /// The buffered sequences exceeding the budget are spilled to temporary files
+    /// and streamed into the data structures,
+    /// so that orders larger than RAM can be indexed.
+    /// The unigram tokens, the vocabulary, the distinct counts, and the data structures built
+    /// are still kept in memory.
+    /// [`EliasFanoTrieArray`](crate::EliasFanoTrieArray), [`EliasFanoRankArray`](crate::EliasFanoRankArray),
+    /// and [`CompactRankArray`](crate::CompactRankArray) are built without collecting the spilled sequences.
+    /// With multiple threads, the budget is applied to each order decoded concurrently.
+    pub const fn memory_budget(mut self, memory_budget: usize) -> Self {
+        self.levels_builder.memory_budget = Some(memory_budget);
+        self
+    }
+
+    /// Sets the directory in which temporary files are placed
+    /// (default: the temporary directory of the system).
+    pub fn tmp_dir<P>(mut self, tmp_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.levels_builder.tmp_dir = Some(tmp_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets whether to sort the grams of *N* = 2, 3, ... before building (default: `false`),
+    /// so that the input files need not be sorted.
+    ///
+    /// The grams are sorted in the order of token ids, i.e., the positions in the unigram file,
+    /// which is not sorted.
+    /// The sorting is performed in memory, or externally with temporary files
+    /// if the grams exceed [`TrieCountLmBuilder::memory_budget`].
+    pub const fn sort_grams(mut self, sort_grams: bool) -> Self {
+        self.levels_builder.sort_grams = sort_grams;
+        self
+    }
+
+    /// Sets the minimum counts of grams for *N* = 1, 2, ... (default: no threshold),
+    /// where `min_counts[i]` is applied to the grams of *N* = `i + 1`.
+    ///
+    /// The grams are pruned keeping the trie prefix-closed,
+    /// i.e., a gram below the threshold survives if any extension of it survives.
+    /// A unigram also survives if its token appears in the surviving grams.
+    /// The pruning is performed on the buffered data of the orders before the trie is built,
+    /// propagating the survival from the highest order to the lowest.
+    /// Note that the buffered data of all the orders are kept at the same time.
+    pub fn min_counts(mut self, min_counts: Vec<usize>) -> Self {
+        self.levels_builder.min_counts = min_counts;
+        self
+    }
+
+    /// Sets the maximum numbers of grams for *N* = 1, 2, ... (default: unlimited),
+    /// where `max_grams[i]` is applied to the grams of *N* = `i + 1`.
+    ///
+    /// The most frequent grams are selected (ties are broken by the trie order),
+    /// and the others are pruned in the same manner as [`TrieCountLmBuilder::min_counts`].
+    /// The threshold of counts is found in a pass over the buffered counts of each order.
+    /// Since the prefixes of the surviving grams are kept, an order can exceed the limit.
+    pub fn max_grams(mut self, max_grams: Vec<usize>) -> Self {
+        self.levels_builder.max_grams = max_grams;
+        self
+    }
+
+    /// Sets the separator of tokens in the index (default: that of the unigram loader),
+    /// with which the grams given as strings are split in lookups.
+    ///
+    /// The grams of each file are split with the token separator of its loader,
+    /// so the files can be written with separators different from that of the index.
+    pub const fn token_separator(mut self, separator: u8) -> Self {
+        self.levels_builder.separator = Some(separator);
+        self
+    }
+
+    /// Sets the callback receiving the progress of the construction (default: none).
+    ///
+    /// The progress of each phase (i.e., the vocabulary, the grams of an order, and their counts)
+    /// is reported periodically and at the end of the phase.
+    /// With multiple threads, the callback can be called concurrently for different orders.
+    pub fn progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&BuildProgress) + Send + Sync + 'static,
+    {
+        self.levels_builder.progress = ProgressReporter::new(Arc::new(callback));
+        self
+    }
+
+    /// Builds [`TrieCountLm`].
+    pub fn build(self) -> Result<TrieCountLm<T, V, A>> {
+        let sources: Vec<&dyn GramsLoader<R>> = self.loaders.iter().map(|l| l.as_ref()).collect();
+        self.levels_builder.build(sources)
+    }
+}
+
+impl<R, T, V, A> TrieCountLmBuilder<R, T, V, A>
+where
+    R: Read + Send,
+    T: TrieArray,
+    V: Vocabulary + Sync,
+    A: RankArray + Send,
+{
+    /// Builds [`TrieCountLm`] on `num_threads` threads.
+    ///
+    /// The files of *N* = 2, 3, ... are decoded concurrently
+    /// after the vocabulary is built from the unigram file,
+    /// for which the parsers of all the files are created beforehand.
+    /// Note that the buffered data of all the orders are kept in memory at the same time.
+    /// If `0` is given, the number of logical CPUs is used,
+    /// and if `1` is given, it is equivalent to [`TrieCountLmBuilder::build`].
+    pub fn build_parallel(mut self, num_threads: usize) -> Result<TrieCountLm<T, V, A>> {
+        if num_threads == 1 {
+            return self.build();
+        }
+        self.levels_builder.num_threads = num_threads;
+        let parsers = self
+            .loaders
+            .iter()
+            .map(|loader| loader.parser())
+            .collect::<Result<Vec<_>>>()?;
+        self.levels_builder.build_in_parallel(parsers)
+    }
+}
+
+/// Builder for [`TrieCountLm`] from in-memory records,
+/// where the source of records can differ for each order.
+///
+/// # Examples
+///
+/// ```
+/// use tongrams::trie_count_lm::{GramsSource, TrieCountLmRecordsBuilder};
+/// use tongrams::{CountRecord, EliasFanoTrieCountLm};
+///
+/// // Token ids are the positions of the unigram records.
+/// let unigrams = vec![
+///     CountRecord::new("A".to_string(), 3),
+///     CountRecord::new("B".to_string(), 2),
+/// ];
+/// let bigrams = [0, 1, 1, 0];
+/// let sources = vec![
+///     GramsSource::records(unigrams),
+///     GramsSource::token_ids(bigrams.chunks(2).zip([2, 1])),
+/// ];
+/// let lm: EliasFanoTrieCountLm = TrieCountLmRecordsBuilder::new(sources)
+///     .unwrap()
+///     .build()
+///     .unwrap();
+///
+/// let mut lookuper = lm.lookuper();
+/// assert_eq!(lookuper.with_str("A B"), Some(2));
+/// assert_eq!(lookuper.with_str("B A"), Some(1));
+/// ```
+pub struct TrieCountLmRecordsBuilder<'a, T, V, A> {
+    sources: Vec<GramsSource<'a>>,
+    levels_builder: LevelsBuilder<T, V, A>,
+}
+
+impl<'a, T, V, A> TrieCountLmRecordsBuilder<'a, T, V, A>
+where
+    T: TrieArray,
+    V: Vocabulary,
+    A: RankArray,
+{
+    /// Creates [`TrieCountLmRecordsBuilder`] from the sources of *N* = 1, 2, ...
+    ///
+    /// The source of unigrams must be given by [`GramsSource::Records`].
+    pub fn new(sources: Vec<GramsSource<'a>>) -> Result<Self> {
+        if sources.is_empty() {
+            return Err(anyhow!("sources must not be empty."));
+        }
+        if let GramsSource::TokenIds(_) = sources[0] {
+            return Err(anyhow!("1-grams must be given as count records."));
+        }
+        Ok(Self {
+            sources,
+            levels_builder: LevelsBuilder::new(),
+        })
+    }
+
+    /// Sets the approximate memory budget in bytes for the buffered data of an order
+    /// (default: unlimited).
+    ///
+    /// See [`TrieCountLmBuilder::memory_budget`] for the details.
+    pub const fn memory_budget(mut self, memory_budget: usize) -> Self {
+        self.levels_builder.memory_budget = Some(memory_budget);
+        self
+    }
+
+    /// Sets the directory in which temporary files are placed
+    /// (default: the temporary directory of the system).
+    pub fn tmp_dir<P>(mut self, tmp_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.levels_builder.tmp_dir = Some(tmp_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets whether to sort the grams of *N* = 2, 3, ... before building (default: `false`),
+    /// so that the sources need not be sorted.
+    ///
+    /// See [`TrieCountLmBuilder::sort_grams`] for the details.
+    pub const fn sort_grams(mut self, sort_grams: bool) -> Self {
+        self.levels_builder.sort_grams = sort_grams;
+        self
+    }
+
+    /// Sets the minimum counts of grams for *N* = 1, 2, ... (default: no threshold).
+    ///
+    /// See [`TrieCountLmBuilder::min_counts`] for the details.
+    pub fn min_counts(mut self, min_counts: Vec<usize>) -> Self {
+        self.levels_builder.min_counts = min_counts;
+        self
+    }
+
+    /// Sets the maximum numbers of grams for *N* = 1, 2, ... (default: unlimited).
+    ///
+    /// See [`TrieCountLmBuilder::max_grams`] for the details.
+    pub fn max_grams(mut self, max_grams: Vec<usize>) -> Self {
+        self.levels_builder.max_grams = max_grams;
+        self
+    }
+
+    /// Sets the separator of tokens in the records and the index
+    /// (default: [`TOKEN_SEPARATOR`]).
+    pub const fn token_separator(mut self, separator: u8) -> Self {
+        self.levels_builder.separator = Some(separator);
+        self
+    }
+
+    /// Sets the callback receiving the progress of the construction (default: none).
+    ///
+    /// See [`TrieCountLmBuilder::progress`] for the details.
+    pub fn progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&BuildProgress) + Send + Sync + 'static,
+    {
+        self.levels_builder.progress = ProgressReporter::new(Arc::new(callback));
+        self
+    }
+
+    /// Builds [`TrieCountLm`].
+    pub fn build(self) -> Result<TrieCountLm<T, V, A>> {
+        self.levels_builder.build(self.sources)
+    }
+}
+
+impl<T, V, A> TrieCountLmRecordsBuilder<'_, T, V, A>
+where
+    T: TrieArray,
+    V: Vocabulary + Sync,
+    A: RankArray + Send,
+{
+    /// Builds [`TrieCountLm`] on `num_threads` threads.
+    ///
+    /// See [`TrieCountLmBuilder::build_parallel`] for the details.
+    pub fn build_parallel(mut self, num_threads: usize) -> Result<TrieCountLm<T, V, A>> {
+        if num_threads == 1 {
+            return self.build();
+        }
+        self.levels_builder.num_threads = num_threads;
+        self.levels_builder.build_in_parallel(self.sources)
+    }
+}
+
+/// Source of the in-memory records of an order, which should be sorted as *N*-gram counts files
+/// unless [`TrieCountLmRecordsBuilder::sort_grams`] is enabled.
+pub enum GramsSource<'a> {
+    /// Records of grams written in tokens.
+    Records(Box<dyn Iterator<Item = CountRecord> + Send + 'a>),
+    /// Records of grams written in token ids with their counts,
+    /// where the token ids are the positions of the unigram records.
+    TokenIds(Box<dyn Iterator<Item = (&'a [usize], usize)> + Send + 'a>),
+}
+
+impl<'a> GramsSource<'a> {
+    /// Creates [`GramsSource::Records`] from records.
+    pub fn records<I>(records: I) -> Self
+    where
+        I: IntoIterator<Item = CountRecord>,
+        I::IntoIter: Send + 'a,
+    {
+        Self::Records(Box::new(records.into_iter()))
+    }
+
+    /// Creates [`GramsSource::TokenIds`] from pairs of token ids and counts.
+    pub fn token_ids<I>(grams: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a [usize], usize)>,
+        I::IntoIter: Send + 'a,
+    {
+        Self::TokenIds(Box::new(grams.into_iter()))
+    }
+}
+
+/// Source of the grams of an order, which is read once when the order is built.
+pub trait LevelSource {
+    /// Scans the unigrams one by one,
+    /// where the tokens of in-memory records are separated by `separator`.
+    fn scan_unigrams(
+        self,
+        separator: u8,
+        options: &BufferOptions,
+        phase: &mut PhaseProgress,
+    ) -> Result<UnigramBuffer>;
+
+    /// Scans the grams of `order` (> 0).
+    fn scan_level<V>(self, order: usize, context: &ScanContext<V>) -> Result<LevelBuffer>
+    where
+        V: Vocabulary;
+}
+
+impl<R> LevelSource for &dyn GramsLoader<R>
+where
+    R: Read,
+{
+    fn scan_unigrams(
+        self,
+        separator: u8,
+        options: &BufferOptions,
+        phase: &mut PhaseProgress,
+    ) -> Result<UnigramBuffer> {
+        self.parser()?.scan_unigrams(separator, options, phase)
+    }
+
+    fn scan_level<V>(self, order: usize, context: &ScanContext<V>) -> Result<LevelBuffer>
+    where
+        V: Vocabulary,
+    {
+        self.parser()?.scan_level(order, context)
+    }
+}
+
+impl<R> LevelSource for GramsParser<R>
+where
+    R: Read,
+{
+    fn scan_unigrams(
+        self,
+        _separator: u8,
+        options: &BufferOptions,
+        phase: &mut PhaseProgress,
+    ) -> Result<UnigramBuffer> {
+        let location = RecordsLocation::file(&self);
+        let records = TickedRecords {
+            records: self,
+            phase,
+        };
+        UnigramBuffer::from_records(records, location, options)
+    }
+
+    fn scan_level<V>(self, order: usize, context: &ScanContext<V>) -> Result<LevelBuffer>
+    where
+        V: Vocabulary,
+    {
+        let location = RecordsLocation::file(&self);
+        context.scan_records(self, order, location)
+    }
+}
+
+impl LevelSource for GramsSource<'_> {
+    fn scan_unigrams(
+        self,
+        separator: u8,
+        options: &BufferOptions,
+        phase: &mut PhaseProgress,
+    ) -> Result<UnigramBuffer> {
+        match self {
+            Self::Records(records) => {
+                let location = RecordsLocation::records(0, separator);
+                let records = TickedRecords {
+                    records: OwnedRecords::new(records),
+                    phase,
+                };
+                UnigramBuffer::from_records(records, location, options)
+            }
+            Self::TokenIds(_) => Err(anyhow!("1-grams must be given as count records.")),
+        }
+    }
+
+    fn scan_level<V>(self, order: usize, context: &ScanContext<V>) -> Result<LevelBuffer>
+    where
+        V: Vocabulary,
+    {
+        match self {
+            Self::Records(records) => {
+                let location = RecordsLocation::records(order, context.separator);
+                context.scan_records(OwnedRecords::new(records), order, location)
+            }
+            Self::TokenIds(grams) => context.scan_id_grams(grams, order),
+        }
+    }
+}
+
+/// Count records scanned one by one, where each record can borrow a buffer of the source
+/// until the next one is scanned.
+trait ScanRecords {
+    /// Scans the next record.
+    fn next_record(&mut self) -> Option<Result<CountRecordRef<'_>>>;
+
+    /// Gets the number of the remaining records if known, or `0` otherwise.
+    fn num_remaining(&self) -> usize;
+}
+
+impl<R> ScanRecords for GramsParser<R>
+where
+    R: Read,
+{
+    fn next_record(&mut self) -> Option<Result<CountRecordRef<'_>>> {
+        self.next_count_record_ref()
+    }
+
+    fn num_remaining(&self) -> usize {
+        Self::num_remaining(self)
+    }
+}
+
+/// [`ScanRecords`] over in-memory records, holding the last record scanned.
+struct OwnedRecords<I> {
+    records: I,
+    last: Option<CountRecord>,
+}
+
+impl<I> OwnedRecords<I> {
+    const fn new(records: I) -> Self {
+        Self {
+            records,
+            last: None,
+        }
+    }
+}
+
+impl<I> ScanRecords for OwnedRecords<I>
+where
+    I: Iterator<Item = CountRecord>,
+{
+    fn next_record(&mut self) -> Option<Result<CountRecordRef<'_>>> {
+        let rec = self.last.insert(self.records.next()?);
+        Some(Ok(CountRecordRef::from(&*rec)))
+    }
+
+    fn num_remaining(&self) -> usize {
+        self.records.size_hint().0
+    }
+}
+
+/// [`ScanRecords`] ticking the progress for each record.
+struct TickedRecords<'a, 'p, S> {
+    records: S,
+    phase: &'a mut PhaseProgress<'p>,
+}
+
+impl<S> ScanRecords for TickedRecords<'_, '_, S>
+where
+    S: ScanRecords,
+{
+    fn next_record(&mut self) -> Option<Result<CountRecordRef<'_>>> {
+        let rec = self.records.next_record()?;
+        self.phase.tick();
+        Some(rec)
+    }
+
+    fn num_remaining(&self) -> usize {
+        self.records.num_remaining()
+    }
+}
+
+/// Data shared in scanning the levels of *N* = 2, 3, ...
+pub struct ScanContext<'a, V> {
+    vocab: &'a V,
+    // The unigram tokens, used to report errors
+    tokens: &'a [String],
+    // The separator of tokens in the index
+    separator: u8,
+    options: &'a BufferOptions,
+    // Whether the grams are sorted before scanned
+    sort_grams: bool,
+    memory_budget: Option<usize>,
+    tmp_dir: Option<&'a Path>,
+    progress: &'a ProgressReporter,
+    // The bytes of the data structures built before the scan, used to report the progress
+    memory: usize,
+}
+
+impl<V> ScanContext<'_, V>
+where
+    V: Vocabulary,
+{
+    /// Gets the sorter of the grams of `order` if they should be sorted,
+    /// which sorts them externally beyond the memory budget.
+    fn sorter(&self, order: usize) -> Option<RecordsSorter> {
+        if !self.sort_grams {
+            return None;
+        }
+        let mut sorter = RecordsSorter::new();
+        if let Some(memory_budget) = self.memory_budget {
+            sorter = sorter.memory_budget(memory_budget, order + 1);
+        }
+        if let Some(tmp_dir) = self.tmp_dir {
+            sorter = sorter.tmp_dir(tmp_dir);
+        }
+        Some(sorter)
+    }
+
+    fn scan_records<S>(
+        &self,
+        records: S,
+        order: usize,
+        location: RecordsLocation,
+    ) -> Result<LevelBuffer>
+    where
+        S: ScanRecords,
+    {
+        let mut phase = self
+            .progress
+            .start(BuildPhase::Level(order + 1), self.memory);
+        let records = TickedRecords {
+            records,
+            phase: &mut phase,
+        };
+        let level = self.buffer_records(records, order, location)?;
+        phase.finish();
+        Ok(level)
+    }
+
+    fn buffer_records<S>(
+        &self,
+        mut records: S,
+        order: usize,
+        location: RecordsLocation,
+    ) -> Result<LevelBuffer>
+    where
+        S: ScanRecords,
+    {
+        let sorter = match self.sorter(order) {
+            Some(sorter) => sorter,
+            None => {
+                return LevelBuffer::from_records(
+                    records,
+                    order,
+                    self.vocab,
+                    location,
+                    self.options,
+                )
+            }
+        };
+
+        let mut result = Ok(());
+        let mut record_id = 0;
+        let mapped = std::iter::from_fn(|| {
+            let mapped = records.next_record()?.and_then(|rec| {
+                let gram = rec.gram().with_separator(location.separator);
+                let error = |kind| location.error(location.line(record_id), gram.to_string(), kind);
+                let tokens = gram.split_to_tokens();
+                if tokens.len() != order + 1 {
+                    return Err(error(ValidationErrorKind::WrongOrder));
+                }
+                let mut mapped_ids = Vec::with_capacity(tokens.len());
+                for token in tokens {
+                    let id = self.vocab.get(token).ok_or_else(|| {
+                        error(ValidationErrorKind::UnknownToken(token.to_string()))
+                    })?;
+                    mapped_ids.push(id);
+                }
+                Ok(MappedRecord {
+                    mapped_ids,
+                    record_id,
+                    count: rec.count(),
+                })
+            });
+            record_id += 1;
+            mapped.map_err(|e| result = Err(e)).ok()
+        });
+        let sorted = sorter.sort(mapped)?;
+        result?;
+
+        let grams = sorted.map(|r| (r.record_id, r.mapped_ids, r.count));
+        let num_tokens = self.tokens.len();
+        let separator = location.separator;
+        LevelBuffer::from_id_grams(grams, order, num_tokens, location, self.options, |ids| {
+            join_tokens(self.tokens, ids, separator)
+        })
+    }
+
+    /// Scans the grams of `order` written in the ids of the unigrams with their counts,
+    /// which should be sorted unless the grams are sorted before scanned.
+    pub fn scan_id_grams<I, G>(&self, grams: I, order: usize) -> Result<LevelBuffer>
+    where
+        I: Iterator<Item = (G, usize)>,
+        G: AsRef<[usize]>,
+    {
+        let location = RecordsLocation::records(order, self.separator);
+        self.scan_token_ids(grams, order, location)
+    }
+
+    fn scan_token_ids<I, G>(
+        &self,
+        grams: I,
+        order: usize,
+        location: RecordsLocation,
+    ) -> Result<LevelBuffer>
+    where
+        I: Iterator<Item = (G, usize)>,
+        G: AsRef<[usize]>,
+    {
+        let mut phase = self
+            .progress
+            .start(BuildPhase::Level(order + 1), self.memory);
+        let grams = grams.inspect(|_| phase.tick());
+        let level = self.buffer_token_ids(grams, order, location)?;
+        phase.finish();
+        Ok(level)
+    }
+
+    fn buffer_token_ids<I, G>(
+        &self,
+        grams: I,
+        order: usize,
+        location: RecordsLocation,
+    ) -> Result<LevelBuffer>
+    where
+        I: Iterator<Item = (G, usize)>,
+        G: AsRef<[usize]>,
+    {
+        let num_tokens = self.tokens.len();
+        let describe = |ids: &[usize]| {
+            if ids.iter().all(|&id| id < num_tokens) {
+                join_tokens(self.tokens, ids, self.separator)
+            } else {
+                format!("{:?}", ids)
+            }
+        };
+        match self.sorter(order) {
+            Some(sorter) => {
+                let mapped = grams
+                    .enumerate()
+                    .map(|(record_id, (ids, count))| MappedRecord {
+                        mapped_ids: ids.as_ref().to_vec(),
+                        record_id,
+                        count,
+                    });
+                let sorted = sorter.sort(mapped)?;
+                let grams = sorted.map(|r| (r.record_id, r.mapped_ids, r.count));
+                LevelBuffer::from_id_grams(
+                    grams,
+                    order,
+                    num_tokens,
+                    location,
+                    self.options,
+                    describe,
+                )
+            }
+            None => {
+                let grams = grams.enumerate().map(|(i, (ids, count))| (i, ids, count));
+                LevelBuffer::from_id_grams(
+                    grams,
+                    order,
+                    num_tokens,
+                    location,
+                    self.options,
+                    describe,
+                )
+            }
+        }
+    }
+}
+
+/// Joins the tokens of the ids with the separator byte, which may not be ASCII.
+fn join_tokens(tokens: &[String], token_ids: &[usize], separator: u8) -> String {
+    let mut gram = Vec::new();
+    for (i, &id) in token_ids.iter().enumerate() {
+        if i != 0 {
+            gram.push(separator);
+        }
+        gram.extend_from_slice(tokens[id].as_bytes());
+    }
+    String::from_utf8_lossy(&gram).into_owned()
+}
+
+/// Location of the records of a source, used to report [`ValidationError`]s.
+#[derive(Clone)]
+pub struct RecordsLocation {
+    name: String,
+    // The line number of the first record
+    first_line: usize,
+    // The separator of tokens in the records
+    separator: u8,
+}
+
+impl RecordsLocation {
+    /// Location of the records of a *N*-gram file.
+    pub fn file<R>(parser: &GramsParser<R>) -> Self
+    where
+        R: Read,
+    {
+        Self {
+            name: parser.name().to_string(),
+            first_line: parser.first_row_line(),
+            separator: parser.separators().token,
+        }
+    }
+
+    /// Location of in-memory records of `order`, whose tokens are separated by `separator`.
+    fn records(order: usize, separator: u8) -> Self {
+        Self {
+            name: format!("{}-grams records", order + 1),
+            first_line: 1,
+            separator,
+        }
+    }
+
+    /// Gets the line number of the `i`-th record.
+    pub const fn line(&self, i: usize) -> usize {
+        self.first_line + i
+    }
+
+    /// Gets the separator of tokens in the records.
+    pub const fn separator(&self) -> u8 {
+        self.separator
+    }
+
+    pub fn error(&self, line: usize, gram: String, kind: ValidationErrorKind) -> anyhow::Error {
+        ValidationError::new(&self.name, line, gram, kind).into()
+    }
+}
+
+/// Builder of the levels of [`TrieCountLm`] from sources of count records,
+/// regardless of where the records come from.
+pub struct LevelsBuilder<T, V, A> {
+    vocab: V,
+    // The unigram tokens, used to report errors
+    tokens: Vec<String>,
+    arrays: Vec<T>,
+    count_ranks: Vec<A>,
+    counts_builder: CountsBuilder,
+    num_threads: usize,
+    memory_budget: Option<usize>,
+    tmp_dir: Option<PathBuf>,
+    sort_grams: bool,
+    min_counts: Vec<usize>,
+    max_grams: Vec<usize>,
+    // The separator of tokens in the index, which is that of the unigrams if not given
+    separator: Option<u8>,
+    progress: ProgressReporter,
+}
+
+impl<T, V, A> LevelsBuilder<T, V, A>
+where
+    T: TrieArray,
+    V: Vocabulary,
+    A: RankArray,
+{
+    pub fn new() -> Self {
+        Self {
             vocab: V::new(),
+            tokens: vec![],
             arrays: vec![],
             count_ranks: vec![],
             counts_builder: CountsBuilder::default(),
-        })
+            num_threads: 1,
+            memory_budget: None,
+            tmp_dir: None,
+            sort_grams: false,
+            min_counts: vec![],
+            max_grams: vec![],
+            separator: None,
+            progress: ProgressReporter::default(),
+        }
     }
 
-    /// Builds [`TrieCountLm`].
-    pub fn build(mut self) -> Result<TrieCountLm<T, V, A>> {
-        self.build_counts()?;
-        self.build_vocabulary()?;
+    /// Sets the separator of tokens in the index.
+    pub const fn token_separator(mut self, separator: u8) -> Self {
+        self.separator = Some(separator);
+        self
+    }
+
+    /// Sets the memory budget in bytes for the buffered data of an order.
+    pub const fn memory_budget(mut self, memory_budget: Option<usize>) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    /// Sets the directory in which temporary files are placed.
+    pub fn tmp_dir(mut self, tmp_dir: Option<PathBuf>) -> Self {
+        self.tmp_dir = tmp_dir;
+        self
+    }
+
+    /// Builds [`TrieCountLm`] from the sources of *N* = 1, 2, ..., one order after another.
+    pub fn build<S>(self, sources: Vec<S>) -> Result<TrieCountLm<T, V, A>>
+    where
+        S: LevelSource,
+    {
+        self.build_with::<S, Sequential>(sources)
+    }
+
+    /// Builds [`TrieCountLm`] from the sources of *N* = 1, 2, ...,
+    /// where the orders of *N* = 2, 3, ... are scanned concurrently on `num_threads` threads.
+    pub fn build_in_parallel<S>(self, sources: Vec<S>) -> Result<TrieCountLm<T, V, A>>
+    where
+        S: LevelSource + Send,
+        V: Sync,
+        A: Send,
+    {
+        self.build_with::<S, Parallel>(sources)
+    }
+
+    fn build_with<S, P>(mut self, sources: Vec<S>) -> Result<TrieCountLm<T, V, A>>
+    where
+        S: LevelSource,
+        P: LevelsScan<S, T, V, A>,
+    {
+        let mut sources = sources.into_iter();
+        let unigrams = sources
+            .next()
+            .ok_or_else(|| anyhow!("sources must not be empty."))?;
+        let options = self.buffer_options();
+        let mut phase = self.progress.start(BuildPhase::Vocabulary, 0);
+        let separator = self.separator.unwrap_or(TOKEN_SEPARATOR);
+        let unigrams = unigrams.scan_unigrams(separator, &options, &mut phase)?;
+        self.vocab = Self::build_vocabulary(&unigrams)?;
+        phase.finish();
+        self.separator = Some(self.separator.unwrap_or(unigrams.location.separator));
+
+        let sources: Vec<S> = sources.collect();
+        if !self.min_counts.is_empty() || !self.max_grams.is_empty() {
+            return self.build_pruned::<S, P>(unigrams, sources, &options);
+        }
 
-        let max_order = self.loaders.len() - 1;
-        for order in 1..=max_order {
-            self.build_sorted_array(order)?;
+        self.push_unigrams(unigrams, &options)?;
+        P::build_levels(&mut self, sources, &options)?;
+        Ok(self.release())
+    }
+
+    /// Builds [`TrieCountLm`] pruning the grams by counts,
+    /// where all the levels are scanned before pruned and built.
+    fn build_pruned<S, P>(
+        mut self,
+        mut unigrams: UnigramBuffer,
+        sources: Vec<S>,
+        options: &BufferOptions,
+    ) -> Result<TrieCountLm<T, V, A>>
+    where
+        S: LevelSource,
+        P: LevelsScan<S, T, V, A>,
+    {
+        // The unigram tokens are lent to the scan to report errors.
+        self.tokens = std::mem::take(&mut unigrams.tokens);
+        let levels = P::scan_levels(&self, sources, options)?;
+        unigrams.tokens = std::mem::take(&mut self.tokens);
+
+        let (unigrams, levels) =
+            pruner::prune(unigrams, levels, &self.min_counts, &self.max_grams, options)?;
+        self.vocab = Self::build_vocabulary(&unigrams)?;
+        self.push_unigrams(unigrams, options)?;
+        for (i, level) in levels.into_iter().enumerate() {
+            let (counts_builder, count_ranks) = Self::build_count_ranks(
+                &level.counts,
+                i + 2,
+                options,
+                &self.progress,
+                self.memory(),
+            )?;
+            self.push_level(i + 1, level, counts_builder, count_ranks, options)?;
         }
+        Ok(self.release())
+    }
 
-        Ok(TrieCountLm {
+    fn release(self) -> TrieCountLm<T, V, A> {
+        TrieCountLm {
             vocab: self.vocab,
             arrays: self.arrays,
             count_ranks: self.count_ranks,
             counts: self.counts_builder.release(),
-        })
+            separator: self.separator.unwrap(),
+        }
+    }
+
+    fn scan_context<'a>(&'a self, options: &'a BufferOptions) -> ScanContext<'a, V> {
+        ScanContext {
+            vocab: &self.vocab,
+            tokens: &self.tokens,
+            separator: self.separator.unwrap_or(TOKEN_SEPARATOR),
+            options,
+            sort_grams: self.sort_grams,
+            memory_budget: self.memory_budget,
+            tmp_dir: self.tmp_dir.as_deref(),
+            progress: &self.progress,
+            memory: self.memory(),
+        }
+    }
+
+    /// Gets the bytes of the data structures built so far if the progress is reported.
+    fn memory(&self) -> usize {
+        if !self.progress.is_enabled() {
+            return 0;
+        }
+        self.vocab.size_in_bytes()
+            + self.arrays.iter().map(|a| a.size_in_bytes()).sum::<usize>()
+            + self
+                .count_ranks
+                .iter()
+                .map(|a| a.size_in_bytes())
+                .sum::<usize>()
+    }
+
+    /// Gets the options of buffers, dividing the memory budget into the sequences of a level.
+    fn buffer_options(&self) -> BufferOptions {
+        self.memory_budget
+            .map_or_else(BufferOptions::default, |memory_budget| {
+                let capacity = memory_budget / (std::mem::size_of::<usize>() * NUM_LEVEL_SEQUENCES);
+                BufferOptions::new(capacity, self.tmp_dir.clone())
+            })
     }
 
-    fn build_counts(&mut self) -> Result<()> {
-        for loader in &self.loaders {
-            let mut gp = loader.parser()?;
-            while let Some(rec) = gp.next_count_record() {
-                self.counts_builder.eat_value(rec?.count());
+    /// Builds the vocabulary from the unigram tokens, checking that they are distinct.
+    fn build_vocabulary(unigrams: &UnigramBuffer) -> Result<V> {
+        let location = &unigrams.location;
+        let mut tokens = HashSet::with_capacity(unigrams.tokens.len());
+        for (i, token) in unigrams.tokens.iter().enumerate() {
+            if !tokens.insert(token.as_str()) {
+                let kind = ValidationErrorKind::Duplicate;
+                return Err(location.error(location.line(i), token.clone(), kind));
             }
-            self.counts_builder.build_sequence();
         }
+        drop(tokens);
+
+        let grams: Vec<Gram> = unigrams.tokens.iter().map(|t| Gram::from_str(t)).collect();
+        V::build(&grams)
+    }
+
+    /// Keeps the unigram tokens and builds their count ranks.
+    fn push_unigrams(&mut self, unigrams: UnigramBuffer, options: &BufferOptions) -> Result<()> {
+        let (counts_builder, count_ranks) =
+            Self::build_count_ranks(&unigrams.counts, 1, options, &self.progress, self.memory())?;
+        self.tokens = unigrams.tokens;
+        self.counts_builder.append(counts_builder);
+        self.count_ranks.push(count_ranks);
         Ok(())
     }
 
-    fn build_vocabulary(&mut self) -> Result<()> {
-        let records = {
-            let mut gp = self.loaders[0].parser()?;
-            let mut records = Vec::new();
-            while let Some(rec) = gp.next_count_record() {
-                let rec = rec?;
-                records.push(rec);
+    /// Builds the count ranks from the counts of the grams of *N* = `n`.
+    fn build_count_ranks(
+        counts: &IntBuffer,
+        n: usize,
+        options: &BufferOptions,
+        progress: &ProgressReporter,
+        memory: usize,
+    ) -> Result<(CountsBuilder, A)> {
+        let mut phase = progress.start(BuildPhase::Counts(n), memory);
+        let mut counts_builder = CountsBuilder::default();
+        for count in counts {
+            counts_builder.eat_value(count?);
+        }
+        counts_builder.build_sequence();
+
+        let mut count_ranks = options.buffer(counts.len());
+        for count in counts {
+            let count_rank = counts_builder.rank(count?).unwrap();
+            count_ranks.push(count_rank)?;
+            phase.tick();
+        }
+        let count_ranks = if count_ranks.is_spilled() {
+            A::build_from_iter(&count_ranks)?
+        } else {
+            A::build(count_ranks.into_vec()?)
+        };
+        phase.finish();
+        Ok((counts_builder, count_ranks))
+    }
+
+    /// Builds the sorted array of `order` from the scanned level.
+    fn push_level(
+        &mut self,
+        order: usize,
+        level: LevelBuffer,
+        counts_builder: CountsBuilder,
+        count_ranks: A,
+        options: &BufferOptions,
+    ) -> Result<()> {
+        let num_pointers = self.count_ranks[order - 1].len() + 1;
+        let mut pointers = options.buffer(num_pointers);
+        pointers.push(0)?;
+
+        let mut pointer = 0;
+        let mut prefix_ids = level.prefix_ids.iter();
+        let mut pattern = Vec::with_capacity(order);
+        for (num_children, line) in level.num_children.iter().zip(&level.pattern_lines) {
+            let line = line?;
+            pattern.clear();
+            for _ in 0..order {
+                pattern.push(prefix_ids.next().unwrap()?);
+            }
+            // NOTE:
+            // 'pattern' should ALWAYS be found within previous order grams,
+            // and the positions should be increasing in sorted data.
+            let pos = self.find_position(&pattern).ok_or_else(|| {
+                let kind = ValidationErrorKind::MissingPrefix;
+                level.location.error(
+                    line,
+                    join_tokens(&self.tokens, &pattern, level.location.separator),
+                    kind,
+                )
+            })?;
+            if pos + 1 < pointers.len() {
+                let kind = ValidationErrorKind::Unsorted;
+                return Err(level.location.error(
+                    line,
+                    join_tokens(&self.tokens, &pattern, level.location.separator),
+                    kind,
+                ));
             }
-            records
+            while pointers.len() <= pos {
+                pointers.push(pointer)?;
+            }
+            pointer += num_children?;
+            pointers.push(pointer)?;
+        }
+        while pointers.len() < num_pointers {
+            pointers.push(pointer)?;
+        }
+
+        let array = if level.token_ids.is_spilled() || pointers.is_spilled() {
+            T::build_from_iters(&level.token_ids, &pointers)?
+        } else {
+            T::build(level.token_ids.into_vec()?, pointers.into_vec()?)
         };
+        self.arrays.push(array);
+        self.count_ranks.push(count_ranks);
+        self.counts_builder.append(counts_builder);
+        Ok(())
+    }
+
+    /// Finds the position of the gram of token ids in the levels built so far.
+    fn find_position(&self, token_ids: &[usize]) -> Option<usize> {
+        let mut pos = token_ids[0];
+        for (&token_id, array) in token_ids[1..].iter().zip(self.arrays.iter()) {
+            pos = array.find_token(pos, token_id)?;
+        }
+        Some(pos)
+    }
+}
+
+/// Way to scan the sources of *N* = 2, 3, ... in [`LevelsBuilder`].
+trait LevelsScan<S, T, V, A> {
+    /// Scans the sources and builds the levels.
+    fn build_levels(
+        builder: &mut LevelsBuilder<T, V, A>,
+        sources: Vec<S>,
+        options: &BufferOptions,
+    ) -> Result<()>;
+
+    /// Scans the sources without building the levels.
+    fn scan_levels(
+        builder: &LevelsBuilder<T, V, A>,
+        sources: Vec<S>,
+        options: &BufferOptions,
+    ) -> Result<Vec<LevelBuffer>>;
+}
+
+/// Scan of the sources one after another, building each level before the next is scanned.
+struct Sequential;
+
+impl<S, T, V, A> LevelsScan<S, T, V, A> for Sequential
+where
+    S: LevelSource,
+    T: TrieArray,
+    V: Vocabulary,
+    A: RankArray,
+{
+    fn build_levels(
+        builder: &mut LevelsBuilder<T, V, A>,
+        sources: Vec<S>,
+        options: &BufferOptions,
+    ) -> Result<()> {
+        for (i, source) in sources.into_iter().enumerate() {
+            let level = source.scan_level(i + 1, &builder.scan_context(options))?;
+            let (counts_builder, count_ranks) = LevelsBuilder::<T, V, A>::build_count_ranks(
+                &level.counts,
+                i + 2,
+                options,
+                &builder.progress,
+                builder.memory(),
+            )?;
+            builder.push_level(i + 1, level, counts_builder, count_ranks, options)?;
+        }
+        Ok(())
+    }
+
+    fn scan_levels(
+        builder: &LevelsBuilder<T, V, A>,
+        sources: Vec<S>,
+        options: &BufferOptions,
+    ) -> Result<Vec<LevelBuffer>> {
+        let context = builder.scan_context(options);
+        sources
+            .into_iter()
+            .enumerate()
+            .map(|(i, source)| source.scan_level(i + 1, &context))
+            .collect()
+    }
+}
 
-        let grams: Vec<Gram> = records.iter().map(|r| r.gram()).collect();
-        self.vocab = V::build(&grams)?;
+/// Scan of the sources concurrently on the threads of `num_threads` in [`LevelsBuilder`],
+/// where the sources and the scanned levels are sent across the threads.
+struct Parallel;
 
-        let mut count_ranks = Vec::with_capacity(records.len());
-        for rec in &records {
-            let count_rank = self.counts_builder.rank(0, rec.count()).unwrap();
-            count_ranks.push(count_rank);
+impl<S, T, V, A> LevelsScan<S, T, V, A> for Parallel
+where
+    S: LevelSource + Send,
+    T: TrieArray,
+    V: Vocabulary + Sync,
+    A: RankArray + Send,
+{
+    fn build_levels(
+        builder: &mut LevelsBuilder<T, V, A>,
+        sources: Vec<S>,
+        options: &BufferOptions,
+    ) -> Result<()> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(builder.num_threads)
+            .build()?;
+        let context = builder.scan_context(options);
+        let levels = pool.install(|| {
+            sources
+                .into_par_iter()
+                .enumerate()
+                .map(|(i, source)| {
+                    let level = source.scan_level(i + 1, &context)?;
+                    let (counts_builder, count_ranks) =
+                        LevelsBuilder::<T, V, A>::build_count_ranks(
+                            &level.counts,
+                            i + 2,
+                            options,
+                            context.progress,
+                            context.memory,
+                        )?;
+                    Ok((level, counts_builder, count_ranks))
+                })
+                .collect::<Result<Vec<_>>>()
+        })?;
+        for (i, (level, counts_builder, count_ranks)) in levels.into_iter().enumerate() {
+            builder.push_level(i + 1, level, counts_builder, count_ranks, options)?;
         }
-        self.count_ranks.push(A::build(count_ranks));
         Ok(())
     }
 
-    /// Builds the sorted array of `order`.
-    fn build_sorted_array(&mut self, order: usize) -> Result<()> {
-        let mut prev_gp = self.loaders[order - 1].parser()?;
-        let mut curr_gp = self.loaders[order].parser()?;
+    fn scan_levels(
+        builder: &LevelsBuilder<T, V, A>,
+        sources: Vec<S>,
+        options: &BufferOptions,
+    ) -> Result<Vec<LevelBuffer>> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(builder.num_threads)
+            .build()?;
+        let context = builder.scan_context(options);
+        pool.install(|| {
+            sources
+                .into_par_iter()
+                .enumerate()
+                .map(|(i, source)| source.scan_level(i + 1, &context))
+                .collect()
+        })
+    }
+}
 
-        let mut token_ids = Vec::with_capacity(curr_gp.num_grams());
-        let mut count_ranks = Vec::with_capacity(curr_gp.num_grams());
+/// Buffered unigrams, whose tokens are kept in memory to build the vocabulary.
+pub struct UnigramBuffer {
+    // The tokens in the order of the token ids
+    tokens: Vec<String>,
+    // The counts of the tokens
+    counts: IntBuffer,
+    location: RecordsLocation,
+}
 
-        let num_pointers = prev_gp.num_grams() + 1;
-        let mut pointers = Vec::with_capacity(num_pointers);
-        pointers.push(0);
+impl UnigramBuffer {
+    /// Scans the unigrams from the records, checking that each gram is a token.
+    fn from_records<S>(
+        mut records: S,
+        location: RecordsLocation,
+        options: &BufferOptions,
+    ) -> Result<Self>
+    where
+        S: ScanRecords,
+    {
+        let mut tokens = Vec::with_capacity(records.num_remaining());
+        let mut counts = options.buffer(records.num_remaining());
+        while let Some(rec) = records.next_record() {
+            let rec = rec?;
+            let gram = rec.gram().with_separator(location.separator);
+            if gram.split_to_tokens().len() != 1 {
+                let kind = ValidationErrorKind::WrongOrder;
+                return Err(location.error(location.line(tokens.len()), gram.to_string(), kind));
+            }
+            tokens.push(gram.to_string());
+            counts.push(rec.count())?;
+        }
+        Ok(Self {
+            tokens,
+            counts,
+            location,
+        })
+    }
 
-        let mut pointer = 0;
-        let mut prev_rec = prev_gp.next_count_record().unwrap()?;
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+
+    pub const fn counts(&self) -> &IntBuffer {
+        &self.counts
+    }
+
+    /// Keeps the unigrams flagged in `keeps`, returning the mapping from the old token ids
+    /// to the new ones, where the removed tokens are mapped to `usize::MAX`.
+    pub fn retain(self, keeps: &[bool], options: &BufferOptions) -> Result<(Self, Vec<usize>)> {
+        let mut tokens = vec![];
+        let mut counts = options.buffer(0);
+        let mut id_map = vec![usize::MAX; keeps.len()];
+        for (id, (token, count)) in self.tokens.into_iter().zip(&self.counts).enumerate() {
+            let count = count?;
+            if keeps[id] {
+                id_map[id] = tokens.len();
+                tokens.push(token);
+                counts.push(count)?;
+            }
+        }
+        let unigrams = Self {
+            tokens,
+            counts,
+            location: self.location,
+        };
+        Ok((unigrams, id_map))
+    }
+}
+
+// The number of integer sequences buffered for a level,
+// i.e., the five in `LevelBuffer`, pointers, and count ranks.
+const NUM_LEVEL_SEQUENCES: usize = 7;
+
+/// Buffered data of the grams of an order.
+pub struct LevelBuffer {
+    // The ids of the last tokens of grams
+    token_ids: IntBuffer,
+    // The counts of grams
+    counts: IntBuffer,
+    // The token ids of distinct patterns (i.e., predecessors of grams) concatenated
+    prefix_ids: IntBuffer,
+    // The numbers of grams sharing each distinct pattern
+    num_children: IntBuffer,
+    // The line numbers at which each distinct pattern appears first
+    pattern_lines: IntBuffer,
+    location: RecordsLocation,
+}
+
+impl LevelBuffer {
+    fn new(options: &BufferOptions, num_grams: usize, location: RecordsLocation) -> Self {
+        Self {
+            token_ids: options.buffer(num_grams),
+            counts: options.buffer(num_grams),
+            prefix_ids: options.buffer(0),
+            num_children: options.buffer(0),
+            pattern_lines: options.buffer(0),
+            location,
+        }
+    }
 
-        while let Some(curr_rec) = curr_gp.next_count_record() {
+    /// Pushes a gram, where the pattern is given if it differs from the previous one.
+    fn push_gram(
+        &mut self,
+        pattern: Option<(&[usize], usize)>,
+        token_id: usize,
+        count: usize,
+    ) -> Result<()> {
+        if let Some((pattern, line)) = pattern {
+            for &id in pattern {
+                self.prefix_ids.push(id)?;
+            }
+            self.num_children.push(0)?;
+            self.pattern_lines.push(line)?;
+        }
+        *self.num_children.last_mut().unwrap() += 1;
+        self.token_ids.push(token_id)?;
+        self.counts.push(count)
+    }
+
+    pub const fn counts(&self) -> &IntBuffer {
+        &self.counts
+    }
+
+    pub const fn num_grams(&self) -> usize {
+        self.token_ids.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.token_ids.is_empty()
+    }
+
+    /// Iterates the distinct patterns of `order` in the sorted order,
+    /// with the numbers of the grams sharing them and the lines at which they appear first.
+    pub fn patterns(
+        &self,
+        order: usize,
+    ) -> impl Iterator<Item = Result<(Vec<usize>, usize, usize)>> + '_ {
+        let mut prefix_ids = self.prefix_ids.iter();
+        self.num_children.iter().zip(&self.pattern_lines).map(
+            move |(num_children, line)| -> Result<_> {
+                let pattern = (0..order)
+                    .map(|_| prefix_ids.next().unwrap())
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((pattern, num_children?, line?))
+            },
+        )
+    }
+
+    /// Visits the grams of `order` in the sorted order with their token ids,
+    /// whether they start new patterns, and their counts.
+    pub fn for_each_gram<F>(&self, order: usize, mut f: F) -> Result<()>
+    where
+        F: FnMut(&[usize], bool, usize) -> Result<()>,
+    {
+        let mut token_ids = self.token_ids.iter();
+        let mut counts = self.counts.iter();
+        let mut gram = vec![0; order + 1];
+        for pattern in self.patterns(order) {
+            let (pattern, num_children, _) = pattern?;
+            gram[..order].copy_from_slice(&pattern);
+            for i in 0..num_children {
+                gram[order] = token_ids.next().unwrap()?;
+                f(&gram, i == 0, counts.next().unwrap()?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the grams of `order` flagged in `keeps`, mapping the token ids with `id_map`.
+    pub fn retain(
+        self,
+        order: usize,
+        keeps: &IntBuffer,
+        id_map: &[usize],
+        options: &BufferOptions,
+    ) -> Result<Self> {
+        let mut level = Self::new(options, 0, self.location.clone());
+        let mut keeps = keeps.iter();
+        let mut token_ids = self.token_ids.iter();
+        let mut counts = self.counts.iter();
+        let mut pattern = Vec::with_capacity(order);
+        for prev_pattern in self.patterns(order) {
+            let (prev_pattern, num_children, line) = prev_pattern?;
+            pattern.clear();
+            pattern.extend(prev_pattern.iter().map(|&id| id_map[id]));
+            let mut is_new_pattern = true;
+            for _ in 0..num_children {
+                let token_id = token_ids.next().unwrap()?;
+                let count = counts.next().unwrap()?;
+                if keeps.next().unwrap()? != 0 {
+                    let pattern = is_new_pattern.then_some((&pattern[..], line));
+                    level.push_gram(pattern, id_map[token_id], count)?;
+                    is_new_pattern = false;
+                }
+            }
+        }
+        Ok(level)
+    }
+
+    /// Makes the error of the pattern missing in the previous order.
+    pub fn missing_prefix(
+        &self,
+        line: usize,
+        pattern: &[usize],
+        tokens: &[String],
+    ) -> anyhow::Error {
+        let gram = join_tokens(tokens, pattern, self.location.separator);
+        self.location
+            .error(line, gram, ValidationErrorKind::MissingPrefix)
+    }
+
+    /// Scans the grams of `order` (> 0) from the records, validating them.
+    fn from_records<S, V>(
+        mut records: S,
+        order: usize,
+        vocab: &V,
+        location: RecordsLocation,
+        options: &BufferOptions,
+    ) -> Result<Self>
+    where
+        S: ScanRecords,
+        V: Vocabulary,
+    {
+        let mut level = Self::new(options, records.num_remaining(), location);
+        let mut prev_pattern = vec![];
+        let mut pattern_ids = vec![];
+        let mut prev_token_id = 0;
+
+        for i in 0.. {
             // NOTE:
             // in a FORWARD trie, 'pattern' is the predecessor of 'gram'
             // and 'token' is the last token of 'gram'
-            let curr_rec = curr_rec?;
-            let (pattern, token) = curr_rec.gram().pop_token().unwrap(); // TODO: Error handling
-
-            while pattern != prev_rec.gram() {
-                // NOTE:
-                // this test is here only to
-                // guarantee termination in
-                // case of wrong data:
-                // 'pattern' should ALWAYS
-                // be found within previous order grams
-                pointers.push(pointer);
-                if let Some(rec) = prev_gp.next_count_record() {
-                    prev_rec = rec?;
-                } else {
-                    return Err(anyhow!("{}-grams data is incomplete.", order + 1));
+            let rec = match records.next_record() {
+                Some(rec) => rec?,
+                None => break,
+            };
+            let line = level.location.line(i);
+            let error = |kind| level.location.error(line, rec.gram().to_string(), kind);
+            let get_token_id = |token: Gram| {
+                vocab
+                    .get(token)
+                    .ok_or_else(|| error(ValidationErrorKind::UnknownToken(token.to_string())))
+            };
+
+            let (pattern, token) = rec
+                .gram()
+                .with_separator(level.location.separator)
+                .pop_token()
+                .ok_or_else(|| error(ValidationErrorKind::WrongOrder))?;
+            let token_id = get_token_id(token)?;
+
+            let is_new_pattern = i == 0 || pattern.raw() != prev_pattern;
+            if is_new_pattern {
+                let tokens = pattern.split_to_tokens();
+                if tokens.len() != order {
+                    return Err(error(ValidationErrorKind::WrongOrder));
                 }
+                let ids = tokens
+                    .into_iter()
+                    .map(get_token_id)
+                    .collect::<Result<Vec<_>>>()?;
+                if i != 0 && ids < pattern_ids {
+                    return Err(error(ValidationErrorKind::Unsorted));
+                }
+                pattern_ids = ids;
+                prev_pattern.clear();
+                prev_pattern.extend_from_slice(pattern.raw());
+            } else if token_id <= prev_token_id {
+                if token_id == prev_token_id {
+                    return Err(error(ValidationErrorKind::Duplicate));
+                }
+                return Err(error(ValidationErrorKind::Unsorted));
             }
+            prev_token_id = token_id;
 
-            pointer += 1;
-
-            let token_id = self.vocab.get(token).unwrap();
-            let count_rank = self.counts_builder.rank(order, curr_rec.count()).unwrap();
-            token_ids.push(token_id);
-            count_ranks.push(count_rank);
+            let pattern = is_new_pattern.then(|| (&pattern_ids[..], line));
+            level.push_gram(pattern, token_id, rec.count())?;
         }
+        Ok(level)
+    }
 
-        while prev_gp.next_count_record().is_some() {
-            pointers.push(pointer);
-        }
-        pointers.push(pointer);
+    /// Scans the grams of `order` (> 0) from the triplets of the record positions,
+    /// token ids, and counts, validating them.
+    ///
+    /// The grams are described by `describe` in errors.
+    fn from_id_grams<I, G, F>(
+        grams: I,
+        order: usize,
+        num_tokens: usize,
+        location: RecordsLocation,
+        options: &BufferOptions,
+        describe: F,
+    ) -> Result<Self>
+    where
+        I: Iterator<Item = (usize, G, usize)>,
+        G: AsRef<[usize]>,
+        F: Fn(&[usize]) -> String,
+    {
+        let mut level = Self::new(options, grams.size_hint().0, location);
+        let mut prev_pattern = Vec::with_capacity(order);
+        let mut prev_token_id = 0;
 
-        self.arrays.push(T::build(token_ids, pointers));
-        self.count_ranks.push(A::build(count_ranks));
-        Ok(())
+        for (i, (record_id, token_ids, count)) in grams.enumerate() {
+            let token_ids = token_ids.as_ref();
+            let line = level.location.line(record_id);
+            let error = |kind| level.location.error(line, describe(token_ids), kind);
+
+            if token_ids.len() != order + 1 {
+                return Err(error(ValidationErrorKind::WrongOrder));
+            }
+            if let Some(id) = token_ids.iter().find(|&&id| num_tokens <= id) {
+                return Err(error(ValidationErrorKind::UnknownToken(id.to_string())));
+            }
+
+            let (&token_id, pattern) = token_ids.split_last().unwrap();
+            let is_new_pattern = i == 0 || pattern != &prev_pattern[..];
+            if is_new_pattern {
+                if i != 0 && pattern < &prev_pattern[..] {
+                    return Err(error(ValidationErrorKind::Unsorted));
+                }
+                prev_pattern.clear();
+                prev_pattern.extend_from_slice(pattern);
+            } else if token_id <= prev_token_id {
+                if token_id == prev_token_id {
+                    return Err(error(ValidationErrorKind::Duplicate));
+                }
+                return Err(error(ValidationErrorKind::Unsorted));
+            }
+            prev_token_id = token_id;
+
+            level.push_gram(is_new_pattern.then_some((pattern, line)), token_id, count)?;
+        }
+        Ok(level)
     }
 }
 
+/// Builder of the sequences of distinct counts of each order, sorted by frequency.
+///
+/// The memory usage depends on the number of distinct counts rather than that of grams,
+/// and the mapping to ranks is kept only for the current order.
 #[derive(Default)]
 pub struct CountsBuilder {
     // Mapping from eaten values to their frequencies
     v2f_map: HashMap<usize, usize>,
-    // Mappings from eaten values to their ranks
-    v2r_maps: Vec<HashMap<usize, usize>>,
+    // Pairs of values and their ranks of the current order, sorted by the values
+    v2r_pairs: Vec<(usize, usize)>,
     // In which values are sorted in decreasing order of their frequencies
     sorted_sequences: Vec<sucds::CompactVector>,
 }
@@ -165,30 +1571,27 @@ impl CountsBuilder {
         self.sorted_sequences
     }
 
+    /// Appends the sequences built in `other` as those of the next orders,
+    /// dropping the mapping to ranks.
+    pub fn append(&mut self, mut other: Self) {
+        self.sorted_sequences.append(&mut other.sorted_sequences);
+    }
+
     pub fn eat_value(&mut self, x: usize) {
-        if let Some(e) = self.v2f_map.get_mut(&x) {
-            *e += 1;
-        } else {
-            self.v2f_map.insert(x, 1);
-        }
+        *self.v2f_map.entry(x).or_insert(0) += 1;
     }
 
     /// Builds the sequence of the current order.
     pub fn build_sequence(&mut self) {
         if self.v2f_map.is_empty() {
-            self.v2r_maps.push(HashMap::new());
+            self.v2r_pairs.clear();
             self.sorted_sequences.push(sucds::CompactVector::default());
             return;
         }
 
-        let mut sorted = vec![];
-        let mut max_value = 0;
-
-        for (&value, &freq) in &self.v2f_map {
-            sorted.push((value, freq));
-            max_value = std::cmp::max(max_value, value);
-        }
-        self.v2f_map.clear();
+        let mut sorted: Vec<(usize, usize)> = self.v2f_map.drain().collect();
+        let max_value = sorted.iter().map(|&(v, _)| v).max().unwrap();
+        self.v2f_map.shrink_to_fit();
 
         // `then_with` is needed to stably sort
         sorted.sort_by(|(v1, f1), (v2, f2)| f2.cmp(f1).then_with(|| v1.cmp(v2)));
@@ -198,15 +1601,20 @@ impl CountsBuilder {
         sorted.iter().for_each(|&(v, _)| values.push(v));
         self.sorted_sequences.push(values);
 
-        let mut v2r_map = HashMap::new();
-        for (i, &(v, _)) in sorted.iter().enumerate() {
-            v2r_map.insert(v, i);
+        // Reuses the pairs of values and frequencies for those of values and ranks.
+        for (i, pair) in sorted.iter_mut().enumerate() {
+            pair.1 = i;
         }
-        self.v2r_maps.push(v2r_map);
+        sorted.sort_unstable();
+        self.v2r_pairs = sorted;
     }
 
-    pub fn rank(&self, order: usize, value: usize) -> Option<usize> {
-        self.v2r_maps[order].get(&value).copied()
+    /// Gets the rank of the value in the current order.
+    pub fn rank(&self, value: usize) -> Option<usize> {
+        self.v2r_pairs
+            .binary_search_by_key(&value, |&(v, _)| v)
+            .ok()
+            .map(|i| self.v2r_pairs[i].1)
     }
 }
 
@@ -214,24 +1622,434 @@ impl CountsBuilder {
 mod tests {
     use super::*;
 
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::loader::GramsTextLoader;
+    use crate::parser::GramsParser;
+    use crate::{
+        CompactRankTrieCountLm, DoubleArrayVocabulary, EliasFanoRankArray, EliasFanoTrieArray,
+        EliasFanoTrieCountLm, Separators,
+    };
+
+    const GRAMS_1: &str = "3
+A\t3
+B\t2
+C\t1
+";
+
+    const GRAMS_2: &str = "3
+A B\t2
+A C\t1
+C A\t1
+";
+
+    const UNSORTED_GRAMS_2: &str = "3
+A B\t2
+C A\t1
+A C\t1
+";
+
+    struct CountingLoader {
+        loader: GramsTextLoader<'static>,
+        num_opened: Arc<AtomicUsize>,
+    }
+
+    impl GramsLoader<&'static [u8]> for CountingLoader {
+        fn parser(&self) -> Result<GramsParser<&'static [u8]>> {
+            self.num_opened.fetch_add(1, Ordering::SeqCst);
+            self.loader.parser()
+        }
+    }
+
+    fn make_counting_loaders(
+        texts: &[&'static str],
+        num_opened: &Arc<AtomicUsize>,
+    ) -> Vec<Box<dyn GramsLoader<&'static [u8]>>> {
+        let mut loaders = Vec::new();
+        for text in texts {
+            let loader: Box<dyn GramsLoader<_>> = Box::new(CountingLoader {
+                loader: GramsTextLoader::new(text.as_bytes()),
+                num_opened: num_opened.clone(),
+            });
+            loaders.push(loader);
+        }
+        loaders
+    }
+
+    fn make_text_loaders(texts: &[&'static str]) -> Vec<Box<dyn GramsLoader<&'static [u8]>>> {
+        texts
+            .iter()
+            .map(|text| Box::new(GramsTextLoader::new(text.as_bytes())) as Box<dyn GramsLoader<_>>)
+            .collect()
+    }
+
+    /// Checks that the index stores the records of the texts.
+    fn assert_counts<T, V, A>(lm: &TrieCountLm<T, V, A>, texts: &[&str])
+    where
+        T: TrieArray,
+        V: Vocabulary,
+        A: RankArray,
+    {
+        let mut lookuper = lm.lookuper();
+        for rec in texts.iter().flat_map(|text| make_records(text)) {
+            assert_eq!(lookuper.with_gram(rec.gram()), Some(rec.count()));
+        }
+    }
+
+    #[test]
+    fn test_single_pass() {
+        for num_threads in [1, 2] {
+            let num_opened = Arc::new(AtomicUsize::new(0));
+            let loaders = make_counting_loaders(&[GRAMS_1, GRAMS_2], &num_opened);
+            let lm: EliasFanoTrieCountLm = TrieCountLmBuilder::new(loaders)
+                .unwrap()
+                .build_parallel(num_threads)
+                .unwrap();
+            assert_eq!(lm.num_grams(), 6);
+            assert_eq!(num_opened.load(Ordering::SeqCst), 2);
+        }
+    }
+
+    fn build_with_budget<T, V, A>(num_threads: usize, memory_budget: usize) -> TrieCountLm<T, V, A>
+    where
+        T: TrieArray,
+        V: Vocabulary + Sync,
+        A: RankArray + Send,
+    {
+        let loaders = make_text_loaders(&[GRAMS_1, GRAMS_2]);
+        let lm = TrieCountLmBuilder::new(loaders)
+            .unwrap()
+            .memory_budget(memory_budget)
+            .build_parallel(num_threads)
+            .unwrap();
+        assert_eq!(lm.num_grams(), 6);
+        assert_counts(&lm, &[GRAMS_1, GRAMS_2]);
+        lm
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        for num_threads in [1, 2] {
+            let _: CompactRankTrieCountLm = build_with_budget(num_threads, 1);
+
+            // The default index is identical regardless of the budget.
+            let lm: EliasFanoTrieCountLm = build_with_budget(num_threads, 1);
+            let other: EliasFanoTrieCountLm = build_with_budget(num_threads, usize::MAX);
+            let (mut data, mut other_data) = (vec![], vec![]);
+            lm.serialize_into(&mut data).unwrap();
+            other.serialize_into(&mut other_data).unwrap();
+            assert_eq!(data, other_data);
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        for num_threads in [1, 2] {
+            let loaders = make_text_loaders(&[GRAMS_1, GRAMS_2]);
+            let reports = Arc::new(std::sync::Mutex::new(vec![]));
+            let reports_cloned = reports.clone();
+            let lm: EliasFanoTrieCountLm = TrieCountLmBuilder::new(loaders)
+                .unwrap()
+                .progress(move |p| reports_cloned.lock().unwrap().push(p.clone()))
+                .build_parallel(num_threads)
+                .unwrap();
+            assert_eq!(lm.num_grams(), 6);
+
+            let reports = std::mem::take(&mut *reports.lock().unwrap());
+            let mut finished: Vec<(BuildPhase, usize)> = reports
+                .iter()
+                .filter(|p| p.finished)
+                .map(|p| (p.phase, p.num_records))
+                .collect();
+            finished.sort_by_key(|&(phase, _)| phase.to_string());
+            assert_eq!(
+                finished,
+                vec![
+                    (BuildPhase::Level(2), 3),
+                    (BuildPhase::Counts(1), 3),
+                    (BuildPhase::Counts(2), 3),
+                    (BuildPhase::Vocabulary, 3),
+                ]
+            );
+            let level = reports.iter().find(|p| p.phase == BuildPhase::Level(2));
+            assert!(level.unwrap().memory > 0);
+        }
+    }
+
+    #[test]
+    fn test_unsorted() {
+        let loaders = make_text_loaders(&[GRAMS_1, UNSORTED_GRAMS_2]);
+        let result: Result<EliasFanoTrieCountLm> =
+            TrieCountLmBuilder::new(loaders).unwrap().build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sort_grams() {
+        for memory_budget in [None, Some(1)] {
+            let loaders = make_text_loaders(&[GRAMS_1, UNSORTED_GRAMS_2]);
+            let mut builder = TrieCountLmBuilder::new(loaders).unwrap().sort_grams(true);
+            if let Some(memory_budget) = memory_budget {
+                builder = builder.memory_budget(memory_budget);
+            }
+            let lm: EliasFanoTrieCountLm = builder.build().unwrap();
+            assert_eq!(lm.num_grams(), 6);
+            assert_counts(&lm, &[GRAMS_2]);
+        }
+
+        // C A, A B, and A C
+        let bigrams = [2, 0, 0, 1, 0, 2];
+        let sources = vec![
+            GramsSource::records(make_records(GRAMS_1)),
+            GramsSource::token_ids(bigrams.chunks(2).zip([1, 2, 1])),
+        ];
+        let lm: EliasFanoTrieCountLm = TrieCountLmRecordsBuilder::new(sources)
+            .unwrap()
+            .sort_grams(true)
+            .build()
+            .unwrap();
+        assert_counts(&lm, &[GRAMS_2]);
+
+        let loaders = make_text_loaders(&[GRAMS_1, "3\nC A\t1\nA B\t2\nA B\t1\n"]);
+        let result: Result<EliasFanoTrieCountLm> = TrieCountLmBuilder::new(loaders)
+            .unwrap()
+            .sort_grams(true)
+            .build();
+        let e = result.err().unwrap();
+        let e = e.downcast_ref::<ValidationError>().unwrap();
+        assert_eq!((e.line, e.gram.as_str()), (4, "A B"));
+        assert_eq!(e.kind, ValidationErrorKind::Duplicate);
+    }
+
+    #[test]
+    fn test_pruning() {
+        let grams_3 = "2\nA B C\t5\nC A B\t1\n";
+        let build = |min_counts: Vec<usize>, max_grams: Vec<usize>| {
+            let mut data = vec![];
+            let mut lms = vec![];
+            for (num_threads, memory_budget) in [(1, usize::MAX), (2, 1)] {
+                let loaders = make_text_loaders(&[GRAMS_1, GRAMS_2, grams_3]);
+                let lm: EliasFanoTrieCountLm = TrieCountLmBuilder::new(loaders)
+                    .unwrap()
+                    .min_counts(min_counts.clone())
+                    .max_grams(max_grams.clone())
+                    .memory_budget(memory_budget)
+                    .build_parallel(num_threads)
+                    .unwrap();
+                let mut bytes = vec![];
+                lm.serialize_into(&mut bytes).unwrap();
+                data.push(bytes);
+                lms.push(lm);
+            }
+            // The pruned index is built identically with the threads and the memory budget.
+            assert_eq!(data[0], data[1]);
+            lms.swap_remove(0)
+        };
+
+        // "A B" survives as the prefix of "A B C".
+        let lm = build(vec![0, 3, 2], vec![]);
+        assert_eq!(lm.num_orders(), 3);
+        assert_eq!(lm.num_grams(), 5);
+        let mut lookuper = lm.lookuper();
+        for (gram, count) in [("C", Some(1)), ("A B", Some(2)), ("A B C", Some(5))] {
+            assert_eq!(lookuper.with_str(gram), count);
+        }
+        for gram in ["A C", "C A", "C A B"] {
+            assert_eq!(lookuper.with_str(gram), None);
+        }
+
+        // "B" survives as a token of "A B".
+        let lm = build(vec![3], vec![3, 1, 0]);
+        assert_eq!(lm.num_orders(), 2);
+        assert_eq!(lm.num_grams(), 3);
+        let mut lookuper = lm.lookuper();
+        for (gram, count) in [
+            ("A", Some(3)),
+            ("B", Some(2)),
+            ("A B", Some(2)),
+            ("C", None),
+        ] {
+            assert_eq!(lookuper.with_str(gram), count);
+        }
+
+        // The missing prefix of a surviving gram is reported as without pruning.
+        let loaders = make_text_loaders(&[GRAMS_1, GRAMS_2, "2\nA B C\t1\nB A C\t1\n"]);
+        let result: Result<EliasFanoTrieCountLm> = TrieCountLmBuilder::new(loaders)
+            .unwrap()
+            .min_counts(vec![0, 0, 1])
+            .build();
+        let e = result.err().unwrap();
+        let e = e.downcast_ref::<ValidationError>().unwrap();
+        assert_eq!((e.line, e.gram.as_str()), (3, "B A"));
+        assert_eq!(e.kind, ValidationErrorKind::MissingPrefix);
+    }
+
+    fn build_error(texts: &[&'static str]) -> ValidationError {
+        let loaders = make_text_loaders(texts);
+        let result: Result<EliasFanoTrieCountLm> =
+            TrieCountLmBuilder::new(loaders).unwrap().build();
+        let e = result.err().unwrap();
+        e.downcast_ref::<ValidationError>().unwrap().clone()
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let e = build_error(&[GRAMS_1, UNSORTED_GRAMS_2]);
+        assert_eq!((e.line, e.gram.as_str()), (4, "A C"));
+        assert_eq!(e.kind, ValidationErrorKind::Unsorted);
+
+        let e = build_error(&[GRAMS_1, "3\nA B\t2\nA B\t1\nC A\t1\n"]);
+        assert_eq!((e.line, e.gram.as_str()), (3, "A B"));
+        assert_eq!(e.kind, ValidationErrorKind::Duplicate);
+
+        let e = build_error(&[GRAMS_1, "2\nA B\t2\nA D\t1\n"]);
+        assert_eq!((e.line, e.gram.as_str()), (3, "A D"));
+        assert_eq!(e.kind, ValidationErrorKind::UnknownToken("D".to_string()));
+
+        let e = build_error(&[GRAMS_1, "1\nA B C\t1\n"]);
+        assert_eq!(e.line, 2);
+        assert_eq!(e.kind, ValidationErrorKind::WrongOrder);
+
+        let e = build_error(&[GRAMS_1, GRAMS_2, "2\nA B C\t1\nB A C\t1\n"]);
+        assert_eq!((e.line, e.gram.as_str()), (3, "B A"));
+        assert_eq!(e.kind, ValidationErrorKind::MissingPrefix);
+
+        let e = build_error(&["2\nA\t3\nA\t2\n"]);
+        assert_eq!((e.line, e.gram.as_str()), (3, "A"));
+        assert_eq!(e.kind, ValidationErrorKind::Duplicate);
+    }
+
+    #[test]
+    fn test_join_tokens() {
+        let tokens = vec!["A".to_string(), "é".to_string()];
+        assert_eq!(join_tokens(&tokens, &[0, 1], b'|'), "A|é");
+        // A non-ASCII separator is joined as the byte, not as the char of the same code point.
+        assert_eq!(join_tokens(&tokens, &[1, 0], 0xe9), "é\u{fffd}A");
+    }
+
+    #[test]
+    fn test_separators() {
+        // Tokens may contain spaces if they are not the separator.
+        let separators = Separators::new(b'|', b',');
+        let texts = ["2\nA a,3\nB,2\n", "2\nA a|B,2\nB|A a,1\n"];
+        let loaders = texts
+            .iter()
+            .map(|text| {
+                let loader = GramsTextLoader::new(text.as_bytes()).separators(separators);
+                Box::new(loader) as Box<dyn GramsLoader<_>>
+            })
+            .collect();
+        let lm: EliasFanoTrieCountLm = TrieCountLmBuilder::new(loaders).unwrap().build().unwrap();
+        assert_eq!(lm.token_separator(), b'|');
+
+        let mut data = vec![];
+        lm.serialize_into(&mut data).unwrap();
+        assert_eq!(data.len(), lm.size_in_bytes());
+        let lm = EliasFanoTrieCountLm::deserialize_from(&data[..]).unwrap();
+        assert_eq!(lm.token_separator(), b'|');
+
+        let mut lookuper = lm.lookuper();
+        assert_eq!(lookuper.with_str("A a"), Some(3));
+        assert_eq!(lookuper.with_str("A a|B"), Some(2));
+        assert_eq!(lookuper.with_str("B|A a"), Some(1));
+        assert_eq!(lookuper.with_tokens(&["B", "A a"]), Some(1));
+        assert_eq!(lookuper.with_gram(Gram::from_str("B A a")), None);
+        assert_eq!(
+            lookuper.with_gram(Gram::from_str("B_A a").with_separator(b'_')),
+            Some(1)
+        );
+
+        // The files are split with the separators of the loaders, unlike the index.
+        let lm: EliasFanoTrieCountLm =
+            TrieCountLmBuilder::new(make_text_loaders(&[GRAMS_1, GRAMS_2]))
+                .unwrap()
+                .token_separator(b'_')
+                .build()
+                .unwrap();
+        assert_eq!(lm.lookuper().with_str("A_B"), Some(2));
+        assert_eq!(lm.lookuper().with_str("A B"), None);
+    }
+
+    fn make_records(text: &str) -> Vec<CountRecord> {
+        let loader = GramsTextLoader::new(text.as_bytes());
+        let records = loader.parser().unwrap().into_count_records();
+        records.map(|rec| rec.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_records() {
+        let sources = vec![
+            GramsSource::records(make_records(GRAMS_1)),
+            GramsSource::records(make_records(GRAMS_2)),
+        ];
+        let lm: EliasFanoTrieCountLm = TrieCountLmRecordsBuilder::new(sources)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(lm.num_grams(), 6);
+        assert_counts(&lm, &[GRAMS_1, GRAMS_2]);
+    }
+
+    #[test]
+    fn test_token_ids() {
+        // A B, A C, and C A
+        let bigrams = [0, 1, 0, 2, 2, 0];
+        for num_threads in [1, 2] {
+            let sources = vec![
+                GramsSource::records(make_records(GRAMS_1)),
+                GramsSource::token_ids(bigrams.chunks(2).zip([2, 1, 1])),
+            ];
+            let lm: EliasFanoTrieCountLm = TrieCountLmRecordsBuilder::new(sources)
+                .unwrap()
+                .build_parallel(num_threads)
+                .unwrap();
+            assert_eq!(lm.num_grams(), 6);
+            assert_counts(&lm, &[GRAMS_2]);
+        }
+    }
+
+    #[test]
+    fn test_invalid_token_ids() {
+        // Out-of-range token id and wrong number of tokens
+        for (bigrams, len) in [(&[0, 3][..], 2), (&[0, 1, 2][..], 3)] {
+            let sources = vec![
+                GramsSource::records(make_records(GRAMS_1)),
+                GramsSource::token_ids(bigrams.chunks(len).zip([1])),
+            ];
+            let result: Result<EliasFanoTrieCountLm> =
+                TrieCountLmRecordsBuilder::new(sources).unwrap().build();
+            assert!(result.is_err());
+        }
+
+        let unigrams = [0, 1, 2];
+        let sources = vec![GramsSource::token_ids(unigrams.chunks(1).zip([3, 2, 1]))];
+        let result: Result<
+            TrieCountLmRecordsBuilder<
+                EliasFanoTrieArray,
+                DoubleArrayVocabulary,
+                EliasFanoRankArray,
+            >,
+        > = TrieCountLmRecordsBuilder::new(sources);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_basic() {
         let seqs = vec![vec![2, 2, 1, 2, 4, 2, 1, 2, 1], vec![2, 1, 2, 1, 1, 1]];
 
         let mut scb = CountsBuilder::default();
+        let mut ranks = vec![];
         for seq in &seqs {
             for &x in seq {
                 scb.eat_value(x);
             }
             scb.build_sequence();
+            ranks.push((1..=4).map(|x| scb.rank(x)).collect::<Vec<_>>());
         }
 
-        assert_eq!(scb.rank(0, 1), Some(1));
-        assert_eq!(scb.rank(0, 2), Some(0));
-        assert_eq!(scb.rank(0, 3), None);
-        assert_eq!(scb.rank(0, 4), Some(2));
-        assert_eq!(scb.rank(1, 1), Some(0));
-        assert_eq!(scb.rank(1, 2), Some(1));
+        assert_eq!(ranks[0], vec![Some(1), Some(0), None, Some(2)]);
+        assert_eq!(ranks[1], vec![Some(0), Some(1), None, None]);
 
         let counts = scb.release();
         assert_eq!(counts[0].get(0), 2);
@@ -0,0 +1,117 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::rank_array::RankArray;
+use crate::trie_array::TrieArray;
+use crate::trie_count_lm::TrieCountLm;
+use crate::vocabulary::Vocabulary;
+use crate::{
+    util, FinishWrite, Gram, GramsFileFormats, GramsWriter, Separators, GRAM_COUNT_SEPARATOR,
+};
+
+/// Writes the grams of *N* = `n` with their counts into `writer` as a *N*-gram counts file,
+/// in the sorted order of the trie.
+///
+/// The tokens are separated by the separator of the index.
+pub fn dump_order<T, V, A, W>(lm: &TrieCountLm<T, V, A>, n: usize, writer: W) -> Result<()>
+where
+    T: TrieArray,
+    V: Vocabulary,
+    A: RankArray,
+    W: FinishWrite,
+{
+    lm.check_reverse_lookup()?;
+    let separators = Separators::new(lm.separator, GRAM_COUNT_SEPARATOR);
+    let grams = lm.grams(n);
+    let num_grams = lm.count_ranks[n - 1].len();
+    let mut writer = GramsWriter::new(writer, num_grams)?.separators(separators);
+    let mut gram = vec![];
+    for (token_ids, count) in grams {
+        gram.clear();
+        for (i, &id) in token_ids.iter().enumerate() {
+            let token = lm.token(id)?;
+            if i != 0 {
+                gram.push(lm.separator);
+            }
+            gram.extend_from_slice(token.raw());
+        }
+        writer.write_count_record(Gram::new(&gram).with_separator(lm.separator), count)?;
+    }
+    writer.finish()
+}
+
+/// Writes the grams of all the orders into *N*-gram counts files named `<order>-grams.sorted`
+/// (with the extension of `fmt`) in `dirpath`, returning the paths of the files.
+pub fn dump_files<T, V, A, P>(
+    lm: &TrieCountLm<T, V, A>,
+    dirpath: P,
+    fmt: GramsFileFormats,
+) -> Result<Vec<PathBuf>>
+where
+    T: TrieArray,
+    V: Vocabulary,
+    A: RankArray,
+    P: AsRef<Path>,
+{
+    // Fails before any file is created.
+    lm.check_reverse_lookup()?;
+    let mut filepaths = Vec::with_capacity(lm.num_orders());
+    for n in 1..=lm.num_orders() {
+        let mut filename = format!("{}-grams.sorted", n);
+        if let Some(ext) = util::get_format_extension(fmt) {
+            filename = format!("{}.{}", filename, ext);
+        }
+        let filepath = dirpath.as_ref().join(filename);
+        let writer = fmt.encoder(BufWriter::new(File::create(&filepath)?))?;
+        dump_order(lm, n, writer)?;
+        filepaths.push(filepath);
+    }
+    Ok(filepaths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::loader::{GramsLoader, GramsTextLoader};
+    use crate::trie_count_lm::TrieCountLmBuilder;
+    use crate::{EliasFanoTrieCountLm, SimpleTrieCountLm};
+
+    #[test]
+    fn test_dump_order() {
+        // The grams are sorted by the token ids, i.e., the positions in the unigrams,
+        // and the tokens are separated by the separator of the index.
+        let texts = ["3\nB\t3\nA\t2\nC\t1\n", "3\nB_C\t1\nA_B\t2\nA_C\t1\n"];
+        let separators = Separators::new(b'_', b'\t');
+        let loaders: Vec<Box<dyn GramsLoader<_>>> = texts
+            .iter()
+            .map(|text| {
+                let loader = GramsTextLoader::new(text.as_bytes()).separators(separators);
+                Box::new(loader) as Box<dyn GramsLoader<_>>
+            })
+            .collect();
+        let lm: SimpleTrieCountLm = TrieCountLmBuilder::new(loaders).unwrap().build().unwrap();
+        for (n, text) in texts.iter().enumerate() {
+            let mut bytes = vec![];
+            dump_order(&lm, n + 1, &mut bytes).unwrap();
+            assert_eq!(String::from_utf8(bytes).unwrap(), *text);
+        }
+    }
+
+    #[test]
+    fn test_irreversible_vocabulary() {
+        let lm = EliasFanoTrieCountLm::from_texts(vec!["1\nA\t1\n"]).unwrap();
+        let e = dump_order(&lm, 1, &mut vec![]).err().unwrap();
+        assert!(e.to_string().contains("DoubleArrayVocabulary"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let e = dump_files(&lm, dir.path(), GramsFileFormats::Plain)
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("reverse lookup"));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
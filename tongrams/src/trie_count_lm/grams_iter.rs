@@ -0,0 +1,69 @@
+use crate::rank_array::RankArray;
+use crate::trie_array::TrieArray;
+use crate::trie_count_lm::TrieCountLm;
+use crate::vocabulary::Vocabulary;
+
+/// Iterator over the grams of an order in [`TrieCountLm`],
+/// which enumerates the token ids and counts in the sorted order of the trie.
+pub struct TrieCountLmGramsIter<'a, T, V, A> {
+    trie: &'a TrieCountLm<T, V, A>,
+    // The positions of the prefixes of the current gram in each level
+    positions: Vec<usize>,
+    num_grams: usize,
+}
+
+impl<'a, T, V, A> TrieCountLmGramsIter<'a, T, V, A>
+where
+    T: TrieArray,
+    V: Vocabulary,
+    A: RankArray,
+{
+    pub(crate) fn new(trie: &'a TrieCountLm<T, V, A>, order: usize) -> Self {
+        Self {
+            trie,
+            positions: vec![0; order + 1],
+            num_grams: trie.count_ranks[order].len(),
+        }
+    }
+}
+
+impl<T, V, A> Iterator for TrieCountLmGramsIter<'_, T, V, A>
+where
+    T: TrieArray,
+    V: Vocabulary,
+    A: RankArray,
+{
+    type Item = (Vec<usize>, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let order = self.positions.len() - 1;
+        let pos = self.positions[order];
+        if pos >= self.num_grams {
+            return None;
+        }
+        // Moves the prefixes to the parents of the current gram,
+        // which are monotonically increasing in the sorted order.
+        for i in (0..order).rev() {
+            let array = &self.trie.arrays[i];
+            while array.range(self.positions[i]).1 <= self.positions[i + 1] {
+                self.positions[i] += 1;
+            }
+        }
+
+        let mut token_ids = Vec::with_capacity(order + 1);
+        token_ids.push(self.positions[0]);
+        for (array, &pos) in self.trie.arrays.iter().zip(&self.positions[1..]) {
+            token_ids.push(array.token_id(pos));
+        }
+        let count_rank = self.trie.count_ranks[order].get(pos);
+        let count = self.trie.counts[order].get(count_rank);
+
+        self.positions[order] += 1;
+        Some((token_ids, count))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.num_grams - self.positions[self.positions.len() - 1];
+        (remaining, Some(remaining))
+    }
+}
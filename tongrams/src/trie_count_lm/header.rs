@@ -0,0 +1,312 @@
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::rank_array::RankArray;
+use crate::trie_array::TrieArray;
+use crate::vocabulary::Vocabulary;
+
+/// The magic bytes at the beginning of a serialized [`TrieCountLm`](crate::TrieCountLm).
+pub const INDEX_MAGIC: &[u8; 8] = b"TONGRAMS";
+
+/// The version of the format of serialized indexes, which is incremented on incompatible changes.
+pub const INDEX_VERSION: u32 = 1;
+
+/// The maximum length of the type names in the header,
+/// above which the data is regarded as broken.
+const MAX_TYPE_NAME_LEN: usize = 64;
+
+/// Header of a serialized [`TrieCountLm`](crate::TrieCountLm), describing the data following it.
+///
+/// The header consists of the magic bytes [`INDEX_MAGIC`], the format version,
+/// the names of the component types (i.e., [`TrieArray::TYPE_NAME`], [`Vocabulary::TYPE_NAME`],
+/// and [`RankArray::TYPE_NAME`]), the numbers of grams of each order,
+/// and the checksum (64-bit FNV-1a) of the data following the header.
+/// It can be read without knowing the component types with [`IndexHeader::deserialize_from`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexHeader {
+    version: u32,
+    trie_array: String,
+    vocabulary: String,
+    rank_array: String,
+    num_grams: Vec<usize>,
+    checksum: u64,
+}
+
+impl IndexHeader {
+    /// Creates the header of an index of the component types.
+    pub(crate) fn new<T, V, A>(num_grams: Vec<usize>, checksum: u64) -> Self
+    where
+        T: TrieArray,
+        V: Vocabulary,
+        A: RankArray,
+    {
+        Self {
+            version: INDEX_VERSION,
+            trie_array: T::TYPE_NAME.to_string(),
+            vocabulary: V::TYPE_NAME.to_string(),
+            rank_array: A::TYPE_NAME.to_string(),
+            num_grams,
+            checksum,
+        }
+    }
+
+    /// Gets the format version.
+    pub const fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Gets the name of the type of [`TrieArray`].
+    pub fn trie_array(&self) -> &str {
+        &self.trie_array
+    }
+
+    /// Gets the name of the type of [`Vocabulary`].
+    pub fn vocabulary(&self) -> &str {
+        &self.vocabulary
+    }
+
+    /// Gets the name of the type of [`RankArray`].
+    pub fn rank_array(&self) -> &str {
+        &self.rank_array
+    }
+
+    /// Gets the maximum of *N*.
+    pub const fn num_orders(&self) -> usize {
+        self.num_grams.len()
+    }
+
+    /// Gets the numbers of grams of *N* = 1, 2, ...
+    pub fn num_grams(&self) -> &[usize] {
+        &self.num_grams
+    }
+
+    /// Gets the checksum of the data following the header.
+    pub const fn checksum(&self) -> u64 {
+        self.checksum
+    }
+
+    /// Serializes the header into the writer.
+    pub(crate) fn serialize_into<W>(&self, mut writer: W) -> Result<usize>
+    where
+        W: Write,
+    {
+        writer.write_all(INDEX_MAGIC)?;
+        writer.write_u32::<LittleEndian>(self.version)?;
+        for name in [&self.trie_array, &self.vocabulary, &self.rank_array] {
+            writer.write_u32::<LittleEndian>(name.len() as u32)?;
+            writer.write_all(name.as_bytes())?;
+        }
+        writer.write_u64::<LittleEndian>(self.num_grams.len() as u64)?;
+        for &num_grams in &self.num_grams {
+            writer.write_u64::<LittleEndian>(num_grams as u64)?;
+        }
+        writer.write_u64::<LittleEndian>(self.checksum)?;
+        Ok(self.size_in_bytes())
+    }
+
+    /// Deserializes the header from the reader, checking the magic bytes and the version.
+    pub fn deserialize_from<R>(mut reader: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let mut magic = [0; INDEX_MAGIC.len()];
+        if reader.read_exact(&mut magic).is_err() || &magic != INDEX_MAGIC {
+            return Err(anyhow!(
+                "The data is not an index of tongrams, or is serialized by an old version without the header. Please rebuild the index."
+            ));
+        }
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != INDEX_VERSION {
+            return Err(anyhow!(
+                "The index is serialized in format version {}, but version {} is supported.",
+                version,
+                INDEX_VERSION
+            ));
+        }
+        let mut names = Vec::with_capacity(3);
+        for _ in 0..3 {
+            let len = reader.read_u32::<LittleEndian>()? as usize;
+            if len > MAX_TYPE_NAME_LEN {
+                return Err(anyhow!(
+                    "The header has a type name of {} bytes, exceeding the maximum of {} bytes.",
+                    len,
+                    MAX_TYPE_NAME_LEN
+                ));
+            }
+            let mut name = vec![0; len];
+            reader.read_exact(&mut name)?;
+            names.push(
+                String::from_utf8(name)
+                    .map_err(|_| anyhow!("The header has a broken type name."))?,
+            );
+        }
+        let num_orders = reader.read_u64::<LittleEndian>()? as usize;
+        let mut num_grams = Vec::with_capacity(num_orders.min(crate::MAX_ORDER));
+        for _ in 0..num_orders {
+            num_grams.push(reader.read_u64::<LittleEndian>()? as usize);
+        }
+        let checksum = reader.read_u64::<LittleEndian>()?;
+        let rank_array = names.pop().unwrap();
+        let vocabulary = names.pop().unwrap();
+        let trie_array = names.pop().unwrap();
+        Ok(Self {
+            version,
+            trie_array,
+            vocabulary,
+            rank_array,
+            num_grams,
+            checksum,
+        })
+    }
+
+    /// Gets the number of bytes to serialize the header.
+    pub(crate) fn size_in_bytes(&self) -> usize {
+        INDEX_MAGIC.len()
+            + 4
+            + [&self.trie_array, &self.vocabulary, &self.rank_array]
+                .iter()
+                .map(|name| 4 + name.len())
+                .sum::<usize>()
+            + 8 * (self.num_grams.len() + 2)
+    }
+
+    /// Checks that the index consists of the component types.
+    pub(crate) fn check_types<T, V, A>(&self) -> Result<()>
+    where
+        T: TrieArray,
+        V: Vocabulary,
+        A: RankArray,
+    {
+        let expected = [T::TYPE_NAME, V::TYPE_NAME, A::TYPE_NAME];
+        let actual = [self.trie_array(), self.vocabulary(), self.rank_array()];
+        if expected == actual {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "The index consists of {}, but is deserialized as {}.",
+                actual.join(", "),
+                expected.join(", ")
+            ))
+        }
+    }
+}
+
+/// Reader or writer computing the 64-bit FNV-1a checksum of the bytes passing through it.
+pub struct Checksum<I> {
+    inner: I,
+    hash: u64,
+}
+
+impl<I> Checksum<I> {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    pub const fn new(inner: I) -> Self {
+        Self {
+            inner,
+            hash: Self::OFFSET_BASIS,
+        }
+    }
+
+    /// Gets the checksum of the bytes passed so far.
+    pub const fn checksum(&self) -> u64 {
+        self.hash
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.hash = (self.hash ^ u64::from(b)).wrapping_mul(Self::PRIME);
+        }
+    }
+}
+
+impl<I> Read for Checksum<I>
+where
+    I: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<I> Write for Checksum<I>
+where
+    I: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{EliasFanoTrieCountLm, SimpleTrieCountLm};
+
+    fn serialize(lm: &SimpleTrieCountLm) -> Vec<u8> {
+        let mut data = vec![];
+        lm.serialize_into(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_header() {
+        let lm = SimpleTrieCountLm::from_texts(vec!["2\nA\t3\nB\t1\n", "1\nA B\t1\n"]).unwrap();
+        let data = serialize(&lm);
+        let header = IndexHeader::deserialize_from(&data[..]).unwrap();
+        assert_eq!(header.version(), INDEX_VERSION);
+        assert_eq!(header.trie_array(), "SimpleTrieArray");
+        assert_eq!(header.vocabulary(), "SimpleVocabulary");
+        assert_eq!(header.rank_array(), "SimpleRankArray");
+        assert_eq!(header.num_grams(), &[2, 1]);
+        assert_eq!(data.len(), lm.size_in_bytes());
+    }
+
+    fn error<L>(lm: Result<L>) -> String {
+        lm.err().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let lm = SimpleTrieCountLm::from_texts(vec!["2\nA\t3\nB\t1\n", "1\nA B\t1\n"]).unwrap();
+        let data = serialize(&lm);
+        assert!(SimpleTrieCountLm::deserialize_from(&data[..]).is_ok());
+
+        let e = error(EliasFanoTrieCountLm::deserialize_from(&data[..]));
+        assert!(e.contains("EliasFanoTrieArray"));
+
+        let e = error(SimpleTrieCountLm::deserialize_from(&data[1..]));
+        assert!(e.contains("not an index"));
+
+        let mut other = data.clone();
+        other[INDEX_MAGIC.len()] = 2;
+        let e = error(SimpleTrieCountLm::deserialize_from(&other[..]));
+        assert!(e.contains("version 2"));
+
+        let mut other = data.clone();
+        *other.last_mut().unwrap() ^= 1;
+        let e = error(SimpleTrieCountLm::deserialize_from(&other[..]));
+        assert!(e.contains("checksum"));
+
+        let e = error(SimpleTrieCountLm::deserialize_from(&data[..data.len() - 1]));
+        assert!(e.contains("truncated"));
+
+        // A broken length of the type name is rejected before allocating the buffer.
+        let mut other = data;
+        let pos = INDEX_MAGIC.len() + 4;
+        other[pos..pos + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let e = error(IndexHeader::deserialize_from(&other[..]));
+        assert!(e.contains("type name"));
+    }
+}
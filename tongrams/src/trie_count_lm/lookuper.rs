@@ -40,11 +40,11 @@ where
         }
     }
 
-    /// Looks up a gram in which tokens are sparated by a space, (e.g., `"the same time"`)
-    /// returning the count.
+    /// Looks up a gram in which tokens are sparated by the separator of the index
+    /// (a space by default, e.g., `"the same time"`), returning the count.
     #[inline(always)]
     pub fn with_str(&mut self, gram: &str) -> Option<usize> {
-        self.with_gram(Gram::from_str(gram))
+        self.with_gram(Gram::from_str(gram).with_separator(self.trie.separator))
     }
 
     /// Looks up a gram formed by a token list, (e.g., `&["the", "same", "time"]`)
@@ -61,6 +61,9 @@ where
     #[inline(always)]
     fn find(&self) -> Option<usize> {
         let token_ids = self.mapper.get();
+        if token_ids.is_empty() || self.trie.num_orders() < token_ids.len() {
+            return None;
+        }
         let order = token_ids.len() - 1;
         let mut pos = token_ids[0];
         for (&token_id, array) in token_ids[1..].iter().zip(self.trie.arrays.iter()) {
@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::int_buffer::BufferOptions;
+use crate::progress::PhaseProgress;
+use crate::rank_array::RankArray;
+use crate::sorter::{MappedRecord, RecordsSorter};
+use crate::trie_array::TrieArray;
+use crate::trie_count_lm::builder::{LevelBuffer, LevelSource, ScanContext, UnigramBuffer};
+use crate::trie_count_lm::{CountLm, GramsSource, LevelsBuilder, TrieCountLm};
+use crate::vocabulary::Vocabulary;
+use crate::{CountRecord, Gram};
+
+/// Merger of indexes into a new one, without going back to *N*-gram counts files.
+///
+/// The vocabularies are unioned, and the counts of identical grams are summed.
+/// The tokens of the union vocabulary are sorted in decreasing order of their summed counts,
+/// and the grams of each order are re-sorted in the order of the new token ids,
+/// in memory or with temporary files beyond [`TrieCountLmMerger::memory_budget`].
+/// The vocabularies should support the reverse lookup of tokens
+/// (e.g., [`ReversibleDoubleArrayVocabulary`](crate::ReversibleDoubleArrayVocabulary)),
+/// which is checked before merging.
+/// Indexes of different component types can be merged with [`TrieCountLmMerger::merge_any`].
+///
+/// # Examples
+///
+/// ```
+/// use tongrams::trie_count_lm::TrieCountLmMerger;
+/// use tongrams::SimpleTrieCountLm;
+///
+/// let lm1 = SimpleTrieCountLm::from_texts(vec!["2\nA\t3\nB\t1\n", "1\nA B\t1\n"]).unwrap();
+/// let lm2 = SimpleTrieCountLm::from_texts(vec!["2\nC\t2\nA\t1\n", "2\nC A\t2\nA C\t1\n"]).unwrap();
+/// let tmp_dir = tempfile::tempdir().unwrap();
+/// let merged = TrieCountLmMerger::new()
+///     .memory_budget(1 << 10)
+///     .tmp_dir(tmp_dir.path())
+///     .merge(&[&lm1, &lm2])
+///     .unwrap();
+///
+/// let mut lookuper = merged.lookuper();
+/// assert_eq!(lookuper.with_str("A"), Some(4));
+/// assert_eq!(lookuper.with_str("C A"), Some(2));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TrieCountLmMerger {
+    memory_budget: Option<usize>,
+    tmp_dir: Option<PathBuf>,
+}
+
+impl TrieCountLmMerger {
+    /// Creates [`TrieCountLmMerger`] that keeps the grams of an order in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the approximate memory budget in bytes for the grams of an order (default: unlimited),
+    /// beyond which they are sorted externally and the buffered data are spilled to temporary files.
+    pub const fn memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = Some(memory_budget);
+        self
+    }
+
+    /// Sets the directory in which temporary files are placed
+    /// (default: the temporary directory of the system).
+    pub fn tmp_dir<P>(mut self, tmp_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.tmp_dir = Some(tmp_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Merges the indexes.
+    pub fn merge<T, V, A>(&self, lms: &[&TrieCountLm<T, V, A>]) -> Result<TrieCountLm<T, V, A>>
+    where
+        T: TrieArray,
+        V: Vocabulary,
+        A: RankArray,
+    {
+        if lms.is_empty() {
+            return Err(anyhow!("lms must not be empty."));
+        }
+        self.merge_inputs(lms, &[])
+    }
+
+    /// Merges the indexes of any component types into an index of `T`, `V`, and `A`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tongrams::trie_count_lm::{CountLm, TrieCountLmMerger};
+    /// use tongrams::{ReversibleTrieCountLm, SimpleTrieCountLm};
+    ///
+    /// let lm1 = SimpleTrieCountLm::from_texts(vec!["2\nA\t3\nB\t1\n", "1\nA B\t1\n"]).unwrap();
+    /// let lm2 = ReversibleTrieCountLm::from_texts(vec!["2\nC\t2\nA\t1\n", "1\nC A\t2\n"]).unwrap();
+    /// let merged: ReversibleTrieCountLm = TrieCountLmMerger::new()
+    ///     .merge_any(&[&lm1 as &dyn CountLm, &lm2])
+    ///     .unwrap();
+    ///
+    /// let mut lookuper = merged.lookuper();
+    /// assert_eq!(lookuper.with_str("A"), Some(4));
+    /// assert_eq!(lookuper.with_str("C A"), Some(2));
+    /// ```
+    pub fn merge_any<T, V, A>(&self, lms: &[&dyn CountLm]) -> Result<TrieCountLm<T, V, A>>
+    where
+        T: TrieArray,
+        V: Vocabulary,
+        A: RankArray,
+    {
+        if lms.is_empty() {
+            return Err(anyhow!("lms must not be empty."));
+        }
+        self.merge_inputs(lms, &[])
+    }
+
+    /// Merges the indexes and the counts of grams in `deltas`,
+    /// where `deltas[i]` maps the grams of *N* = `i + 1` to the counts added.
+    ///
+    /// The grams in `deltas` should be prefix-closed together with the indexes,
+    /// and their tokens are separated by the separator of the indexes.
+    pub(crate) fn merge_with_deltas<T, V, A>(
+        &self,
+        lms: &[&TrieCountLm<T, V, A>],
+        deltas: &[HashMap<String, usize>],
+    ) -> Result<TrieCountLm<T, V, A>>
+    where
+        T: TrieArray,
+        V: Vocabulary,
+        A: RankArray,
+    {
+        self.merge_inputs(lms, deltas)
+    }
+
+    /// Merges the indexes and the deltas into an index of `T`, `V`, and `A`.
+    fn merge_inputs<L, T, V, A>(
+        &self,
+        lms: &[&L],
+        deltas: &[HashMap<String, usize>],
+    ) -> Result<TrieCountLm<T, V, A>>
+    where
+        L: MergeInput + ?Sized,
+        T: TrieArray,
+        V: Vocabulary,
+        A: RankArray,
+    {
+        let separator = lms[0].separator();
+        if lms.iter().any(|lm| lm.separator() != separator) {
+            return Err(anyhow!(
+                "The token separators of the indexes must be identical."
+            ));
+        }
+        for lm in lms {
+            lm.check_reverse_lookup()?;
+        }
+
+        // Unions the vocabularies, with the mappings from the token ids of each index
+        let mut token_ids = HashMap::new();
+        let mut unigrams: Vec<(String, usize)> = vec![];
+        let mut id_maps = Vec::with_capacity(lms.len());
+        for lm in lms {
+            let mut id_map = vec![];
+            for (ids, count) in lm.id_grams(1) {
+                let token = lm.token(ids[0])?.to_string();
+                let id = *token_ids.entry(token.clone()).or_insert_with(|| {
+                    unigrams.push((token, 0));
+                    unigrams.len() - 1
+                });
+                unigrams[id].1 += count;
+                if id_map.len() <= ids[0] {
+                    id_map.resize(ids[0] + 1, 0);
+                }
+                id_map[ids[0]] = id;
+            }
+            id_maps.push(id_map);
+        }
+        for (token, &count) in deltas.iter().take(1).flatten() {
+            let id = *token_ids.entry(token.clone()).or_insert_with(|| {
+                unigrams.push((token.clone(), 0));
+                unigrams.len() - 1
+            });
+            unigrams[id].1 += count;
+        }
+
+        // Sorts the tokens in decreasing order of the counts and renumbers them.
+        let mut sorted: Vec<usize> = (0..unigrams.len()).collect();
+        sorted.sort_by(|&i, &j| {
+            let ((t1, c1), (t2, c2)) = (&unigrams[i], &unigrams[j]);
+            c2.cmp(c1).then_with(|| t1.cmp(t2))
+        });
+        let mut new_ids = vec![0; sorted.len()];
+        for (new_id, &id) in sorted.iter().enumerate() {
+            new_ids[id] = new_id;
+        }
+        for id in token_ids.values_mut().chain(id_maps.iter_mut().flatten()) {
+            *id = new_ids[*id];
+        }
+        let unigrams = sorted.into_iter().map(move |id| {
+            let (token, count) = std::mem::take(&mut unigrams[id]);
+            CountRecord::new(token, count)
+        });
+
+        let max_order = lms
+            .iter()
+            .map(|lm| lm.num_orders())
+            .chain([deltas.len()])
+            .max()
+            .unwrap();
+        if max_order == 0 {
+            return Err(anyhow!("No grams are given."));
+        }
+
+        let mut sources = Vec::with_capacity(max_order);
+        sources.push(MergeSource::Unigrams(GramsSource::records(unigrams)));
+        for n in 2..=max_order {
+            sources.push(MergeSource::Level(MergedLevel {
+                lms,
+                id_maps: &id_maps,
+                delta: deltas.get(n - 1),
+                token_ids: &token_ids,
+                separator,
+                sorter: self.sorter(n),
+            }));
+        }
+        LevelsBuilder::new()
+            .token_separator(separator)
+            .memory_budget(self.memory_budget)
+            .tmp_dir(self.tmp_dir.clone())
+            .build(sources)
+    }
+
+    /// Gets the sorter of the grams of *N* = `n`.
+    fn sorter(&self, n: usize) -> RecordsSorter {
+        let mut sorter = RecordsSorter::new();
+        if let Some(memory_budget) = self.memory_budget {
+            sorter = sorter.memory_budget(memory_budget, n);
+        }
+        if let Some(tmp_dir) = &self.tmp_dir {
+            sorter = sorter.tmp_dir(tmp_dir);
+        }
+        sorter
+    }
+}
+
+/// Index to merge, regardless of the component types.
+trait MergeInput {
+    fn separator(&self) -> u8;
+
+    fn num_orders(&self) -> usize;
+
+    fn check_reverse_lookup(&self) -> Result<()>;
+
+    fn token(&self, id: usize) -> Result<Gram<'_>>;
+
+    fn id_grams(&self, n: usize) -> Box<dyn Iterator<Item = (Vec<usize>, usize)> + '_>;
+}
+
+impl<T, V, A> MergeInput for TrieCountLm<T, V, A>
+where
+    T: TrieArray,
+    V: Vocabulary,
+    A: RankArray,
+{
+    fn separator(&self) -> u8 {
+        self.separator
+    }
+
+    fn num_orders(&self) -> usize {
+        Self::num_orders(self)
+    }
+
+    fn check_reverse_lookup(&self) -> Result<()> {
+        Self::check_reverse_lookup(self)
+    }
+
+    fn token(&self, id: usize) -> Result<Gram<'_>> {
+        Self::token(self, id)
+    }
+
+    fn id_grams(&self, n: usize) -> Box<dyn Iterator<Item = (Vec<usize>, usize)> + '_> {
+        Box::new(self.grams(n))
+    }
+}
+
+impl MergeInput for dyn CountLm + '_ {
+    fn separator(&self) -> u8 {
+        self.token_separator()
+    }
+
+    fn num_orders(&self) -> usize {
+        CountLm::num_orders(self)
+    }
+
+    fn check_reverse_lookup(&self) -> Result<()> {
+        CountLm::check_reverse_lookup(self)
+    }
+
+    fn token(&self, id: usize) -> Result<Gram<'_>> {
+        CountLm::token(self, id)
+    }
+
+    fn id_grams(&self, n: usize) -> Box<dyn Iterator<Item = (Vec<usize>, usize)> + '_> {
+        self.grams(n)
+    }
+}
+
+/// Source of an order of the merged index.
+enum MergeSource<'a, L: ?Sized> {
+    Unigrams(GramsSource<'a>),
+    Level(MergedLevel<'a, L>),
+}
+
+impl<L> LevelSource for MergeSource<'_, L>
+where
+    L: MergeInput + ?Sized,
+{
+    fn scan_unigrams(
+        self,
+        separator: u8,
+        options: &BufferOptions,
+        phase: &mut PhaseProgress,
+    ) -> Result<UnigramBuffer> {
+        match self {
+            Self::Unigrams(source) => source.scan_unigrams(separator, options, phase),
+            Self::Level(_) => Err(anyhow!("1-grams must be given as count records.")),
+        }
+    }
+
+    fn scan_level<W>(self, order: usize, context: &ScanContext<W>) -> Result<LevelBuffer>
+    where
+        W: Vocabulary,
+    {
+        match self {
+            Self::Unigrams(source) => source.scan_level(order, context),
+            Self::Level(level) => level.scan(order, context),
+        }
+    }
+}
+
+/// Grams of an order in the indexes and the deltas,
+/// which are re-sorted and summed when the order is built.
+struct MergedLevel<'a, L: ?Sized> {
+    lms: &'a [&'a L],
+    id_maps: &'a [Vec<usize>],
+    delta: Option<&'a HashMap<String, usize>>,
+    token_ids: &'a HashMap<String, usize>,
+    separator: u8,
+    sorter: RecordsSorter,
+}
+
+impl<L> MergedLevel<'_, L>
+where
+    L: MergeInput + ?Sized,
+{
+    fn scan<W>(self, order: usize, context: &ScanContext<W>) -> Result<LevelBuffer>
+    where
+        W: Vocabulary,
+    {
+        let n = order + 1;
+        let grams = self
+            .lms
+            .iter()
+            .zip(self.id_maps)
+            .filter(|(lm, _)| n <= lm.num_orders())
+            .flat_map(|(lm, id_map)| {
+                lm.id_grams(n).map(move |(ids, count)| {
+                    let mapped_ids = ids.iter().map(|&id| id_map[id]).collect::<Vec<_>>();
+                    (mapped_ids, count)
+                })
+            });
+        let mut result = Ok(());
+        let delta = self
+            .delta
+            .into_iter()
+            .flatten()
+            .map_while(|(gram, &count)| {
+                let mapped_ids = self.map_gram(gram, n);
+                mapped_ids
+                    .map(|mapped_ids| (mapped_ids, count))
+                    .map_err(|e| result = Err(e))
+                    .ok()
+            });
+        let mapped = grams
+            .chain(delta)
+            .enumerate()
+            .map(|(record_id, (mapped_ids, count))| MappedRecord {
+                mapped_ids,
+                record_id,
+                count,
+            });
+        let sorted = self.sorter.sort(mapped)?;
+        result?;
+        context.scan_id_grams(SummedGrams::new(sorted), order)
+    }
+
+    /// Maps the tokens of a gram in the deltas to the new token ids.
+    fn map_gram(&self, gram: &str, n: usize) -> Result<Vec<usize>> {
+        let mapped_ids = Gram::from_str(gram)
+            .with_separator(self.separator)
+            .split_to_tokens()
+            .iter()
+            .map(|token| {
+                self.token_ids
+                    .get(&token.to_string())
+                    .copied()
+                    .ok_or_else(|| anyhow!("Token {:?} absent from the unigrams.", token))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if mapped_ids.len() != n {
+            return Err(anyhow!("Gram {:?} must consist of {} tokens.", gram, n));
+        }
+        Ok(mapped_ids)
+    }
+}
+
+/// Iterator over the sorted records with the counts of identical grams summed.
+struct SummedGrams<I>
+where
+    I: Iterator<Item = MappedRecord>,
+{
+    records: Peekable<I>,
+}
+
+impl<I> SummedGrams<I>
+where
+    I: Iterator<Item = MappedRecord>,
+{
+    fn new(records: I) -> Self {
+        Self {
+            records: records.peekable(),
+        }
+    }
+}
+
+impl<I> Iterator for SummedGrams<I>
+where
+    I: Iterator<Item = MappedRecord>,
+{
+    type Item = (Vec<usize>, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rec = self.records.next()?;
+        let mut count = rec.count;
+        while let Some(next) = self
+            .records
+            .next_if(|next| next.mapped_ids == rec.mapped_ids)
+        {
+            count += next.count;
+        }
+        Some((rec.mapped_ids, count))
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use crate::rank_array::RankArray;
+use crate::trie_array::TrieArray;
+use crate::trie_count_lm::{TrieCountLm, TrieCountLmMerger};
+use crate::vocabulary::Vocabulary;
+use crate::Gram;
+
+/// Mutable overlay in front of an immutable [`TrieCountLm`] for incremental count updates.
+///
+/// The overlay accepts count increments of existing grams and new grams
+/// (including grams of new tokens), and answers lookups by combining the counts
+/// in the base index and the overlay.
+/// The updates are kept in hash maps until [`TrieCountLmOverlay::compact`] builds a fresh index.
+///
+/// # Examples
+///
+/// ```
+/// use tongrams::trie_count_lm::TrieCountLmOverlay;
+/// use tongrams::ReversibleTrieCountLm;
+///
+/// let lm = ReversibleTrieCountLm::from_texts(vec!["2\nA\t3\nB\t1\n", "1\nA B\t1\n"]).unwrap();
+/// let mut overlay = TrieCountLmOverlay::new(lm);
+/// overlay.add_count("A B", 2).unwrap();
+/// overlay.add_count("C", 1).unwrap();
+/// overlay.add_count("C A", 1).unwrap();
+///
+/// assert_eq!(overlay.with_str("A B"), Some(3));
+/// assert_eq!(overlay.with_str("C A"), Some(1));
+///
+/// let lm = overlay.compact().unwrap();
+/// let mut lookuper = lm.lookuper();
+/// assert_eq!(lookuper.with_str("A B"), Some(3));
+/// assert_eq!(lookuper.with_str("C A"), Some(1));
+/// ```
+pub struct TrieCountLmOverlay<T, V, A> {
+    base: TrieCountLm<T, V, A>,
+    // `deltas[i]` maps the grams of N = i + 1 to the counts added
+    deltas: Vec<HashMap<String, usize>>,
+}
+
+impl<T, V, A> TrieCountLmOverlay<T, V, A>
+where
+    T: TrieArray,
+    V: Vocabulary,
+    A: RankArray,
+{
+    /// Creates an empty overlay in front of `base`.
+    pub const fn new(base: TrieCountLm<T, V, A>) -> Self {
+        Self {
+            base,
+            deltas: vec![],
+        }
+    }
+
+    /// Gets the reference to the base index.
+    pub const fn base(&self) -> &TrieCountLm<T, V, A> {
+        &self.base
+    }
+
+    /// Adds `count` to the count of a gram in which tokens are separated by the separator
+    /// of the base index (a space by default), inserting the gram if it does not exist.
+    ///
+    /// The grams should be prefix-closed as in *N*-gram counts files,
+    /// i.e., the gram without the last token should already exist in the base index or the overlay.
+    /// A gram with a new token can be added after the token is added as a unigram.
+    pub fn add_count(&mut self, gram: &str, count: usize) -> Result<()> {
+        let parsed = self.gram(gram);
+        let tokens = parsed.split_to_tokens();
+        if tokens.iter().any(|token| token.raw().is_empty()) {
+            return Err(anyhow!("Gram {:?} has an empty token.", gram));
+        }
+        if let Some((prefix, _)) = parsed.pop_token() {
+            if self.with_gram(prefix).is_none() {
+                return Err(anyhow!(
+                    "The prefix of gram {:?} must be added in advance.",
+                    gram
+                ));
+            }
+        }
+        let order = tokens.len() - 1;
+        if self.deltas.len() <= order {
+            self.deltas.resize_with(order + 1, HashMap::new);
+        }
+        *self.deltas[order].entry(gram.to_string()).or_insert(0) += count;
+        Ok(())
+    }
+
+    /// Looks up a gram, returning the count combining the base index and the overlay.
+    pub fn with_gram(&self, gram: Gram) -> Option<usize> {
+        let base = self.base.lookuper().with_gram(gram);
+        let tokens = gram.split_to_tokens();
+        // A gram of invalid UTF-8 cannot be added, i.e., is only in the base index.
+        let delta = self
+            .deltas
+            .get(tokens.len() - 1)
+            .zip(std::str::from_utf8(gram.raw()).ok())
+            .and_then(|(delta, gram)| delta.get(gram).copied());
+        match (base, delta) {
+            (Some(base), Some(delta)) => Some(base + delta),
+            (base, delta) => base.or(delta),
+        }
+    }
+
+    /// Looks up a gram in which tokens are sparated by the separator of the base index
+    /// (a space by default, e.g., `"the same time"`),
+    /// returning the count combining the base index and the overlay.
+    pub fn with_str(&self, gram: &str) -> Option<usize> {
+        self.with_gram(self.gram(gram))
+    }
+
+    /// Looks up a gram formed by a token list, (e.g., `&["the", "same", "time"]`)
+    /// returning the count combining the base index and the overlay.
+    pub fn with_tokens(&self, tokens: &[&str]) -> Option<usize> {
+        let separator = self.base.separator;
+        let mut gram = Vec::new();
+        for (i, token) in tokens.iter().enumerate() {
+            if i != 0 {
+                gram.push(separator);
+            }
+            gram.extend_from_slice(token.as_bytes());
+        }
+        self.with_gram(Gram::new(&gram).with_separator(separator))
+    }
+
+    /// Gets the number of grams added or updated in the overlay.
+    pub fn num_deltas(&self) -> usize {
+        self.deltas.iter().map(|delta| delta.len()).sum()
+    }
+
+    /// Makes a [`Gram`] split with the separator of the base index.
+    const fn gram<'a>(&self, gram: &'a str) -> Gram<'a> {
+        Gram::from_str(gram).with_separator(self.base.separator)
+    }
+
+    /// Builds a fresh static index from the base index and the overlay,
+    /// for which the vocabulary should support the reverse lookup of tokens
+    /// (e.g., [`ReversibleDoubleArrayVocabulary`](crate::ReversibleDoubleArrayVocabulary)).
+    pub fn compact(&self) -> Result<TrieCountLm<T, V, A>> {
+        TrieCountLmMerger::new().merge_with_deltas(&[&self.base], &self.deltas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::loader::{GramsLoader, GramsTextLoader};
+    use crate::trie_count_lm::TrieCountLmBuilder;
+    use crate::{ReversibleTrieCountLm, Separators};
+
+    const GRAMS_1: &str = "3
+A\t3
+B\t2
+C\t1
+";
+
+    const GRAMS_2: &str = "3
+A B\t2
+A C\t1
+C A\t1
+";
+
+    #[test]
+    fn test_overlay() {
+        let lm = ReversibleTrieCountLm::from_texts(vec![GRAMS_1, GRAMS_2]).unwrap();
+        let mut overlay = TrieCountLmOverlay::new(lm);
+
+        overlay.add_count("A B", 3).unwrap();
+        overlay.add_count("D", 4).unwrap();
+        overlay.add_count("D A", 1).unwrap();
+        overlay.add_count("D A B", 1).unwrap();
+        overlay.add_count("A B", 1).unwrap();
+        assert!(overlay.add_count("E A", 1).is_err());
+        assert!(overlay.add_count("B C A", 1).is_err());
+        assert!(overlay.add_count("A  B", 1).is_err());
+        assert_eq!(overlay.num_deltas(), 4);
+
+        let expected = [
+            ("A", Some(3)),
+            ("D", Some(4)),
+            ("A B", Some(6)),
+            ("A C", Some(1)),
+            ("D A", Some(1)),
+            ("D A B", Some(1)),
+            ("B A", None),
+            ("E", None),
+        ];
+        for (gram, count) in expected {
+            assert_eq!(overlay.with_str(gram), count);
+        }
+        assert_eq!(overlay.with_tokens(&["D", "A"]), Some(1));
+
+        let lm = overlay.compact().unwrap();
+        assert_eq!(lm.num_orders(), 3);
+        assert_eq!(lm.num_grams(), 9);
+        let mut lookuper = lm.lookuper();
+        for (gram, count) in expected {
+            assert_eq!(lookuper.with_str(gram), count);
+        }
+    }
+
+    #[test]
+    fn test_separator() {
+        let separators = Separators::new(b'|', b'\t');
+        let texts = ["2\nA\t3\nB\t1\n", "1\nA|B\t1\n"];
+        let loaders = texts
+            .iter()
+            .map(|text| {
+                let loader = GramsTextLoader::new(text.as_bytes()).separators(separators);
+                Box::new(loader) as Box<dyn GramsLoader<_>>
+            })
+            .collect();
+        let lm: ReversibleTrieCountLm = TrieCountLmBuilder::new(loaders).unwrap().build().unwrap();
+        let mut overlay = TrieCountLmOverlay::new(lm);
+        overlay.add_count("B|A", 2).unwrap();
+        assert_eq!(overlay.with_tokens(&["A", "B"]), Some(1));
+        assert_eq!(overlay.with_tokens(&["B", "A"]), Some(2));
+        assert_eq!(overlay.with_tokens(&["A B"]), None);
+    }
+}
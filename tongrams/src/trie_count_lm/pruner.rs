@@ -0,0 +1,227 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::iter::Peekable;
+
+use anyhow::Result;
+
+use crate::int_buffer::{BufferOptions, IntBuffer};
+use crate::trie_count_lm::builder::{LevelBuffer, UnigramBuffer};
+
+/// Prunes the buffered grams of *N* = 1, 2, ... by counts before the trie is built,
+/// keeping it prefix-closed.
+///
+/// A gram of *N* = `i + 1` is selected if its count is no less than `min_counts[i]`
+/// and it is within the `max_grams[i]` most frequent grams (ties are broken by the trie order),
+/// where the missing thresholds are not applied.
+/// A gram survives if it is selected or any extension of it survives,
+/// and a unigram also survives if its token appears in the surviving grams.
+/// The survival is propagated from the highest order to the lowest
+/// in a pass over the buffers of each order, which can be spilled.
+/// The orders without surviving grams at the end are removed.
+pub fn prune(
+    unigrams: UnigramBuffer,
+    levels: Vec<LevelBuffer>,
+    min_counts: &[usize],
+    max_grams: &[usize],
+    options: &BufferOptions,
+) -> Result<(UnigramBuffer, Vec<LevelBuffer>)> {
+    let tokens = unigrams.tokens();
+    // Whether each unigram survives, which is first marked for the tokens of surviving grams
+    let mut unigram_keeps = vec![false; tokens.len()];
+    // Whether each gram survives, pushed from the highest order
+    let mut keeps: Vec<IntBuffer> = Vec::with_capacity(levels.len());
+    // Whether each pattern of the order scanned last has surviving grams
+    let mut pattern_keeps: Option<IntBuffer> = None;
+
+    for (i, level) in levels.iter().enumerate().rev() {
+        let order = i + 1;
+        let mut selector = CountSelector::new(
+            level.counts(),
+            min_counts.get(order).copied().unwrap_or(0),
+            max_grams.get(order).copied(),
+        )?;
+        let mut children = pattern_keeps
+            .as_ref()
+            .map(|flags| ChildCursor::new(&levels[i + 1], order + 1, flags, tokens));
+        let mut keep = options.buffer(level.num_grams());
+        let mut flags = options.buffer(0);
+        level.for_each_gram(order, |gram, is_new_pattern, count| {
+            let selected = selector.select(count);
+            let has_child = match children.as_mut() {
+                Some(children) => children.has_surviving_child(gram)?,
+                None => false,
+            };
+            if is_new_pattern {
+                flags.push(0)?;
+            }
+            if selected || has_child {
+                *flags.last_mut().unwrap() = 1;
+                unigram_keeps[gram[order]] = true;
+            }
+            keep.push(usize::from(selected || has_child))
+        })?;
+        if let Some(children) = children {
+            children.finish()?;
+        }
+        keeps.push(keep);
+        pattern_keeps = Some(flags);
+    }
+
+    let mut selector = CountSelector::new(
+        unigrams.counts(),
+        min_counts.first().copied().unwrap_or(0),
+        max_grams.first().copied(),
+    )?;
+    let mut children = pattern_keeps
+        .as_ref()
+        .map(|flags| ChildCursor::new(&levels[0], 1, flags, tokens));
+    for (id, count) in unigrams.counts().iter().enumerate() {
+        let selected = selector.select(count?);
+        let has_child = match children.as_mut() {
+            Some(children) => children.has_surviving_child(&[id])?,
+            None => false,
+        };
+        unigram_keeps[id] |= selected || has_child;
+    }
+    if let Some(children) = children {
+        children.finish()?;
+    }
+
+    let (unigrams, id_map) = unigrams.retain(&unigram_keeps, options)?;
+    let mut pruned = Vec::with_capacity(levels.len());
+    for (i, (level, keep)) in levels.into_iter().zip(keeps.iter().rev()).enumerate() {
+        pruned.push(level.retain(i + 1, keep, &id_map, options)?);
+    }
+    while pruned.last().is_some_and(LevelBuffer::is_empty) {
+        pruned.pop();
+    }
+    Ok((unigrams, pruned))
+}
+
+/// Selector of the grams of an order by counts, which are given in the trie order.
+struct CountSelector {
+    // The grams with counts greater than the threshold are selected.
+    threshold: usize,
+    // The number of the grams still selected with counts equal to the threshold
+    num_ties: usize,
+}
+
+impl CountSelector {
+    /// Creates the selector of the grams with counts no less than `min_count`,
+    /// limited to the `max_grams` most frequent ones if given.
+    ///
+    /// For the limit, the distinct counts and their frequencies are collected
+    /// in a pass over the buffered counts.
+    fn new(counts: &IntBuffer, min_count: usize, max_grams: Option<usize>) -> Result<Self> {
+        let mut selector = Self {
+            threshold: min_count,
+            num_ties: usize::MAX,
+        };
+        let max_grams = match max_grams {
+            Some(max_grams) => max_grams,
+            None => return Ok(selector),
+        };
+
+        let mut freqs = HashMap::new();
+        for count in counts {
+            let count = count?;
+            if min_count <= count {
+                *freqs.entry(count).or_insert(0) += 1;
+            }
+        }
+        let mut freqs: Vec<(usize, usize)> = freqs.into_iter().collect();
+        freqs.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut num_selected = 0;
+        for (count, freq) in freqs {
+            if max_grams <= num_selected + freq {
+                selector = Self {
+                    threshold: count,
+                    num_ties: max_grams - num_selected,
+                };
+                break;
+            }
+            num_selected += freq;
+        }
+        Ok(selector)
+    }
+
+    /// Checks if the next gram in the trie order is selected.
+    const fn select(&mut self, count: usize) -> bool {
+        if self.threshold < count {
+            return true;
+        }
+        if self.threshold == count && self.num_ties != 0 {
+            self.num_ties -= 1;
+            return true;
+        }
+        false
+    }
+}
+
+// The patterns of an order with their lines and the flags whether they have surviving grams
+type FlaggedPatterns<'a> = Box<dyn Iterator<Item = Result<(Vec<usize>, usize, bool)>> + 'a>;
+
+/// Cursor over the flagged patterns of an order,
+/// which are looked up with the grams of the previous order in the trie order.
+struct ChildCursor<'a> {
+    patterns: Peekable<FlaggedPatterns<'a>>,
+    level: &'a LevelBuffer,
+    tokens: &'a [String],
+}
+
+impl<'a> ChildCursor<'a> {
+    fn new(
+        level: &'a LevelBuffer,
+        order: usize,
+        flags: &'a IntBuffer,
+        tokens: &'a [String],
+    ) -> Self {
+        let patterns = level
+            .patterns(order)
+            .zip(flags)
+            .map(|(pattern, flag)| -> Result<_> {
+                let (pattern, _, line) = pattern?;
+                Ok((pattern, line, flag? != 0))
+            });
+        let patterns: FlaggedPatterns<'a> = Box::new(patterns);
+        Self {
+            patterns: patterns.peekable(),
+            level,
+            tokens,
+        }
+    }
+
+    /// Checks if the gram has surviving extensions,
+    /// where the patterns before it should have no surviving grams.
+    fn has_surviving_child(&mut self, gram: &[usize]) -> Result<bool> {
+        while let Some(next) = self.patterns.peek() {
+            let ordering = match next {
+                Ok((pattern, _, _)) => pattern[..].cmp(gram),
+                Err(_) => Ordering::Less,
+            };
+            if ordering == Ordering::Greater {
+                break;
+            }
+            let (pattern, line, flag) = self.patterns.next().unwrap()?;
+            if ordering == Ordering::Equal {
+                return Ok(flag);
+            }
+            if flag {
+                return Err(self.level.missing_prefix(line, &pattern, self.tokens));
+            }
+        }
+        Ok(false)
+    }
+
+    /// Checks that the remaining patterns have no surviving grams.
+    fn finish(self) -> Result<()> {
+        for pattern in self.patterns {
+            let (pattern, line, flag) = pattern?;
+            if flag {
+                return Err(self.level.missing_prefix(line, &pattern, self.tokens));
+            }
+        }
+        Ok(())
+    }
+}
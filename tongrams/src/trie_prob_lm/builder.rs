@@ -1,13 +1,17 @@
+use std::collections::HashSet;
 use std::io::Read;
+use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
 
 use super::TrieProbLm;
 use crate::loader::GramsLoader;
+use crate::progress::{BuildPhase, BuildProgress, ProgressReporter};
 use crate::trie_array::TrieArray;
+use crate::trie_count_lm::RecordsLocation;
+use crate::validation::ValidationErrorKind;
 use crate::vocabulary::Vocabulary;
 use crate::Gram;
-use crate::MAX_ORDER;
 
 pub struct TrieProbLmBuilder<R, T, V> {
     loaders: Vec<Box<dyn GramsLoader<R>>>,
@@ -15,6 +19,7 @@ pub struct TrieProbLmBuilder<R, T, V> {
     arrays: Vec<T>,
     probs: Vec<Vec<f32>>,    // TODO: Quantize
     backoffs: Vec<Vec<f32>>, // TODO: Quantize
+    progress: ProgressReporter,
 }
 
 impl<R, T, V> TrieProbLmBuilder<R, T, V>
@@ -24,8 +29,8 @@ where
     V: Vocabulary,
 {
     pub fn new(loaders: Vec<Box<dyn GramsLoader<R>>>) -> Result<Self> {
-        if MAX_ORDER < loaders.len() {
-            return Err(anyhow!("loaders.len() must be no more than {}", MAX_ORDER));
+        if loaders.is_empty() {
+            return Err(anyhow!("loaders must not be empty."));
         }
         Ok(Self {
             loaders,
@@ -33,9 +38,22 @@ where
             arrays: vec![],
             probs: vec![],
             backoffs: vec![],
+            progress: ProgressReporter::default(),
         })
     }
 
+    /// Sets the callback receiving the progress of the construction (default: none).
+    ///
+    /// The progress of the vocabulary and the grams of each order
+    /// is reported periodically and at the end of the phase.
+    pub fn progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&BuildProgress) + Send + Sync + 'static,
+    {
+        self.progress = ProgressReporter::new(Arc::new(callback));
+        self
+    }
+
     pub fn build(mut self) -> Result<TrieProbLm<T, V>> {
         self.build_vocabulary()?;
 
@@ -53,15 +71,25 @@ where
     }
 
     fn build_vocabulary(&mut self) -> Result<()> {
-        let records = {
-            let mut gp = self.loaders[0].parser()?;
-            let mut records = Vec::new();
-            while let Some(rec) = gp.next_prob_record() {
-                let rec = rec?;
+        let mut phase = self.progress.start(BuildPhase::Vocabulary, 0);
+        let mut gp = self.loaders[0].parser()?;
+        let location = RecordsLocation::file(&gp);
+        let mut records = Vec::new();
+        let mut tokens = HashSet::new();
+        while let Some(rec) = gp.next_prob_record() {
+            let rec = rec?;
+            let gram = rec.gram().with_separator(location.separator());
+            let kind = if gram.split_to_tokens().len() != 1 {
+                ValidationErrorKind::WrongOrder
+            } else if !tokens.insert(gram.to_string()) {
+                ValidationErrorKind::Duplicate
+            } else {
                 records.push(rec);
-            }
-            records
-        };
+                phase.tick();
+                continue;
+            };
+            return Err(location.error(location.line(records.len()), gram.to_string(), kind));
+        }
 
         let grams: Vec<Gram> = records.iter().map(|r| r.gram()).collect();
         self.vocab = V::build(&grams)?;
@@ -75,73 +103,199 @@ where
         }
         self.probs.push(probs);
         self.backoffs.push(backoffs);
+        phase.finish();
 
         Ok(())
     }
 
     /// Builds the sorted array of `order`.
     fn build_sorted_array(&mut self, order: usize) -> Result<()> {
-        let mut prev_gp = self.loaders[order - 1].parser()?;
-        let mut curr_gp = self.loaders[order].parser()?;
+        let mut phase = self
+            .progress
+            .start(BuildPhase::Level(order + 1), self.memory());
+        let mut gp = self.loaders[order].parser()?;
+        let location = RecordsLocation::file(&gp);
 
-        let mut token_ids = Vec::with_capacity(curr_gp.num_grams());
+        let mut token_ids = Vec::with_capacity(gp.num_grams());
         let mut probs = vec![];
         let mut backoffs = vec![];
 
-        let num_pointers = prev_gp.num_grams() + 1;
+        let num_pointers = self.probs[order - 1].len() + 1;
         let mut pointers = Vec::with_capacity(num_pointers);
         pointers.push(0);
 
         let mut pointer = 0;
-        let mut prev_rec = prev_gp.next_prob_record().unwrap()?;
+        let mut gram_ids = Vec::with_capacity(order + 1);
+
+        while let Some(rec) = gp.next_prob_record_ref() {
+            let rec = rec?;
+            let gram = rec.gram().with_separator(location.separator());
+            let line = location.line(token_ids.len());
+            let error = |kind| location.error(line, gram.to_string(), kind);
+
+            let tokens = gram.split_to_tokens();
+            if tokens.len() != order + 1 {
+                return Err(error(ValidationErrorKind::WrongOrder));
+            }
+            let (_, pattern) = gram
+                .pop_front_token()
+                .ok_or_else(|| error(ValidationErrorKind::WrongOrder))?;
+            gram_ids.clear();
+            for token in tokens {
+                let token_id = self
+                    .vocab
+                    .get(token)
+                    .ok_or_else(|| error(ValidationErrorKind::UnknownToken(token.to_string())))?;
+                gram_ids.push(token_id);
+            }
 
-        while let Some(curr_rec) = curr_gp.next_prob_record() {
             // NOTE:
             // in a BACKWARD trie, 'pattern' is the suffix of 'gram'
-            // and 'token' is the first token of 'gram'
-            let curr_rec = curr_rec?;
-            let (token, pattern) = curr_rec.gram().pop_front_token().unwrap(); // TODO: Error handling
-
-            while pattern != prev_rec.gram() {
-                // NOTE:
-                // this test is here only to
-                // guarantee termination in
-                // case of wrong data:
-                // 'pattern' should ALWAYS
-                // be found within previous order grams
-                pointers.push(pointer);
-                if let Some(rec) = prev_gp.next_prob_record() {
-                    prev_rec = rec?;
-                } else {
-                    return Err(anyhow!("{}-grams data is incomplete.", order + 1));
+            // and 'token' is the first token of 'gram'.
+            // 'pattern' should ALWAYS be found within previous order grams,
+            // and the positions should be increasing in sorted data.
+            let token_id = gram_ids[0];
+            let pos = self.find_position(&gram_ids[1..]).ok_or_else(|| {
+                let kind = ValidationErrorKind::MissingPrefix;
+                location.error(line, pattern.to_string(), kind)
+            })?;
+            if pos + 1 < pointers.len() {
+                return Err(error(ValidationErrorKind::Unsorted));
+            }
+            if pos + 1 == pointers.len() && pointers[pos] < pointer {
+                let last_id = token_ids[pointer - 1];
+                if token_id == last_id {
+                    return Err(error(ValidationErrorKind::Duplicate));
+                } else if token_id < last_id {
+                    return Err(error(ValidationErrorKind::Unsorted));
                 }
             }
+            while pointers.len() <= pos {
+                pointers.push(pointer);
+            }
 
             pointer += 1;
 
-            let token_id = self.vocab.get(token).unwrap();
             token_ids.push(token_id);
-            probs.push(curr_rec.prob());
+            probs.push(rec.prob());
             if order < self.max_order() {
-                backoffs.push(curr_rec.backoff());
+                backoffs.push(rec.backoff());
             }
+            phase.tick();
         }
 
-        while prev_gp.next_count_record().is_some() {
+        while pointers.len() < num_pointers {
             pointers.push(pointer);
         }
-        pointers.push(pointer);
 
         self.arrays.push(T::build(token_ids, pointers));
         self.probs.push(probs);
         if order < self.max_order() {
             self.backoffs.push(backoffs);
         }
+        phase.finish();
 
         Ok(())
     }
 
+    /// Finds the position of the pattern, i.e., the suffix of a gram, in the levels built so far.
+    fn find_position(&self, token_ids: &[usize]) -> Option<usize> {
+        let (&token_id, token_ids) = token_ids.split_last()?;
+        let mut pos = token_id;
+        for (&token_id, array) in token_ids.iter().rev().zip(self.arrays.iter()) {
+            pos = array.find_token(pos, token_id)?;
+        }
+        Some(pos)
+    }
+
+    /// Gets the bytes of the data structures built so far if the progress is reported.
+    fn memory(&self) -> usize {
+        if !self.progress.is_enabled() {
+            return 0;
+        }
+        let num_values: usize = self
+            .probs
+            .iter()
+            .chain(&self.backoffs)
+            .map(|v| v.len())
+            .sum();
+        self.vocab.size_in_bytes()
+            + self.arrays.iter().map(|a| a.size_in_bytes()).sum::<usize>()
+            + num_values * std::mem::size_of::<f32>()
+    }
+
     fn max_order(&self) -> usize {
         self.loaders.len() - 1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{SimpleTrieProbLm, ValidationError};
+
+    const GRAMS_1: &str = "3
+A\t-1.83\t-0.74
+B\t-2.01\t-0.69
+C\t-2.22\t-0.55
+";
+
+    const GRAMS_2: &str = "3
+C A\t-1.39\t-0.49
+A B\t-1.23\t-0.41
+C B\t-1.11\t-0.29
+";
+
+    fn build_error(texts: Vec<&'static str>) -> ValidationError {
+        let e = SimpleTrieProbLm::from_texts(texts).err().unwrap();
+        e.downcast_ref::<ValidationError>().unwrap().clone()
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        assert!(SimpleTrieProbLm::from_texts(vec![GRAMS_1, GRAMS_2, "1\nA C B\t0.5\n"]).is_ok());
+
+        let e = build_error(vec![GRAMS_1, "2\nA B\t-1.2\nC A\t-1.3\n"]);
+        assert_eq!((e.line, e.gram.as_str()), (3, "C A"));
+        assert_eq!(e.kind, ValidationErrorKind::Unsorted);
+
+        let e = build_error(vec![GRAMS_1, "2\nC A\t-1.2\nA A\t-1.3\n"]);
+        assert_eq!((e.line, e.gram.as_str()), (3, "A A"));
+        assert_eq!(e.kind, ValidationErrorKind::Unsorted);
+
+        let e = build_error(vec![GRAMS_1, "2\nC A\t-1.2\nC A\t-1.3\n"]);
+        assert_eq!((e.line, e.gram.as_str()), (3, "C A"));
+        assert_eq!(e.kind, ValidationErrorKind::Duplicate);
+
+        let e = build_error(vec![GRAMS_1, "2\nC A\t-1.2\nD B\t-1.3\n"]);
+        assert_eq!((e.line, e.gram.as_str()), (3, "D B"));
+        assert_eq!(e.kind, ValidationErrorKind::UnknownToken("D".to_string()));
+
+        let e = build_error(vec![GRAMS_1, "1\nA B C\t-1.2\n"]);
+        assert_eq!(e.line, 2);
+        assert_eq!(e.kind, ValidationErrorKind::WrongOrder);
+
+        let e = build_error(vec![GRAMS_1, GRAMS_2, "1\nC B A\t0.5\n"]);
+        assert_eq!((e.line, e.gram.as_str()), (2, "B A"));
+        assert_eq!(e.kind, ValidationErrorKind::MissingPrefix);
+
+        // The lower order is empty.
+        let e = build_error(vec![GRAMS_1, "0\n", "1\nA C B\t0.5\n"]);
+        assert_eq!((e.line, e.gram.as_str()), (2, "C B"));
+        assert_eq!(e.kind, ValidationErrorKind::MissingPrefix);
+
+        let e = build_error(vec![GRAMS_1, "2\nC A\t-1.2\n"]);
+        assert_eq!(
+            e.kind,
+            ValidationErrorKind::HeaderMismatch {
+                expected: 2,
+                actual: 1
+            }
+        );
+
+        let e = build_error(vec!["2\nA\t-1.8\t-0.7\nA\t-2.0\t-0.6\n"]);
+        assert_eq!((e.line, e.gram.as_str()), (3, "A"));
+        assert_eq!(e.kind, ValidationErrorKind::Duplicate);
+    }
+}
@@ -3,7 +3,7 @@ use std::path::Path;
 
 use anyhow::Result;
 
-use crate::loader::{GramsFileLoader, GramsGzFileLoader, GramsLoader};
+use crate::loader::{GramsAutoFileLoader, GramsFileLoader, GramsGzFileLoader, GramsLoader};
 use crate::vocabulary::{DoubleArrayVocabulary, Vocabulary};
 use crate::{CountRecord, Gram, GramsFileFormats};
 
@@ -26,6 +26,11 @@ where
             let loader: Box<dyn GramsLoader<_>> = Box::new(GramsGzFileLoader::new(filepath));
             load_records(loader)
         }
+        _ => {
+            let loader: Box<dyn GramsLoader<_>> =
+                Box::new(GramsAutoFileLoader::new(filepath).format(fmt));
+            load_records(loader)
+        }
     }
 }
 
@@ -67,5 +72,10 @@ pub fn get_format_extension(fmt: GramsFileFormats) -> Option<String> {
     match fmt {
         GramsFileFormats::Plain => None,
         GramsFileFormats::Gzip => Some("gz".to_string()),
+        GramsFileFormats::Deflate => Some("deflate".to_string()),
+        GramsFileFormats::Zlib => Some("zlib".to_string()),
+        GramsFileFormats::Zstd => Some("zst".to_string()),
+        GramsFileFormats::Xz => Some("xz".to_string()),
+        GramsFileFormats::Bzip2 => Some("bz2".to_string()),
     }
 }
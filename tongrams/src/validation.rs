@@ -0,0 +1,86 @@
+//! Validation errors of input *N*-gram data.
+use std::fmt;
+
+/// Kinds of [`ValidationError`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationErrorKind {
+    /// The row cannot be parsed.
+    Malformed,
+    /// The gram has a wrong number of tokens for the order.
+    WrongOrder,
+    /// The gram is not sorted in the order of token ids.
+    Unsorted,
+    /// The gram appears more than once.
+    Duplicate,
+    /// The prefix of the gram is missing in the lower order.
+    MissingPrefix,
+    /// The token is absent from the unigrams.
+    UnknownToken(String),
+    /// The number of rows differs from the header.
+    HeaderMismatch {
+        /// The number of grams in the header.
+        expected: usize,
+        /// The actual number of rows.
+        actual: usize,
+    },
+}
+
+impl fmt::Display for ValidationErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "malformed row"),
+            Self::WrongOrder => write!(f, "wrong number of tokens"),
+            Self::Unsorted => write!(f, "gram not sorted in the order of token ids"),
+            Self::Duplicate => write!(f, "duplicate gram"),
+            Self::MissingPrefix => write!(f, "prefix missing in the lower order"),
+            Self::UnknownToken(token) => write!(f, "token {:?} absent from the unigrams", token),
+            Self::HeaderMismatch { expected, actual } => write!(
+                f,
+                "header says {} grams, but {} rows are found",
+                expected, actual
+            ),
+        }
+    }
+}
+
+/// Error for malformed or unsorted input data, reported with the location of the offending row.
+///
+/// This is wrapped in [`anyhow::Error`] and can be obtained with `downcast_ref`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationError {
+    /// Name of the input, e.g., the file path.
+    pub name: String,
+    /// Line number (1-origin) of the offending row, where the header is the first line of a file.
+    pub line: usize,
+    /// The offending row or gram.
+    pub gram: String,
+    /// Kind of the error.
+    pub kind: ValidationErrorKind,
+}
+
+impl ValidationError {
+    pub(crate) fn new<S, G>(name: S, line: usize, gram: G, kind: ValidationErrorKind) -> Self
+    where
+        S: Into<String>,
+        G: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            line,
+            gram: gram.into(),
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {:?}",
+            self.name, self.line, self.kind, self.gram
+        )
+    }
+}
+
+impl std::error::Error for ValidationError {}
@@ -5,11 +5,17 @@ use std::io::{Read, Write};
 
 use anyhow::Result;
 
-pub use crate::vocabulary::{simple::SimpleVocabulary, yada::DoubleArrayVocabulary};
+pub use crate::vocabulary::{
+    simple::SimpleVocabulary,
+    yada::{DoubleArrayVocabulary, ReversibleDoubleArrayVocabulary},
+};
 use crate::Gram;
 
 /// Trait for a data structure for mapping tokens to unique identifiers.
 pub trait Vocabulary {
+    /// Name of the data structure, which identifies it in serialized indexes.
+    const TYPE_NAME: &'static str;
+
     /// Creates an empty [`Vocabulary`].
     fn new() -> Self;
 
@@ -34,6 +40,10 @@ pub trait Vocabulary {
 
     /// Looks up a token.
     fn get(&self, token: Gram) -> Option<usize>;
+
+    /// Gets the token of an identifier, i.e., the reverse lookup of [`Vocabulary::get`],
+    /// or `None` if the identifier is out of range or the reverse lookup is not supported.
+    fn token(&self, id: usize) -> Option<Gram<'_>>;
 }
 
 #[cfg(test)]
@@ -60,4 +70,37 @@ mod tests {
         assert_eq!(vocab.get(Gram::from_str("C")), None);
         assert_eq!(vocab.get(Gram::from_str("D")), Some(1));
     }
+
+    fn test_token<V: Vocabulary>() {
+        let grams = vec![
+            Gram::from_str("A"),
+            Gram::from_str("D"),
+            Gram::from_str("B"),
+        ];
+        let vocab = V::build(&grams).unwrap();
+        let mut data = vec![];
+        vocab.serialize_into(&mut data).unwrap();
+        let other = V::deserialize_from(&data[..]).unwrap();
+        for v in [vocab, other] {
+            for (id, &gram) in grams.iter().enumerate() {
+                assert_eq!(v.token(id), Some(gram));
+            }
+            assert_eq!(v.token(3), None);
+            assert_eq!(v.token(usize::MAX), None);
+        }
+        assert_eq!(V::new().token(0), None);
+    }
+
+    #[test]
+    fn test_token_simple() {
+        test_token::<SimpleVocabulary>();
+    }
+
+    #[test]
+    fn test_token_double_array() {
+        let vocab = DoubleArrayVocabulary::build(&[Gram::from_str("A")]).unwrap();
+        assert_eq!(vocab.token(0), None);
+
+        test_token::<ReversibleDoubleArrayVocabulary>();
+    }
 }
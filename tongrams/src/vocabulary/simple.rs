@@ -10,12 +10,17 @@ use crate::Gram;
 #[derive(Default, Debug)]
 pub struct SimpleVocabulary {
     map: HashMap<String, usize>,
+    // Reverse mapping, which is restored from `map` in deserialization
+    tokens: Vec<String>,
 }
 
 impl Vocabulary for SimpleVocabulary {
+    const TYPE_NAME: &'static str = "SimpleVocabulary";
+
     fn new() -> Self {
         Self {
             map: HashMap::new(),
+            tokens: Vec::new(),
         }
     }
 
@@ -26,7 +31,8 @@ impl Vocabulary for SimpleVocabulary {
                 return Err(anyhow!("Depulicated key: {:?} => {}", token, v));
             }
         }
-        Ok(Self { map })
+        let tokens = tokens.iter().map(|token| token.to_string()).collect();
+        Ok(Self { map, tokens })
     }
 
     fn serialize_into<W>(&self, writer: W) -> Result<usize>
@@ -41,8 +47,15 @@ impl Vocabulary for SimpleVocabulary {
     where
         R: Read,
     {
-        let map = bincode::deserialize_from(reader).map_err(handle_bincode_error)?;
-        Ok(Self { map })
+        let map: HashMap<String, usize> =
+            bincode::deserialize_from(reader).map_err(handle_bincode_error)?;
+        let mut tokens = vec![String::new(); map.len()];
+        for (token, &id) in &map {
+            *tokens
+                .get_mut(id)
+                .ok_or_else(|| anyhow!("Invalid token id: {:?} => {}", token, id))? = token.clone();
+        }
+        Ok(Self { map, tokens })
     }
 
     fn size_in_bytes(&self) -> usize {
@@ -59,6 +72,10 @@ impl Vocabulary for SimpleVocabulary {
     fn get(&self, token: Gram) -> Option<usize> {
         self.map.get(&token.to_string()).copied()
     }
+
+    fn token(&self, id: usize) -> Option<Gram<'_>> {
+        self.tokens.get(id).map(|token| Gram::from_str(token))
+    }
 }
 
 fn handle_bincode_error(e: std::boxed::Box<bincode::ErrorKind>) -> anyhow::Error {
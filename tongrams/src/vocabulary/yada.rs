@@ -2,18 +2,24 @@ use std::io::{Read, Write};
 
 use anyhow::{anyhow, Result};
 use sucds::util::VecIO;
+use sucds::{CompactVector, Searial};
 use yada::{builder::DoubleArrayBuilder, DoubleArray};
 
 use crate::vocabulary::Vocabulary;
 use crate::Gram;
 
 /// Compact double-array implementation of [`Vocabulary`].
+///
+/// The reverse lookup with [`Vocabulary::token`] is not supported,
+/// for which [`ReversibleDoubleArrayVocabulary`] can be used instead.
 #[derive(Default, Debug)]
 pub struct DoubleArrayVocabulary {
     data: Vec<u8>,
 }
 
 impl Vocabulary for DoubleArrayVocabulary {
+    const TYPE_NAME: &'static str = "DoubleArrayVocabulary";
+
     fn new() -> Self {
         Self { data: Vec::new() }
     }
@@ -72,4 +78,97 @@ impl Vocabulary for DoubleArrayVocabulary {
         let da = DoubleArray::new(&self.data[..]);
         da.exact_match_search(token.raw()).map(|x| x as usize)
     }
+
+    fn token(&self, _id: usize) -> Option<Gram<'_>> {
+        None
+    }
+}
+
+/// [`DoubleArrayVocabulary`] supporting the reverse lookup with [`Vocabulary::token`],
+/// which is needed to merge and dump indexes.
+///
+/// The tokens are stored concatenated in addition to the double array,
+/// which takes the total bytes of the tokens and an offset for each token.
+#[derive(Default, Debug)]
+pub struct ReversibleDoubleArrayVocabulary {
+    vocab: DoubleArrayVocabulary,
+    // Concatenated tokens for the reverse lookup
+    tokens: Vec<u8>,
+    // Offsets of the tokens in `tokens`
+    offsets: CompactVector,
+}
+
+impl Vocabulary for ReversibleDoubleArrayVocabulary {
+    const TYPE_NAME: &'static str = "ReversibleDoubleArrayVocabulary";
+
+    fn new() -> Self {
+        Self {
+            vocab: DoubleArrayVocabulary::new(),
+            tokens: Vec::new(),
+            offsets: CompactVector::default(),
+        }
+    }
+
+    fn build(tokens: &[Gram]) -> Result<Self> {
+        let vocab = DoubleArrayVocabulary::build(tokens)?;
+        let mut concatenated = vec![];
+        let mut offsets = vec![0];
+        for token in tokens {
+            concatenated.extend_from_slice(token.raw());
+            offsets.push(concatenated.len());
+        }
+        Ok(Self {
+            vocab,
+            tokens: concatenated,
+            offsets: CompactVector::from_slice(&offsets),
+        })
+    }
+
+    fn serialize_into<W>(&self, mut writer: W) -> Result<usize>
+    where
+        W: Write,
+    {
+        let mut mem = self.vocab.serialize_into(&mut writer)?;
+        mem += self.tokens.serialize_into(&mut writer)?;
+        mem += self.offsets.serialize_into(&mut writer)?;
+        Ok(mem)
+    }
+
+    fn deserialize_from<R>(mut reader: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let vocab = DoubleArrayVocabulary::deserialize_from(&mut reader)?;
+        let tokens = Vec::<u8>::deserialize_from(&mut reader)?;
+        let offsets = CompactVector::deserialize_from(&mut reader)?;
+        Ok(Self {
+            vocab,
+            tokens,
+            offsets,
+        })
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.vocab.size_in_bytes() + self.tokens.size_in_bytes() + self.offsets.size_in_bytes()
+    }
+
+    fn memory_statistics(&self) -> serde_json::Value {
+        let data = self.vocab.size_in_bytes();
+        let tokens = self.tokens.size_in_bytes();
+        let offsets = self.offsets.size_in_bytes();
+        serde_json::json!({ "data": data, "tokens": tokens, "offsets": offsets })
+    }
+
+    #[inline(always)]
+    fn get(&self, token: Gram) -> Option<usize> {
+        self.vocab.get(token)
+    }
+
+    fn token(&self, id: usize) -> Option<Gram<'_>> {
+        if id >= self.offsets.len().saturating_sub(1) {
+            return None;
+        }
+        let (begin, end) = (self.offsets.get(id), self.offsets.get(id + 1));
+        Some(Gram::new(&self.tokens[begin..end]))
+    }
 }
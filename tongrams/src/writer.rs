@@ -0,0 +1,521 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, IntoInnerError, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use tempfile::NamedTempFile;
+
+use crate::{Gram, GramsFileFormats, Separators};
+
+/// The number of digits of the header written in finishing [`GramsWriter::create`],
+/// which is enough for any `usize`.
+const DEFERRED_HEADER_DIGITS: usize = 20;
+
+/// Function writing the header in finishing, given the number of grams written.
+type HeaderWriter = Box<dyn FnOnce(usize) -> Result<()> + Send>;
+
+/// Writer finished explicitly in [`GramsWriter::finish`],
+/// which reports errors in writing the remaining bytes instead of ignoring them in dropping.
+///
+/// A borrowed writer (i.e., `&mut W`) is only flushed, since it cannot be finished.
+pub trait FinishWrite: Write {
+    /// Writes the remaining bytes, e.g., the end of a compressed stream, and flushes them.
+    fn finish_write(self) -> std::io::Result<()>;
+}
+
+impl<W> FinishWrite for &mut W
+where
+    W: Write + ?Sized,
+{
+    fn finish_write(self) -> std::io::Result<()> {
+        self.flush()
+    }
+}
+
+impl FinishWrite for Vec<u8> {
+    fn finish_write(self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FinishWrite for File {
+    fn finish_write(mut self) -> std::io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<W> FinishWrite for BufWriter<W>
+where
+    W: FinishWrite,
+{
+    fn finish_write(self) -> std::io::Result<()> {
+        self.into_inner()
+            .map_err(IntoInnerError::into_error)?
+            .finish_write()
+    }
+}
+
+/// Writer compressing bytes in a format.
+///
+/// The writer should be finished with [`FinishWrite::finish_write`] (e.g., in [`GramsWriter::finish`]).
+/// Otherwise, the end of the compressed stream is written in dropping ignoring errors,
+/// or is not written at all for zstd.
+pub struct GramsEncoder<W>
+where
+    W: Write,
+{
+    inner: Encoder<W>,
+}
+
+enum Encoder<W>
+where
+    W: Write,
+{
+    Plain(W),
+    Gzip(::flate2::write::GzEncoder<W>),
+    Deflate(::flate2::write::DeflateEncoder<W>),
+    Zlib(::flate2::write::ZlibEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(::zstd::stream::write::Encoder<'static, W>),
+    #[cfg(feature = "xz")]
+    Xz(::xz2::write::XzEncoder<W>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(::bzip2::write::BzEncoder<W>),
+}
+
+impl<W> GramsEncoder<W>
+where
+    W: Write,
+{
+    /// Wraps a writer, compressing bytes in the format.
+    pub fn new(writer: W, fmt: GramsFileFormats) -> Result<Self> {
+        let inner = match fmt {
+            GramsFileFormats::Plain => Encoder::Plain(writer),
+            GramsFileFormats::Gzip => Encoder::Gzip(::flate2::write::GzEncoder::new(
+                writer,
+                ::flate2::Compression::default(),
+            )),
+            GramsFileFormats::Deflate => Encoder::Deflate(::flate2::write::DeflateEncoder::new(
+                writer,
+                ::flate2::Compression::default(),
+            )),
+            GramsFileFormats::Zlib => Encoder::Zlib(::flate2::write::ZlibEncoder::new(
+                writer,
+                ::flate2::Compression::default(),
+            )),
+            #[cfg(feature = "zstd")]
+            GramsFileFormats::Zstd => {
+                Encoder::Zstd(::zstd::stream::write::Encoder::new(writer, 0)?)
+            }
+            #[cfg(feature = "xz")]
+            GramsFileFormats::Xz => Encoder::Xz(::xz2::write::XzEncoder::new(writer, 6)),
+            #[cfg(feature = "bzip2")]
+            GramsFileFormats::Bzip2 => Encoder::Bzip2(::bzip2::write::BzEncoder::new(
+                writer,
+                ::bzip2::Compression::default(),
+            )),
+            #[allow(unreachable_patterns)]
+            fmt => {
+                return Err(anyhow!(
+                    "{:?} format requires enabling the cargo feature of it.",
+                    fmt
+                ))
+            }
+        };
+        Ok(Self { inner })
+    }
+
+    /// Writes the end of the compressed stream, returning the underlying writer.
+    pub fn finish(self) -> std::io::Result<W> {
+        match self.inner {
+            Encoder::Plain(writer) => Ok(writer),
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Deflate(encoder) => encoder.finish(),
+            Encoder::Zlib(encoder) => encoder.finish(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.finish(),
+            #[cfg(feature = "xz")]
+            Encoder::Xz(encoder) => encoder.finish(),
+            #[cfg(feature = "bzip2")]
+            Encoder::Bzip2(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W> Write for GramsEncoder<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.inner {
+            Encoder::Plain(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Deflate(encoder) => encoder.write(buf),
+            Encoder::Zlib(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.write(buf),
+            #[cfg(feature = "xz")]
+            Encoder::Xz(encoder) => encoder.write(buf),
+            #[cfg(feature = "bzip2")]
+            Encoder::Bzip2(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.inner {
+            Encoder::Plain(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Deflate(encoder) => encoder.flush(),
+            Encoder::Zlib(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.flush(),
+            #[cfg(feature = "xz")]
+            Encoder::Xz(encoder) => encoder.flush(),
+            #[cfg(feature = "bzip2")]
+            Encoder::Bzip2(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl<W> FinishWrite for GramsEncoder<W>
+where
+    W: FinishWrite,
+{
+    fn finish_write(self) -> std::io::Result<()> {
+        self.finish()?.finish_write()
+    }
+}
+
+/// Writer for a *N*-gram file of counts or probs/backoff-weights,
+/// i.e., the counterpart of [`GramsParser`](crate::GramsParser).
+///
+/// The header of the number of grams is written
+///
+///  - first if the number is given with [`GramsWriter::new`],
+///  - in finishing if the file is created with [`GramsWriter::create`], or
+///  - never with [`GramsWriter::without_header`].
+///
+/// The writer should be finished with [`GramsWriter::finish`],
+/// which checks the number of grams written against the header.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::BufReader;
+///
+/// use tongrams::{Gram, GramsParser, GramsWriter};
+///
+/// let mut bytes = vec![];
+/// let mut writer = GramsWriter::new(&mut bytes, 2).unwrap();
+/// writer.write_count_record(Gram::from_str("A"), 3).unwrap();
+/// writer.write_count_record(Gram::from_str("B"), 1).unwrap();
+/// writer.finish().unwrap();
+/// assert_eq!(bytes, b"2\nA\t3\nB\t1\n");
+///
+/// let mut parser = GramsParser::new(BufReader::new(&bytes[..])).unwrap();
+/// assert_eq!(parser.next_count_record().unwrap().unwrap().count(), 3);
+/// ```
+pub struct GramsWriter<W> {
+    writer: W,
+    separators: Separators,
+    num_grams: Option<usize>,
+    num_written: usize,
+    deferred_header: Option<HeaderWriter>,
+}
+
+impl GramsWriter<BufWriter<File>> {
+    /// Creates a *N*-gram file of the format, whose header is written in finishing.
+    ///
+    /// A plain file is patched with the header, which is padded with zeros to 20 digits.
+    /// The rows of a compressed file are buffered in a temporary file
+    /// and compressed after the header in finishing.
+    pub fn create<P>(filepath: P, fmt: GramsFileFormats) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::create_with_spool(filepath.as_ref(), fmt, None)
+    }
+
+    /// Creates a *N*-gram file of the format like [`GramsWriter::create`],
+    /// buffering the rows of a compressed file in a temporary file in `tmp_dir`
+    /// instead of the system's temporary directory.
+    pub fn create_in<P, Q>(filepath: P, fmt: GramsFileFormats, tmp_dir: Q) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        Self::create_with_spool(filepath.as_ref(), fmt, Some(tmp_dir.as_ref()))
+    }
+
+    fn create_with_spool(
+        filepath: &Path,
+        fmt: GramsFileFormats,
+        tmp_dir: Option<&Path>,
+    ) -> Result<Self> {
+        let filepath = filepath.to_path_buf();
+        let (writer, deferred_header): (BufWriter<File>, HeaderWriter) =
+            if fmt == GramsFileFormats::Plain {
+                let mut writer = BufWriter::new(File::create(&filepath)?);
+                writeln!(writer, "{:0width$}", 0, width = DEFERRED_HEADER_DIGITS)?;
+                let deferred_header = move |num_grams: usize| -> Result<()> {
+                    let mut file = OpenOptions::new().write(true).open(&filepath)?;
+                    write!(
+                        file,
+                        "{:0width$}",
+                        num_grams,
+                        width = DEFERRED_HEADER_DIGITS
+                    )?;
+                    Ok(())
+                };
+                (writer, Box::new(deferred_header))
+            } else {
+                let spool = match tmp_dir {
+                    Some(tmp_dir) => NamedTempFile::new_in(tmp_dir)?,
+                    None => NamedTempFile::new()?,
+                };
+                let writer = BufWriter::new(spool.as_file().try_clone()?);
+                let deferred_header = move |num_grams: usize| -> Result<()> {
+                    let file = BufWriter::new(File::create(&filepath)?);
+                    let mut writer = GramsEncoder::new(file, fmt)?;
+                    writeln!(writer, "{}", num_grams)?;
+                    std::io::copy(&mut spool.reopen()?, &mut writer)?;
+                    writer.finish_write()?;
+                    Ok(())
+                };
+                (writer, Box::new(deferred_header))
+            };
+        Ok(Self {
+            writer,
+            separators: Separators::default(),
+            num_grams: None,
+            num_written: 0,
+            deferred_header: Some(deferred_header),
+        })
+    }
+}
+
+impl<W> GramsWriter<W>
+where
+    W: Write,
+{
+    /// Creates a new [`GramsWriter`] writing the header of `num_grams` first,
+    /// where `writer` should compress the bytes if needed (e.g., with [`GramsFileFormats::encoder`]).
+    pub fn new(mut writer: W, num_grams: usize) -> Result<Self> {
+        writeln!(writer, "{}", num_grams)?;
+        Ok(Self {
+            writer,
+            separators: Separators::default(),
+            num_grams: Some(num_grams),
+            num_written: 0,
+            deferred_header: None,
+        })
+    }
+
+    /// Creates a new [`GramsWriter`] writing no header.
+    pub fn without_header(writer: W) -> Self {
+        Self {
+            writer,
+            separators: Separators::default(),
+            num_grams: None,
+            num_written: 0,
+            deferred_header: None,
+        }
+    }
+
+    /// Sets the separators of tokens and fields (default: [`Separators::default`]).
+    ///
+    /// The tokens of a gram are rejoined with the separator of tokens if it differs from
+    /// the separator of the gram.
+    pub const fn separators(mut self, separators: Separators) -> Self {
+        self.separators = separators;
+        self
+    }
+
+    /// Gets the number of grams written so far.
+    pub const fn num_written(&self) -> usize {
+        self.num_written
+    }
+
+    /// Writes a row of a gram and its count.
+    pub fn write_count_record(&mut self, gram: Gram, count: usize) -> Result<()> {
+        self.write_gram(gram)?;
+        writeln!(self.writer, "{}{}", self.separators.field as char, count)?;
+        Ok(())
+    }
+
+    /// Writes a row of a gram, its probability, and its backoff weight if given,
+    /// where the backoff weight is omitted for grams of the highest order.
+    pub fn write_prob_record(&mut self, gram: Gram, prob: f32, backoff: Option<f32>) -> Result<()> {
+        self.write_gram(gram)?;
+        let field = self.separators.field as char;
+        write!(self.writer, "{}{}", field, prob)?;
+        if let Some(backoff) = backoff {
+            write!(self.writer, "{}{}", field, backoff)?;
+        }
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn write_gram(&mut self, gram: Gram) -> Result<()> {
+        let separator = self.separators.token;
+        if gram.separator() == separator {
+            self.writer.write_all(gram.raw())?;
+        } else {
+            for (i, token) in gram.split_to_tokens().into_iter().enumerate() {
+                if i != 0 {
+                    self.writer.write_all(&[separator])?;
+                }
+                self.writer.write_all(token.raw())?;
+            }
+        }
+        self.num_written += 1;
+        Ok(())
+    }
+}
+
+impl<W> GramsWriter<W>
+where
+    W: FinishWrite,
+{
+    /// Finishes the writer with [`FinishWrite::finish_write`], checking the number of grams
+    /// written against the header or writing the header if deferred.
+    pub fn finish(self) -> Result<()> {
+        if let Some(num_grams) = self.num_grams {
+            if num_grams != self.num_written {
+                return Err(anyhow!(
+                    "The header has {} grams, but {} grams are written.",
+                    num_grams,
+                    self.num_written
+                ));
+            }
+        }
+        let num_written = self.num_written;
+        self.writer.finish_write()?;
+        self.deferred_header
+            .map_or(Ok(()), |write_header| write_header(num_written))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{BufReader, Cursor};
+
+    use crate::loader::{GramsAutoFileLoader, GramsLoader};
+    use crate::{CountRecord, GramsParser};
+
+    const GRAMS_1: &str = "3
+A B\t3
+A C\t2
+B C\t1
+";
+
+    fn write_records<W>(mut writer: GramsWriter<W>) -> Result<()>
+    where
+        W: FinishWrite,
+    {
+        for line in GRAMS_1.lines().skip(1) {
+            let (gram, count) = line.split_once('\t').unwrap();
+            writer.write_count_record(Gram::from_str(gram), count.parse().unwrap())?;
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn test_header() {
+        let mut bytes = vec![];
+        write_records(GramsWriter::new(&mut bytes, 3).unwrap()).unwrap();
+        assert_eq!(bytes, GRAMS_1.as_bytes());
+
+        let mut bytes = vec![];
+        write_records(GramsWriter::without_header(&mut bytes)).unwrap();
+        assert_eq!(bytes, GRAMS_1.split_once('\n').unwrap().1.as_bytes());
+
+        let mut bytes = vec![];
+        assert!(write_records(GramsWriter::new(&mut bytes, 2).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_create() {
+        let expected: Vec<CountRecord> = GramsParser::new(BufReader::new(GRAMS_1.as_bytes()))
+            .unwrap()
+            .into_count_records()
+            .collect::<Result<_>>()
+            .unwrap();
+        for fmt in [GramsFileFormats::Plain, GramsFileFormats::Gzip] {
+            let tmp = NamedTempFile::new().unwrap();
+            write_records(GramsWriter::create(tmp.path(), fmt).unwrap()).unwrap();
+            let parser = GramsAutoFileLoader::new(tmp.path()).parser().unwrap();
+            assert_eq!(parser.num_grams(), 3);
+            let records: Vec<CountRecord> =
+                parser.into_count_records().collect::<Result<_>>().unwrap();
+            assert_eq!(records, expected);
+        }
+    }
+
+    #[test]
+    fn test_create_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let tmp = NamedTempFile::new().unwrap();
+        let fmt = GramsFileFormats::Gzip;
+        let mut writer = GramsWriter::create_in(tmp.path(), fmt, dir.path()).unwrap();
+        writer.write_count_record(Gram::from_str("A"), 3).unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        writer.finish().unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let mut parser = GramsAutoFileLoader::new(tmp.path()).parser().unwrap();
+        assert_eq!(parser.num_grams(), 1);
+        assert_eq!(parser.next_count_record().unwrap().unwrap().count(), 3);
+    }
+
+    #[test]
+    fn test_finish_error() {
+        let fmt = GramsFileFormats::Gzip;
+        let mut bytes = vec![];
+        let writer = GramsEncoder::new(&mut bytes, fmt).unwrap();
+        write_records(GramsWriter::new(writer, 3).unwrap()).unwrap();
+
+        // The end of the compressed stream, written in finishing, does not fit in the buffer.
+        let mut buf = vec![0; bytes.len() - 1];
+        let mut cursor = Cursor::new(&mut buf[..]);
+        let writer = GramsEncoder::new(&mut cursor, fmt).unwrap();
+        assert!(write_records(GramsWriter::new(writer, 3).unwrap()).is_err());
+
+        let mut buf = vec![0; bytes.len()];
+        let mut cursor = Cursor::new(&mut buf[..]);
+        let writer = GramsEncoder::new(&mut cursor, fmt).unwrap();
+        write_records(GramsWriter::new(writer, 3).unwrap()).unwrap();
+        assert_eq!(buf, bytes);
+    }
+
+    #[test]
+    fn test_prob_records() {
+        let mut bytes = vec![];
+        let mut writer = GramsWriter::new(&mut bytes, 2).unwrap();
+        writer
+            .write_prob_record(Gram::from_str("A"), -1.83, Some(-0.74))
+            .unwrap();
+        writer
+            .write_prob_record(Gram::from_str("B"), -2.22, None)
+            .unwrap();
+        writer.finish().unwrap();
+        assert_eq!(bytes, b"2\nA\t-1.83\t-0.74\nB\t-2.22\n");
+    }
+
+    #[test]
+    fn test_separators() {
+        let mut bytes = vec![];
+        let mut writer =
+            GramsWriter::without_header(&mut bytes).separators(Separators::new(b'_', b','));
+        writer
+            .write_count_record(Gram::from_str("A B C"), 1)
+            .unwrap();
+        writer
+            .write_count_record(Gram::from_str("A_B").with_separator(b'_'), 2)
+            .unwrap();
+        writer.finish().unwrap();
+        assert_eq!(bytes, b"A_B_C,1\nA_B,2\n");
+    }
+}
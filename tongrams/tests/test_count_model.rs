@@ -3,8 +3,16 @@ use std::io::{prelude::*, BufReader};
 use std::path::PathBuf;
 use std::str::FromStr;
 
+use flate2::read::MultiGzDecoder;
+
 use tongrams::loader::{GramsGzFileLoader, GramsLoader};
-use tongrams::EliasFanoTrieCountLm;
+use tongrams::trie_count_lm::{GramsSource, TrieCountLmBuilder, TrieCountLmRecordsBuilder};
+use tongrams::util;
+use tongrams::{
+    CompactRankTrieCountLm, DoubleArrayVocabulary, EliasFanoTrieArray, EliasFanoTrieCountLm,
+    GammaRankTrieCountLm, Gram, GramsFileFormats, ReversibleTrieCountLm, SimpleRankArray,
+    TrieCountLm,
+};
 
 const TEST_FILENAMES: [&str; 5] = [
     "../test_data/1-grams.sorted.gz",
@@ -26,6 +34,28 @@ fn load_noexist_queries() -> Vec<String> {
         .collect()
 }
 
+fn make_loaders() -> Vec<Box<dyn GramsLoader<MultiGzDecoder<File>>>> {
+    TEST_FILENAMES
+        .iter()
+        .map(|&filename| Box::new(GramsGzFileLoader::new(filename)) as Box<dyn GramsLoader<_>>)
+        .collect()
+}
+
+/// Calls `f` with the gram and the count of each record in the test files of *N* = `i + 1`.
+fn for_each_record<F>(mut f: F)
+where
+    F: FnMut(usize, Gram, usize),
+{
+    for (i, &filename) in TEST_FILENAMES.iter().enumerate() {
+        let loader = GramsGzFileLoader::new(filename);
+        let mut parser = loader.parser().unwrap();
+        while let Some(rec) = parser.next_count_record() {
+            let rec = rec.unwrap();
+            f(i, rec.gram(), rec.count());
+        }
+    }
+}
+
 #[test]
 fn test_parser() {
     for (&filename, &num_grams) in TEST_FILENAMES.iter().zip(NUM_GRAMS.iter()) {
@@ -41,16 +71,159 @@ fn test_lookup() {
     assert_eq!(lm.num_orders(), 5);
 
     let mut lookuper = lm.lookuper();
+    for_each_record(|_, gram, count| assert_eq!(lookuper.with_gram(gram), Some(count)));
+}
+
+#[test]
+fn test_parallel_lookup() {
+    let lm: EliasFanoTrieCountLm = TrieCountLmBuilder::new(make_loaders())
+        .unwrap()
+        .build_parallel(0)
+        .unwrap();
+    assert_eq!(lm.num_orders(), 5);
+
+    let mut lookuper = lm.lookuper();
+    for_each_record(|_, gram, count| assert_eq!(lookuper.with_gram(gram), Some(count)));
+}
+
+#[test]
+fn test_external_memory_lookup() {
+    let lm: CompactRankTrieCountLm = TrieCountLmBuilder::new(make_loaders())
+        .unwrap()
+        .memory_budget(1 << 16)
+        .build()
+        .unwrap();
+    assert_eq!(lm.num_orders(), 5);
+
+    let mut lookuper = lm.lookuper();
+    for_each_record(|_, gram, count| assert_eq!(lookuper.with_gram(gram), Some(count)));
+}
+
+#[test]
+fn test_records_lookup() {
+    let mut records = Vec::new();
     for filename in TEST_FILENAMES {
-        let loader = GramsGzFileLoader::new(filename);
-        let mut parser = loader.parser().unwrap();
-        while let Some(rec) = parser.next_count_record() {
-            let rec = rec.unwrap();
-            assert_eq!(lookuper.with_gram(rec.gram()), Some(rec.count()));
+        records.push(util::load_records_from_file(filename, GramsFileFormats::Gzip).unwrap());
+    }
+    let sources = records
+        .iter()
+        .map(|records| GramsSource::records(records.clone()))
+        .collect();
+    let lm: EliasFanoTrieCountLm = TrieCountLmRecordsBuilder::new(sources)
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(lm.num_orders(), 5);
+
+    let mut lookuper = lm.lookuper();
+    for rec in records.iter().flatten() {
+        assert_eq!(lookuper.with_gram(rec.gram()), Some(rec.count()));
+    }
+}
+
+#[test]
+fn test_headerless_lookup() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut records = Vec::new();
+    let mut filepaths = Vec::new();
+    for (i, filename) in TEST_FILENAMES.iter().enumerate() {
+        let recs = util::load_records_from_file(filename, GramsFileFormats::Gzip).unwrap();
+        let filepath = dir.path().join(format!("{}-grams", i + 1));
+        let mut writer = std::io::BufWriter::new(File::create(&filepath).unwrap());
+        for rec in &recs {
+            writeln!(writer, "{}\t{}", rec.gram(), rec.count()).unwrap();
         }
+        records.push(recs);
+        filepaths.push(filepath);
+    }
+    let lm = EliasFanoTrieCountLm::from_detected_files(&filepaths).unwrap();
+    assert_eq!(lm.num_grams(), NUM_GRAMS.iter().sum::<usize>());
+
+    let mut lookuper = lm.lookuper();
+    for rec in records.iter().flatten() {
+        assert_eq!(lookuper.with_gram(rec.gram()), Some(rec.count()));
+    }
+}
+
+#[test]
+fn test_unsorted_records_lookup() {
+    let mut records = Vec::new();
+    for filename in TEST_FILENAMES {
+        records.push(util::load_records_from_file(filename, GramsFileFormats::Gzip).unwrap());
+    }
+    let sources = records
+        .iter()
+        .enumerate()
+        .map(|(i, records)| {
+            if i == 0 {
+                GramsSource::records(records.clone())
+            } else {
+                GramsSource::records(records.clone().into_iter().rev())
+            }
+        })
+        .collect();
+    let lm: EliasFanoTrieCountLm = TrieCountLmRecordsBuilder::new(sources)
+        .unwrap()
+        .sort_grams(true)
+        .memory_budget(1 << 16)
+        .build()
+        .unwrap();
+    assert_eq!(lm.num_orders(), 5);
+
+    let mut lookuper = lm.lookuper();
+    for rec in records.iter().flatten() {
+        assert_eq!(lookuper.with_gram(rec.gram()), Some(rec.count()));
     }
 }
 
+#[test]
+fn test_pruned_lookup() {
+    let min_counts = vec![1, 1, 2, 2, 3];
+    let lm: EliasFanoTrieCountLm = TrieCountLmBuilder::new(make_loaders())
+        .unwrap()
+        .min_counts(min_counts.clone())
+        .build()
+        .unwrap();
+    assert_eq!(lm.num_orders(), 5);
+
+    let mut lookuper = lm.lookuper();
+    let mut num_grams = 0;
+    for_each_record(|i, gram, count| {
+        let found = lookuper.with_gram(gram);
+        if min_counts[i] <= count {
+            assert_eq!(found, Some(count));
+        } else {
+            assert!(found.is_none() || found == Some(count));
+        }
+        num_grams += found.is_some() as usize;
+    });
+    assert_eq!(lm.num_grams(), num_grams);
+    assert!(num_grams < NUM_GRAMS.iter().sum());
+}
+
+#[test]
+fn test_rank_arrays() {
+    let ef_lm = EliasFanoTrieCountLm::from_gz_files(&TEST_FILENAMES).unwrap();
+    let cv_lm = CompactRankTrieCountLm::from_gz_files(&TEST_FILENAMES).unwrap();
+    let gm_lm = GammaRankTrieCountLm::from_gz_files(&TEST_FILENAMES).unwrap();
+    let sm_lm: TrieCountLm<EliasFanoTrieArray, DoubleArrayVocabulary, SimpleRankArray> =
+        TrieCountLm::from_gz_files(&TEST_FILENAMES).unwrap();
+    assert_eq!(ef_lm.num_grams(), cv_lm.num_grams());
+    assert_eq!(ef_lm.num_grams(), gm_lm.num_grams());
+    assert_eq!(ef_lm.num_grams(), sm_lm.num_grams());
+
+    // The indexes differ only in the rank arrays.
+    assert!(cv_lm.size_in_bytes() < sm_lm.size_in_bytes());
+    assert!(gm_lm.size_in_bytes() < sm_lm.size_in_bytes());
+
+    let mut cv_lookuper = cv_lm.lookuper();
+    let mut gm_lookuper = gm_lm.lookuper();
+    for_each_record(|_, gram, count| {
+        assert_eq!(cv_lookuper.with_gram(gram), Some(count));
+        assert_eq!(gm_lookuper.with_gram(gram), Some(count));
+    });
+}
+
 #[test]
 fn test_noexist_lookup() {
     let lm = EliasFanoTrieCountLm::from_gz_files(&TEST_FILENAMES).unwrap();
@@ -63,6 +236,17 @@ fn test_noexist_lookup() {
     }
 }
 
+#[test]
+fn test_merge() {
+    let lm = ReversibleTrieCountLm::from_gz_files(&TEST_FILENAMES).unwrap();
+    let merged = ReversibleTrieCountLm::merge(&[&lm, &lm]).unwrap();
+    assert_eq!(lm.num_orders(), merged.num_orders());
+    assert_eq!(lm.num_grams(), merged.num_grams());
+
+    let mut lookuper = merged.lookuper();
+    for_each_record(|_, gram, count| assert_eq!(lookuper.with_gram(gram), Some(count * 2)));
+}
+
 #[test]
 fn test_serialization() {
     let lm = EliasFanoTrieCountLm::from_gz_files(&TEST_FILENAMES).unwrap();
@@ -74,3 +258,17 @@ fn test_serialization() {
     assert_eq!(lm.num_orders(), other.num_orders());
     assert_eq!(lm.num_grams(), other.num_grams());
 }
+
+#[test]
+fn test_dump_roundtrip() {
+    let lm = ReversibleTrieCountLm::from_gz_files(&TEST_FILENAMES).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let filepaths = lm.dump_files(dir.path(), GramsFileFormats::Gzip).unwrap();
+    let other = ReversibleTrieCountLm::from_gz_files(&filepaths).unwrap();
+
+    let mut data = vec![];
+    lm.serialize_into(&mut data).unwrap();
+    let mut other_data = vec![];
+    other.serialize_into(&mut other_data).unwrap();
+    assert_eq!(data, other_data);
+}
@@ -0,0 +1,60 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use structopt::StructOpt;
+
+use tongrams::{AnyTrieCountLm, GramsFileFormats};
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "dump",
+    about = "A program to dump an index back to N-gram counts files, which needs the index built with --reversible of index."
+)]
+struct Opt {
+    #[structopt(short = "i")]
+    index_filepath: PathBuf,
+
+    #[structopt(short = "o", help = "Output directory, which is created if absent.")]
+    output_dirpath: PathBuf,
+
+    #[structopt(
+        short = "f",
+        long,
+        default_value = "gzip",
+        help = "Output file format from plain, gzip, deflate, zlib, zstd, xz, and bzip2."
+    )]
+    file_format: GramsFileFormats,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    let index_filepath = opt.index_filepath;
+    let output_dirpath = opt.output_dirpath;
+    let file_format = opt.file_format;
+
+    println!("Loading the index from {:?}...", &index_filepath);
+    let mut reader = BufReader::new(File::open(&index_filepath)?);
+    let lm = AnyTrieCountLm::deserialize_from(&mut reader)?;
+    lm.check_reverse_lookup()
+        .context("The index should be built with --reversible of index to be dumped.")?;
+
+    println!("Dumping the grams into {:?}...", &output_dirpath);
+    std::fs::create_dir_all(&output_dirpath)?;
+    let start = std::time::Instant::now();
+    let filepaths = lm.dump_files(&output_dirpath, file_format)?;
+    let duration = start.elapsed();
+    println!("Elapsed time: {:.3} [sec]", duration.as_secs_f64());
+    for (n, filepath) in filepaths.iter().enumerate() {
+        println!("{}-grams: {:?}", n + 1, filepath);
+    }
+    if lm.token_separator() != tongrams::TOKEN_SEPARATOR {
+        println!(
+            "The tokens are separated by {:?}, which should be given with --token-separator of index.",
+            lm.token_separator() as char
+        );
+    }
+
+    Ok(())
+}
@@ -1,12 +1,20 @@
-use std::fmt::Write as _;
 use std::fs::File;
-use std::io::BufWriter;
-use std::path::PathBuf;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use structopt::StructOpt;
 
-use tongrams::{util, EliasFanoTrieCountLm, GramsFileFormats};
+use tongrams::loader::{
+    BoxedGramsLoader, GramsAutoFileLoader, GramsLoader, GramsMultiOrderLoader, GramsStreamLoader,
+};
+use tongrams::progress::BuildProgress;
+use tongrams::trie_count_lm::{CountLm, TrieCountLmBuilder};
+use tongrams::{
+    util, CompactRankArray, DoubleArrayVocabulary, EliasFanoRankArray, EliasFanoTrieArray,
+    GammaRankArray, GramsFileFormats, RankArray, ReversibleDoubleArrayVocabulary, Separators,
+    SimpleRankArray, TrieCountLm, Vocabulary,
+};
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "index", about = "A program to build and write the index.")]
@@ -15,50 +23,276 @@ struct Opt {
         short = "f",
         long,
         default_value = "gzip",
-        help = "Input file format from plain, gzip, deflate, and zlib."
+        help = "Input file format from plain, gzip, deflate, zlib, zstd, xz, and bzip2."
     )]
     file_format: GramsFileFormats,
 
-    #[structopt(short = "i", long)]
-    grams_dirpath: PathBuf,
+    #[structopt(
+        short = "i",
+        long,
+        required_unless_one = &["input-files", "multi-order-file"]
+    )]
+    grams_dirpath: Option<PathBuf>,
+
+    #[structopt(
+        short = "n",
+        long,
+        required_unless_one = &["input-files", "multi-order-file"]
+    )]
+    order: Option<usize>,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["grams-dirpath", "order"],
+        help = "Input files of N = 1, 2, ..., instead of -i and -n, where - denotes the standard input (e.g., for pipelines)."
+    )]
+    input_files: Vec<PathBuf>,
 
-    #[structopt(short = "n", long)]
-    order: usize,
+    #[structopt(
+        long,
+        conflicts_with_all = &["grams-dirpath", "order", "input-files"],
+        help = "Input file containing all the orders, split by section headers such as \\2-grams: or by the numbers of tokens."
+    )]
+    multi_order_file: Option<PathBuf>,
 
     #[structopt(short = "o", long)]
     index_filepath: PathBuf,
+
+    #[structopt(
+        short = "t",
+        long,
+        default_value = "1",
+        help = "Number of threads to build the index (0 means the number of logical CPUs)."
+    )]
+    num_threads: usize,
+
+    #[structopt(
+        short = "m",
+        long,
+        help = "Memory budget in MiB for the buffered data of an order, beyond which temporary files are used."
+    )]
+    memory_budget: Option<usize>,
+
+    #[structopt(
+        short = "u",
+        long,
+        help = "Reads unsorted files and sorts them in the construction, where the files in the directory of -i are named <order>-grams instead of <order>-grams.sorted."
+    )]
+    unsorted: bool,
+
+    #[structopt(
+        long,
+        help = "Reads files without the header, which is otherwise detected from the first line."
+    )]
+    headerless: bool,
+
+    #[structopt(
+        long,
+        help = "ASCII separator of tokens in the files and the index (default: a space)."
+    )]
+    token_separator: Option<char>,
+
+    #[structopt(
+        long,
+        help = "ASCII separator of a gram and the count in the files (default: a tab)."
+    )]
+    field_separator: Option<char>,
+
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Minimum counts of grams for N = 1, 2, ..., separated by commas (e.g., 1,1,2,2)."
+    )]
+    min_counts: Vec<usize>,
+
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Maximum numbers of grams for N = 1, 2, ..., separated by commas."
+    )]
+    max_grams: Vec<usize>,
+
+    #[structopt(
+        long,
+        help = "Stores the tokens for the reverse lookup, which is needed to merge and dump the index."
+    )]
+    reversible: bool,
+
+    #[structopt(
+        long,
+        default_value = "ef",
+        possible_values = &["ef", "compact", "gamma", "simple"],
+        help = "Array of count ranks from ef (Elias-Fano), compact (bit-packed), gamma (Elias-gamma coded), and simple."
+    )]
+    rank_array: String,
+}
+
+/// Converts a separator given as a char into the byte.
+fn to_separator(c: char) -> Result<u8> {
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err(anyhow!("The separator {:?} must be an ASCII character.", c))
+    }
+}
+
+/// Displays the progress of the construction in a line of stderr for each phase.
+fn display_progress(progress: &BuildProgress) {
+    let line = format!(
+        "{}: {} records ({:.0} records/sec), {:.3} MiB built",
+        progress.phase,
+        progress.num_records,
+        progress.throughput(),
+        progress.memory as f64 / (1024.0 * 1024.0)
+    );
+    let mut stderr = std::io::stderr().lock();
+    if progress.finished {
+        writeln!(stderr, "\r{}", line).ok();
+    } else {
+        write!(stderr, "\r{}", line).ok();
+        stderr.flush().ok();
+    }
+}
+
+/// Makes the loader of an input file, which is read as a stream if it is not a regular file
+/// (e.g., the standard input given as `-` or a named pipe).
+fn make_loader(filepath: &Path, opt: &Opt, separators: Separators) -> Result<BoxedGramsLoader> {
+    // The format of each file is detected from the magic bytes,
+    // except for raw deflate streams that have no magic bytes.
+    let deflate = opt.file_format == GramsFileFormats::Deflate;
+    if filepath.is_file() {
+        let mut loader = GramsAutoFileLoader::new(filepath)
+            .headerless(opt.headerless)
+            .separators(separators);
+        if deflate {
+            loader = loader.format(opt.file_format);
+        }
+        return Ok(Box::new(loader));
+    }
+    // The stream need not be spooled since the builder reads each input only once.
+    let mut loader = if filepath.as_os_str() == "-" {
+        GramsStreamLoader::new(Box::new(std::io::stdin()) as Box<dyn Read + Send>).name("stdin")
+    } else {
+        let file = File::open(filepath)?;
+        GramsStreamLoader::new(Box::new(file) as Box<dyn Read + Send>)
+            .name(filepath.display().to_string())
+    };
+    loader = loader
+        .spool(false)
+        .headerless(opt.headerless)
+        .separators(separators);
+    if deflate {
+        loader = loader.format(opt.file_format);
+    }
+    Ok(Box::new(loader))
+}
+
+/// Builds the index with the rank array given in `opt`.
+fn build_any_index<R, V>(
+    loaders: Vec<Box<dyn GramsLoader<R>>>,
+    opt: &Opt,
+) -> Result<Box<dyn CountLm>>
+where
+    R: Read + Send,
+    V: Vocabulary + Send + Sync + 'static,
+{
+    let lm: Box<dyn CountLm> = match opt.rank_array.as_str() {
+        "compact" => Box::new(build_index::<_, V, CompactRankArray>(loaders, opt)?),
+        "gamma" => Box::new(build_index::<_, V, GammaRankArray>(loaders, opt)?),
+        "simple" => Box::new(build_index::<_, V, SimpleRankArray>(loaders, opt)?),
+        _ => Box::new(build_index::<_, V, EliasFanoRankArray>(loaders, opt)?),
+    };
+    Ok(lm)
+}
+
+fn build_index<R, V, A>(
+    loaders: Vec<Box<dyn GramsLoader<R>>>,
+    opt: &Opt,
+) -> Result<TrieCountLm<EliasFanoTrieArray, V, A>>
+where
+    R: Read + Send,
+    V: Vocabulary + Sync,
+    A: RankArray + Send,
+{
+    let mut builder = TrieCountLmBuilder::new(loaders)?
+        .sort_grams(opt.unsorted)
+        .min_counts(opt.min_counts.clone())
+        .max_grams(opt.max_grams.clone())
+        .progress(display_progress);
+    if let Some(memory_budget) = opt.memory_budget {
+        builder = builder.memory_budget(memory_budget << 20);
+    }
+    builder.build_parallel(opt.num_threads)
 }
 
 fn main() -> Result<()> {
     let opt = Opt::from_args();
-    let order = opt.order;
     let file_format = opt.file_format;
-    let grams_dirpath = opt.grams_dirpath;
-    let index_filepath = opt.index_filepath;
-
-    let mut grams_filepaths = vec![];
-    for i in 1..=order {
-        let mut grams_filepath = grams_dirpath.clone();
-        grams_filepath.push(format!("{}-grams.sorted", i));
-        let mut grams_filename = grams_filepath.into_os_string().into_string().unwrap();
-        if let Some(ext) = util::get_format_extension(file_format) {
-            write!(grams_filename, ".{}", ext)?;
+    let index_filepath = &opt.index_filepath;
+    let unsorted = opt.unsorted;
+
+    let mut grams_filepaths = opt.input_files.clone();
+    if let (Some(grams_dirpath), Some(order)) = (&opt.grams_dirpath, opt.order) {
+        for i in 1..=order {
+            let mut grams_filepath = grams_dirpath.clone();
+            if unsorted {
+                grams_filepath.push(format!("{}-grams", i));
+            } else {
+                grams_filepath.push(format!("{}-grams.sorted", i));
+            }
+            let mut grams_filename = grams_filepath.into_os_string();
+            if let Some(ext) = util::get_format_extension(file_format) {
+                grams_filename.push(format!(".{}", ext));
+            }
+            grams_filepaths.push(PathBuf::from(grams_filename));
         }
-        grams_filepaths.push(grams_filename);
+    }
+    grams_filepaths.extend(opt.multi_order_file.clone());
+    if grams_filepaths
+        .iter()
+        .filter(|p| p.as_os_str() == "-")
+        .count()
+        > 1
+    {
+        return Err(anyhow!("The standard input can be given only once."));
     }
     println!("Input files: {:?}", grams_filepaths);
 
     println!("Counstructing the index...");
     let start = std::time::Instant::now();
-    let lm = EliasFanoTrieCountLm::from_files(&grams_filepaths, file_format)?;
+    let mut separators = Separators::default();
+    if let Some(c) = opt.token_separator {
+        separators.token = to_separator(c)?;
+    }
+    if let Some(c) = opt.field_separator {
+        separators.field = to_separator(c)?;
+    }
+    let loaders = if let Some(filepath) = &opt.multi_order_file {
+        let mut loader = GramsMultiOrderLoader::new(filepath).separators(separators);
+        if file_format == GramsFileFormats::Deflate {
+            loader = loader.format(file_format);
+        }
+        loader.loaders()?
+    } else {
+        let mut loaders = Vec::with_capacity(grams_filepaths.len());
+        for filepath in &grams_filepaths {
+            loaders.push(make_loader(filepath, &opt, separators)?);
+        }
+        loaders
+    };
+    let lm = if opt.reversible {
+        build_any_index::<_, ReversibleDoubleArrayVocabulary>(loaders, &opt)?
+    } else {
+        build_any_index::<_, DoubleArrayVocabulary>(loaders, &opt)?
+    };
     let duration = start.elapsed();
     println!("Elapsed time: {:.3} [sec]", duration.as_secs_f64());
 
     let num_grams = lm.num_grams();
     println!("{} grams are stored.", num_grams);
 
-    println!("Writing the index into {:?}...", &index_filepath);
-    let mut writer = BufWriter::new(File::create(&index_filepath)?);
+    println!("Writing the index into {:?}...", index_filepath);
+    let mut writer = BufWriter::new(File::create(index_filepath)?);
     let mem = lm.serialize_into(&mut writer)?;
     println!(
         "Index size: {} bytes ({:.3} MiB)",
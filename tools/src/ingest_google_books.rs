@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use structopt::StructOpt;
+
+use tongrams::google_books::{GoogleBooksCounter, PosTags};
+use tongrams::GramsFileFormats;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "ingest_google_books",
+    about = "A program to convert Google Books Ngram files into sorted N-gram counts files."
+)]
+struct Opt {
+    #[structopt(short = "i", required = true, min_values = 1)]
+    input_filepaths: Vec<PathBuf>,
+
+    #[structopt(short = "o")]
+    output_dirpath: PathBuf,
+
+    #[structopt(
+        short = "f",
+        long,
+        default_value = "gzip",
+        help = "Output file format from plain, gzip, deflate, zlib, zstd, xz, and bzip2."
+    )]
+    file_format: GramsFileFormats,
+
+    #[structopt(long, help = "First year of the counts aggregated.")]
+    from_year: Option<u32>,
+
+    #[structopt(long, help = "Last year of the counts aggregated.")]
+    to_year: Option<u32>,
+
+    #[structopt(
+        long,
+        default_value = "keep",
+        help = "Handling of part-of-speech tagged tokens from keep, strip, and skip."
+    )]
+    pos_tags: PosTags,
+
+    #[structopt(
+        short = "m",
+        long,
+        help = "Memory budget in MiB for the aggregated counts, beyond which temporary files are used."
+    )]
+    memory_budget: Option<usize>,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    let years = opt.from_year.unwrap_or(0)..=opt.to_year.unwrap_or(u32::MAX);
+
+    let mut counter = GoogleBooksCounter::new()
+        .years(years)
+        .pos_tags(opt.pos_tags);
+    if let Some(memory_budget) = opt.memory_budget {
+        counter = counter.memory_budget(memory_budget << 20);
+    }
+    for input_filepath in &opt.input_filepaths {
+        println!("Loading the records: {:?}", input_filepath);
+        counter.add_file(input_filepath)?;
+    }
+
+    println!("Writing the files into {:?}...", &opt.output_dirpath);
+    let filepaths = counter.write_files(&opt.output_dirpath, opt.file_format)?;
+    println!("Output files: {:?}", filepaths);
+
+    Ok(())
+}
@@ -6,7 +6,7 @@ use std::path::PathBuf;
 use anyhow::Result;
 use structopt::StructOpt;
 
-use tongrams::EliasFanoTrieCountLm;
+use tongrams::AnyTrieCountLm;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "lookup", about = "A demo program to lookup ngrams.")]
@@ -21,7 +21,7 @@ fn main() -> Result<()> {
 
     println!("Loading the index from {:?}...", &index_filepath);
     let mut reader = BufReader::new(File::open(&index_filepath)?);
-    let lm = EliasFanoTrieCountLm::deserialize_from(&mut reader)?;
+    let lm = AnyTrieCountLm::deserialize_from(&mut reader)?;
     let mut lookuper = lm.lookuper();
 
     println!("Performing the lookup...");
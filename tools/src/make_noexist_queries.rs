@@ -18,7 +18,7 @@ struct Opt {
         short = "f",
         long,
         default_value = "gzip",
-        help = "Input file format from plain, gzip, deflate, and zlib."
+        help = "Input file format from plain, gzip, deflate, zlib, zstd, xz, and bzip2."
     )]
     file_format: GramsFileFormats,
 
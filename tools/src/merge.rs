@@ -0,0 +1,72 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use structopt::StructOpt;
+
+use tongrams::trie_count_lm::{CountLm, TrieCountLmMerger};
+use tongrams::AnyTrieCountLm;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "merge",
+    about = "A program to merge indexes built with --reversible of index into one, which consists of the component types of the first index."
+)]
+struct Opt {
+    #[structopt(short = "i", required = true, min_values = 2)]
+    index_filepaths: Vec<PathBuf>,
+
+    #[structopt(short = "o")]
+    output_filepath: PathBuf,
+
+    #[structopt(
+        short = "m",
+        long,
+        help = "Memory budget in MiB for the grams of an order, beyond which temporary files are used."
+    )]
+    memory_budget: Option<usize>,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    let index_filepaths = opt.index_filepaths;
+    let output_filepath = opt.output_filepath;
+
+    let mut lms = Vec::with_capacity(index_filepaths.len());
+    for index_filepath in &index_filepaths {
+        println!("Loading the index from {:?}...", index_filepath);
+        let mut reader = BufReader::new(File::open(index_filepath)?);
+        let lm = AnyTrieCountLm::deserialize_from(&mut reader)?;
+        lm.check_reverse_lookup().with_context(|| {
+            format!(
+                "The index {:?} should be built with --reversible of index to be merged.",
+                index_filepath
+            )
+        })?;
+        lms.push(lm);
+    }
+
+    println!("Merging the indexes...");
+    let start = std::time::Instant::now();
+    let mut merger = TrieCountLmMerger::new();
+    if let Some(memory_budget) = opt.memory_budget {
+        merger = merger.memory_budget(memory_budget << 20);
+    }
+    let others: Vec<&dyn CountLm> = lms[1..].iter().map(|lm| &**lm).collect();
+    let lm = lms[0].merge_with(&others, &merger)?;
+    let duration = start.elapsed();
+    println!("Elapsed time: {:.3} [sec]", duration.as_secs_f64());
+    println!("{} grams are stored.", lm.num_grams());
+
+    println!("Writing the index into {:?}...", &output_filepath);
+    let mut writer = BufWriter::new(File::create(&output_filepath)?);
+    let mem = lm.serialize_into(&mut writer)?;
+    println!(
+        "Index size: {} bytes ({:.3} MiB)",
+        mem,
+        mem as f64 / (1024.0 * 1024.0)
+    );
+
+    Ok(())
+}
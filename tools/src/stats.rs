@@ -5,7 +5,7 @@ use std::path::PathBuf;
 use anyhow::Result;
 use structopt::StructOpt;
 
-use tongrams::EliasFanoTrieCountLm;
+use tongrams::AnyTrieCountLm;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "stats", about = "A program to print memory statistics.")]
@@ -20,7 +20,14 @@ fn main() -> Result<()> {
 
     eprintln!("Loading the index from {:?}...", &index_filepath);
     let mut reader = BufReader::new(File::open(&index_filepath)?);
-    let lm = EliasFanoTrieCountLm::deserialize_from(&mut reader)?;
+    let lm = AnyTrieCountLm::deserialize_from(&mut reader)?;
+    let header = lm.header();
+    eprintln!(
+        "Components: {}, {}, {}",
+        header.trie_array(),
+        header.vocabulary(),
+        header.rank_array()
+    );
 
     let mem_stats = lm.memory_statistics();
     println!("{}", mem_stats);